@@ -0,0 +1,77 @@
+// per-pair against batched level hashing for MerkleTree::from_leaves, which
+// hands each level of internal nodes to the tree's TreeHasher in one call.
+// run with cargo bench --bench hash_level. the batched hasher here only reuses
+// one sha256 state across a level, which is all a plain digest allows; a
+// digest with a real multi-buffer api gains more
+use merkle::{
+    composite::{Tagged, TreeHasher},
+    merkle::MerkleTree,
+};
+use sha2::{Digest, Sha256};
+use std::{hint::black_box, time::Instant};
+
+type PerPair = MerkleTree<Sha256, 32, 64>;
+type Batched = MerkleTree<Sha256, 32, 64, ReusedState>;
+
+const LEAVES: usize = 1 << 18;
+const RUNS: usize = 5;
+
+#[derive(Default)]
+struct ReusedState(Tagged<Sha256, 32, 64>);
+
+impl TreeHasher for ReusedState {
+    type Output = Vec<u8>;
+
+    fn leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.0.leaf(data)
+    }
+
+    fn node(&self, left: &Vec<u8>, right: &Vec<u8>) -> Vec<u8> {
+        self.0.node(left, right)
+    }
+
+    fn level(&self, pairs: &[(&Vec<u8>, &Vec<u8>)]) -> Vec<Vec<u8>> {
+        let mut hasher = Sha256::new();
+        let mut concat = [0; 64];
+
+        pairs
+            .iter()
+            .map(|(left, right)| {
+                concat[..32].copy_from_slice(left);
+                concat[32..].copy_from_slice(right);
+                Digest::update(&mut hasher, concat);
+                let inner = hasher.finalize_reset();
+                Digest::update(&mut hasher, inner);
+                let rehashed = hasher.finalize_reset();
+                Digest::update(&mut hasher, [2; 32]);
+                Digest::update(&mut hasher, rehashed);
+                hasher.finalize_reset().to_vec()
+            })
+            .collect()
+    }
+}
+
+// the fastest of RUNS builds, in milliseconds, and the root it gave
+fn best_of(build: impl Fn() -> Option<Vec<u8>>) -> (f64, Option<Vec<u8>>) {
+    let mut best = f64::MAX;
+    let mut root = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        root = black_box(build());
+        best = best.min(start.elapsed().as_secs_f64() * 1000.0);
+    }
+
+    (best, root)
+}
+
+fn main() {
+    let leaves = (0..LEAVES as u32).map(u32::to_le_bytes).collect::<Vec<_>>();
+
+    let (per_pair, per_pair_root) = best_of(|| PerPair::from_leaves(&leaves).ok()?.root());
+    let (batched, batched_root) = best_of(|| Batched::from_leaves(&leaves).ok()?.root());
+    assert_eq!(per_pair_root, batched_root);
+
+    println!("{LEAVES} leaves, best of {RUNS}");
+    println!("  per pair: {per_pair:>8.2} ms");
+    println!("  batched:  {batched:>8.2} ms ({:.2}x)", per_pair / batched);
+}
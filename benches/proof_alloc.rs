@@ -0,0 +1,66 @@
+// heap allocations and time per proof for MerkleTree::create_proof_by_index.
+// run with cargo bench --bench proof_alloc. a counting allocator wraps the
+// system one, so the figures are exact for this process and need no profiler
+use merkle::merkle::MerkleTree;
+use sha2::Sha256;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+type Tree = MerkleTree<Sha256, 32, 64>;
+
+const LEAVES: usize = 1 << 16;
+const PROOFS: usize = 1 << 14;
+const RUNS: usize = 5;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: forwarded unchanged to the system allocator
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: forwarded unchanged to the system allocator
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let leaves = (0..LEAVES as u32).map(u32::to_le_bytes).collect::<Vec<_>>();
+    let tree = Tree::from_leaves(&leaves).unwrap();
+    // spread over the whole tree, including the leaves of the short right edge
+    let indices = (0..PROOFS).map(|i| i * (LEAVES / PROOFS) + i % (LEAVES / PROOFS));
+
+    let mut best = f64::MAX;
+    let mut allocations = 0;
+    for _ in 0..RUNS {
+        let indices = indices.clone().collect::<Vec<_>>();
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        for &i in &indices {
+            black_box(tree.create_proof_by_index(i));
+        }
+        best = best.min(start.elapsed().as_secs_f64() * 1e6 / PROOFS as f64);
+        allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    }
+
+    let depth = tree.create_proof_by_index(0).unwrap().len();
+    println!("{PROOFS} proofs of {depth} elements over {LEAVES} leaves, best of {RUNS}");
+    println!(
+        "  allocations: {:>8.2} per proof",
+        allocations as f64 / PROOFS as f64
+    );
+    println!("  time:        {best:>8.3} us per proof");
+}
@@ -0,0 +1,63 @@
+/* builds a tree through the c api and checks every proof against its root:
+ *
+ *   cargo rustc --lib --features ffi --crate-type cdylib
+ *   cc -Iinclude ffi/smoke.c -Ltarget/debug -lmerkle -o target/smoke
+ *   LD_LIBRARY_PATH=target/debug target/smoke
+ */
+#include <stdio.h>
+#include <string.h>
+
+#include "merkle.h"
+
+/* leaves 00..04, the root the rust tests pin */
+static const char *ROOT =
+    "43680581585abed181b1eec574d755598bd9773593cb15dda0898c6110a65909";
+
+#define CHECK(cond)                                                  \
+    do {                                                             \
+        if (!(cond)) {                                               \
+            fprintf(stderr, "%s:%d: %s\n", __FILE__, __LINE__, #cond); \
+            return 1;                                                \
+        }                                                            \
+    } while (0)
+
+int main(void) {
+    MtTree *tree = mt_tree_new();
+    CHECK(tree != NULL);
+
+    for (uint8_t i = 0; i < 5; i++) {
+        CHECK(mt_tree_add(tree, &i, 1) == MT_OK);
+    }
+    CHECK(mt_tree_len(tree) == 5);
+
+    uint8_t root[MT_HASH_LEN];
+    CHECK(mt_tree_root(tree, root, sizeof root) == MT_OK);
+
+    char hex[2 * MT_HASH_LEN + 1];
+    for (size_t i = 0; i < MT_HASH_LEN; i++) {
+        sprintf(hex + 2 * i, "%02x", root[i]);
+    }
+    CHECK(strcmp(hex, ROOT) == 0);
+
+    for (uint8_t i = 0; i < 5; i++) {
+        uint8_t proof[8 * MT_PROOF_ELEMENT_LEN];
+        size_t len = 0;
+        CHECK(mt_tree_proof(tree, i, proof, sizeof proof, &len) == MT_OK);
+        CHECK(len % MT_PROOF_ELEMENT_LEN == 0);
+
+        uint8_t other = 9;
+        CHECK(mt_verify_proof(&i, 1, proof, len, root, sizeof root) == MT_OK);
+        CHECK(mt_verify_proof(&other, 1, proof, len, root, sizeof root) ==
+              MT_ERR_PROOF_MISMATCH);
+        CHECK(mt_verify_proof(&i, 1, proof, len, root, 31) ==
+              MT_ERR_INVALID_HASH_LENGTH);
+    }
+
+    size_t len = 0;
+    CHECK(mt_tree_proof(tree, 5, NULL, 0, &len) == MT_ERR_LEAF_OUT_OF_BOUNDS);
+    CHECK(mt_tree_add(NULL, NULL, 0) == MT_ERR_NULL_POINTER);
+
+    mt_tree_free(tree);
+    puts("ok");
+    return 0;
+}
@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use merkle::merkle::MerkleTree;
+use sha2::Sha256;
+
+type Tree = MerkleTree<Sha256, 32, 64>;
+
+// decoders see raw bytes. anything they accept must re-encode to the same bytes
+fuzz_target!(|bytes: &[u8]| {
+    if let Ok(proof) = Tree::proof_from_bytes(bytes) {
+        assert_eq!(Tree::proof_to_bytes(&proof).unwrap(), bytes);
+    }
+
+    if let Ok(tree) = Tree::from_bytes(bytes) {
+        assert_eq!(tree.to_bytes(), bytes);
+
+        // a decoded tree is unverified, so only the audit is safe to run on it
+        let _ = tree.verify_integrity();
+    }
+});
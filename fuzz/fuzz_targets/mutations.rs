@@ -0,0 +1,66 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use merkle::merkle::{IndexBinding, MerkleTree, TagMode};
+use sha2::Sha256;
+
+type Tree = MerkleTree<Sha256, 32, 64>;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Add(Vec<u8>),
+    Update(usize, Vec<u8>),
+    Truncate(usize),
+}
+
+// drives a tree and a plain list of leaves side by side and checks after every
+// step that the tree is sound and proves every leaf, also after a byte round trip
+fuzz_target!(|input: (IndexBinding, Vec<Op>)| {
+    let (binding, ops) = input;
+    let mut tree = Tree::with_index_binding(binding);
+    let mut leaves: Vec<Vec<u8>> = vec![];
+
+    for op in ops {
+        match op {
+            Op::Add(data) => {
+                tree.add(&data).unwrap();
+                leaves.push(data);
+            }
+            Op::Update(index, data) => {
+                let res = tree.update(index, &data);
+                match leaves.get_mut(index) {
+                    Some(leaf) => {
+                        res.unwrap();
+                        *leaf = data;
+                    }
+                    None => assert!(res.is_err()),
+                }
+            }
+            Op::Truncate(len) => {
+                tree.truncate(len).unwrap();
+                leaves.truncate(len);
+            }
+        }
+
+        tree.verify_integrity().unwrap();
+        assert_eq!(tree.len(), leaves.len());
+
+        let decoded = Tree::from_bytes(&tree.to_bytes()).unwrap();
+        assert!(decoded == tree);
+
+        let Some(root) = tree.root() else {
+            continue;
+        };
+        for (i, (leaf, proof)) in leaves.iter().zip(tree.prove_all()).enumerate() {
+            let proof = Tree::proof_from_bytes(&Tree::proof_to_bytes(&proof).unwrap()).unwrap();
+            let verified = match binding {
+                IndexBinding::None => Tree::verify_proof(leaf, &proof, &root),
+                IndexBinding::Prefix => {
+                    Tree::verify_proof_at_index(leaf, i, &proof, &root, TagMode::Block)
+                }
+            };
+            assert!(verified);
+        }
+    }
+});
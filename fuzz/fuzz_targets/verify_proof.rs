@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use merkle::merkle::{MerkleTree, Proof, TagMode};
+use sha2::Sha256;
+
+type Tree = MerkleTree<Sha256, 32, 64>;
+
+// untrusted (data, proof, root) triples must never panic, whatever the widths
+fuzz_target!(|input: (&[u8], Proof<Sha256, 32, 64>, &[u8], usize)| {
+    let (data, proof, root, index) = input;
+
+    let verified = Tree::verify_proof(data, &proof, root);
+    let detailed = Tree::verify_proof_detailed(data, &proof, root);
+    assert_eq!(verified, detailed == Ok(true));
+
+    if root.len() != 32 {
+        assert!(!verified);
+    }
+
+    let _ = Tree::verify_proof_at_index(data, index, &proof, root, TagMode::Block);
+});
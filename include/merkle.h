@@ -0,0 +1,123 @@
+#ifndef MERKLE_H
+#define MERKLE_H
+
+/* generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define MT_HASH_LEN 32
+
+#define MT_PROOF_ELEMENT_LEN (1 + MT_HASH_LEN)
+
+#define MT_OK 0
+
+#define MT_ERR_NULL_POINTER -1
+
+#define MT_ERR_PROOF_MISMATCH -2
+
+#define MT_ERR_INVALID_ENCODING -3
+
+#define MT_ERR_INVALID_HASH_LENGTH -4
+
+#define MT_ERR_LEAF_OUT_OF_BOUNDS -5
+
+#define MT_ERR_CAPACITY_EXCEEDED -6
+
+#define MT_ERR_EMPTY_TREE -7
+
+#define MT_ERR_BUFFER_TOO_SMALL -8
+
+#define MT_ERR_OTHER -9
+
+#define MT_ERR_PANIC -100
+
+typedef struct MtTree MtTree;
+
+/**
+ * Checks a flat proof for `data` against a 32-byte root.
+ *
+ * Returns `MT_OK` if the proof reaches the root, `MT_ERR_PROOF_MISMATCH` if a
+ * well-formed proof does not, and another negative code for malformed input.
+ *
+ * # Safety
+ *
+ * Each pointer must be valid for reads of its length in bytes for the duration
+ * of the call. A pointer may only be null when its length is 0.
+ */
+int32_t mt_verify_proof(const uint8_t *data_ptr,
+                        size_t data_len,
+                        const uint8_t *proof_ptr,
+                        size_t proof_len,
+                        const uint8_t *root_ptr,
+                        size_t root_len);
+
+/**
+ * Creates an empty tree, or returns null if that fails.
+ *
+ * The tree must be released with `mt_tree_free` exactly once.
+ */
+MtTree *mt_tree_new(void);
+
+/**
+ * Releases a tree from `mt_tree_new`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `tree` must be null or a pointer from `mt_tree_new` that has not been freed.
+ * It must not be used again afterwards.
+ */
+void mt_tree_free(MtTree *tree);
+
+/**
+ * Appends a leaf holding `data_len` bytes of `data_ptr`.
+ *
+ * # Safety
+ *
+ * `tree` must be a live pointer from `mt_tree_new` not used concurrently, and
+ * `data_ptr` valid for reads of `data_len` bytes (or null if it is 0).
+ */
+int32_t mt_tree_add(MtTree *tree, const uint8_t *data_ptr, size_t data_len);
+
+/**
+ * The number of leaves in the tree, or 0 for a null tree.
+ *
+ * # Safety
+ *
+ * `tree` must be null or a live pointer from `mt_tree_new`.
+ */
+size_t mt_tree_len(const MtTree *tree);
+
+/**
+ * Writes the tree's 32-byte root to `out`.
+ *
+ * # Safety
+ *
+ * `tree` must be a live pointer from `mt_tree_new`, and `out` valid for
+ * writes of `out_len` bytes.
+ */
+int32_t mt_tree_root(const MtTree *tree, uint8_t *out, size_t out_len);
+
+/**
+ * Writes the flat proof for the leaf at `index` to `out` and its length in
+ * bytes, `MT_PROOF_ELEMENT_LEN` per element, to `*written`.
+ *
+ * If `out_len` is too small nothing is written to `out`, `*written` is set to
+ * the length needed and `MT_ERR_BUFFER_TOO_SMALL` returned, so a caller can
+ * size its buffer with a first call passing a null `out` and 0.
+ *
+ * # Safety
+ *
+ * `tree` must be a live pointer from `mt_tree_new`, `out` valid for writes of
+ * `out_len` bytes (or null if it is 0), and `written` valid for one write.
+ */
+int32_t mt_tree_proof(const MtTree *tree,
+                      size_t index,
+                      uint8_t *out,
+                      size_t out_len,
+                      size_t *written);
+
+#endif  /* MERKLE_H */
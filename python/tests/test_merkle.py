@@ -0,0 +1,65 @@
+import pytest
+
+import merkle
+
+# the same fixtures the rust tests in src/python.rs pin: leaves 0x00..0x04
+ROOTS = {
+    "sha256": "43680581585abed181b1eec574d755598bd9773593cb15dda0898c6110a65909",
+    "sha512": "77800ff88913029a0ac609e82a7561b4450ea1d95e7cca5b8ea55d5d06f7a00a"
+    "aede43967873d41536ef293c5aedf7128b6d983575562d00fc5af75b296a73c3",
+    "sha3_256": "145f5b6b4d430a3b50f2bf6a363e8ecec1b18ae185aa31a081193a496edf94cd",
+    "keccak256": "a87177fc26162d9168edcd32c1a28e5598bc36faff45f1871a919d264509b609",
+}
+ROOT = bytes.fromhex(ROOTS["sha256"])
+
+
+def tree_of(n, hash="sha256"):
+    tree = merkle.MerkleTree(hash)
+    for i in range(n):
+        assert tree.add(bytes([i])) == i
+    return tree
+
+
+def test_algorithms_match_rust_fixtures():
+    assert list(merkle.ALGORITHMS) == list(ROOTS)
+
+
+@pytest.mark.parametrize("hash", merkle.ALGORITHMS)
+def test_root_matches_rust_fixture(hash):
+    tree = tree_of(5, hash)
+    assert len(tree) == 5
+    assert tree.root() == bytes.fromhex(ROOTS[hash])
+
+
+def test_proofs_verify():
+    tree = tree_of(5)
+    for i in range(5):
+        proof = tree.prove(i)
+        assert merkle.verify(bytes([i]), proof, ROOT)
+        assert not merkle.verify(b"\x09", proof, ROOT)
+
+
+@pytest.mark.parametrize("hash", merkle.ALGORITHMS)
+def test_every_algorithm_round_trips(hash):
+    tree = tree_of(7, hash)
+    root = tree.root()
+    for i in range(7):
+        assert merkle.verify(bytes([i]), tree.prove(i), root, hash)
+
+    # a proof only verifies under the hash it was made with
+    other = "sha3_256" if hash == "sha256" else "sha256"
+    if len(root) == 32:
+        assert not merkle.verify(b"\x00", tree.prove(0), root, other)
+
+
+def test_errors_raise():
+    tree = tree_of(5)
+    with pytest.raises(IndexError):
+        tree.prove(5)
+    with pytest.raises(merkle.MerkleError):
+        merkle.verify(b"\x00", b"\x00\x00\x00\x01", ROOT)
+    with pytest.raises(merkle.MerkleError):
+        merkle.verify(b"\x00", tree.prove(0), ROOT[:31])
+    with pytest.raises(merkle.MerkleError):
+        merkle.MerkleTree("md5")
+    assert issubclass(merkle.MerkleError, ValueError)
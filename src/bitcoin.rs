@@ -0,0 +1,284 @@
+use sha2::{Digest, Sha256};
+
+use crate::hex;
+
+// the transaction merkle tree from bitcoin block headers: double sha-256 for
+// leaves' parents and every node above, no domain separation tags, leaves are
+// the txids themselves, and a level with an odd number of nodes pairs its last
+// node with itself. that duplication is what makes two different txid lists
+// share a root (cve-2012-2459), so callers validating blocks must still reject
+// duplicate transactions themselves.
+//
+// hashes are in internal byte order, the order they are hashed and serialized
+// in. block explorers show txids and merkle roots byte-reversed; from_display_hex
+// and to_display_hex convert between the two.
+pub struct BitcoinMode {
+    // levels[0] holds the txids, the last level just the root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut pair = [0; 64];
+    pair[..32].copy_from_slice(left);
+    pair[32..].copy_from_slice(right);
+
+    sha256d(&pair)
+}
+
+// parses a txid or merkle root as printed by explorers and bitcoind
+#[must_use]
+pub fn from_display_hex(display: &str) -> Option<[u8; 32]> {
+    let mut out: [u8; 32] = hex::decode(display).ok()?.try_into().ok()?;
+    out.reverse();
+
+    Some(out)
+}
+
+#[must_use]
+pub fn to_display_hex(hash: &[u8; 32]) -> String {
+    let mut reversed = *hash;
+    reversed.reverse();
+
+    hex::encode(&reversed)
+}
+
+impl BitcoinMode {
+    pub fn from_txids<I>(txids: I) -> Self
+    where
+        I: IntoIterator<Item = [u8; 32]>,
+    {
+        let mut levels = vec![txids.into_iter().collect::<Vec<_>>()];
+
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next = level
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+                .collect();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    // the header's merkle root; bitcoin has no root for an empty list
+    #[must_use]
+    pub fn root(&self) -> Option<[u8; 32]> {
+        self.levels.last()?.first().copied()
+    }
+
+    // the merkle branch for the transaction at index, siblings from the bottom
+    // up. where a level was padded by duplication the sibling is the node itself
+    #[must_use]
+    pub fn branch(&self, mut index: usize) -> Option<Vec<[u8; 32]>> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let mut branch = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            branch.push(*level.get(index ^ 1).unwrap_or(&level[index]));
+            index /= 2;
+        }
+
+        Some(branch)
+    }
+
+    // bitcoin core's ComputeMerkleRootFromBranch: bit i of index says whether
+    // the node at height i is a right child. index bits beyond the branch
+    // length would be ignored there, which lets one branch pass for several
+    // positions, so they are rejected here
+    #[must_use]
+    pub fn verify_branch(
+        txid: &[u8; 32],
+        index: usize,
+        branch: &[[u8; 32]],
+        root: &[u8; 32],
+    ) -> bool {
+        if index.checked_shr(branch.len() as u32).unwrap_or(0) != 0 {
+            return false;
+        }
+
+        // one bit per level, so a branch of usize::BITS or more siblings
+        // cannot overflow the shift
+        let mut bits = index;
+        let computed = branch.iter().fold(*txid, |acc, sibling| {
+            let parent = if bits & 1 == 1 {
+                node_hash(sibling, &acc)
+            } else {
+                node_hash(&acc, sibling)
+            };
+            bits >>= 1;
+            parent
+        });
+
+        computed == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitcoinMode, from_display_hex, sha256d, to_display_hex};
+
+    fn check_block(txids: &[&str], root: &str) {
+        let txids = txids
+            .iter()
+            .map(|t| from_display_hex(t).unwrap())
+            .collect::<Vec<_>>();
+        let tree = BitcoinMode::from_txids(txids.iter().copied());
+        let computed = tree.root().unwrap();
+
+        assert_eq!(to_display_hex(&computed), root);
+
+        for (i, txid) in txids.iter().enumerate() {
+            let branch = tree.branch(i).unwrap();
+            assert!(BitcoinMode::verify_branch(txid, i, &branch, &computed));
+            assert!(!BitcoinMode::verify_branch(
+                txid,
+                i + (1 << branch.len()),
+                &branch,
+                &computed
+            ));
+        }
+    }
+
+    #[test]
+    fn genesis_block() {
+        let coinbase = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+        check_block(&[coinbase], coinbase);
+    }
+
+    #[test]
+    fn block_170() {
+        check_block(
+            &[
+                "b1fea52486ce0c62bb442b530a3f0132b826c74e473d1f2c220bfa78111c5082",
+                "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+            ],
+            "7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff",
+        );
+    }
+
+    // block 00000000b0c5a240b2a61d2e75692224efd4cbecdf6eaf4cc2cf477ca7c270e7
+    #[test]
+    fn block_b0c5a240() {
+        check_block(
+            &[
+                "77dfc2fe598419b00641c296181a96cf16943697f573480b023b77cce82ada21",
+                "a3b0e9e7cddbbe78270fa4182a7675ff00b92872d8df7d14265a2b1e379a9d33",
+            ],
+            "4c917a410f4e899195f816081844e56aceda71c4cc4fe634aebe9437e57344bf",
+        );
+    }
+
+    #[test]
+    fn block_100000() {
+        check_block(
+            &[
+                "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+                "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+                "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+                "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+            ],
+            "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766",
+        );
+    }
+
+    // block 000000000000000000000c835b2adcaedc20fdf6ee440009c249452c726dafae,
+    // the mainnet block rust-bitcoin tests against. its 2500 transactions leave
+    // an odd node count on six levels (625, 313, 157, 79, 5 and 3 nodes). the
+    // txids, one per line in display order, were taken from the raw block
+    #[test]
+    fn block_702861() {
+        let txids = include_str!("../tests/data/mainnet_block_702861.txids")
+            .lines()
+            .collect::<Vec<_>>();
+        assert_eq!(txids.len(), 2500);
+
+        check_block(
+            &txids,
+            "407d72768cec1a244b7599af79f554055c72d6b2356c890f8c25abf797679022",
+        );
+    }
+
+    // odd counts exercise the duplicate-last rule on one or more levels. txid i
+    // is sha256d([i]); the roots were computed with rust-bitcoin's
+    // merkle_tree::calculate_root
+    #[test]
+    fn odd_counts_duplicate_the_last_node() {
+        for (count, root) in [
+            (
+                3u8,
+                "d0c1e5f32d1d424371ac1018770af4446140436d5926d112c67f562fe0df29e1",
+            ),
+            (
+                5,
+                "48b7979f4fc409cc282b911c6c23eee3aea685f70fcc91bcc3f728d6493811f4",
+            ),
+            (
+                6,
+                "ed75af01123c0e843d6a4a1322528433528f0c96ecbac2e8ea225982e4b75bfb",
+            ),
+            (
+                7,
+                "4da57c69139fb1b4d2ebccb63f239fe9d46aaf94abbec97129c7cd577d5ce67d",
+            ),
+        ] {
+            let txids = (0..count)
+                .map(|i| to_display_hex(&sha256d(&[i])))
+                .collect::<Vec<_>>();
+            check_block(&txids.iter().map(String::as_str).collect::<Vec<_>>(), root);
+        }
+
+        // the malleability the duplication rule allows: repeating the odd last
+        // txid gives a different list with the same root
+        let three = (0..3u8).map(|i| sha256d(&[i])).collect::<Vec<_>>();
+        let four = [three[0], three[1], three[2], three[2]];
+        assert_eq!(
+            BitcoinMode::from_txids(three).root(),
+            BitcoinMode::from_txids(four).root()
+        );
+    }
+
+    // an untrusted branch may be longer than usize has bits
+    #[test]
+    fn overlong_branches_do_not_panic() {
+        let txid = sha256d(b"tx");
+        let branch = (0..65u8).map(|i| sha256d(&[i])).collect::<Vec<_>>();
+        let root = branch
+            .iter()
+            .fold(txid, |acc, sibling| super::node_hash(&acc, sibling));
+
+        assert!(BitcoinMode::verify_branch(&txid, 0, &branch, &root));
+        assert!(!BitcoinMode::verify_branch(&txid, 1, &branch, &root));
+        assert!(!BitcoinMode::verify_branch(
+            &txid,
+            usize::MAX,
+            &branch,
+            &root
+        ));
+    }
+
+    #[test]
+    fn edge_cases() {
+        assert!(BitcoinMode::from_txids([]).root().is_none());
+        assert!(BitcoinMode::from_txids([[0; 32]]).branch(1).is_none());
+        assert!(from_display_hex("00").is_none());
+        assert!(from_display_hex(&"zz".repeat(32)).is_none());
+        // u8::from_str_radix takes "+1" for 1
+        assert!(from_display_hex(&format!("+1{}", "0".repeat(62))).is_none());
+    }
+}
@@ -0,0 +1,371 @@
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+
+// bittorrent v2 (bep 52) file trees: a file is cut into 16 KiB blocks, each leaf
+// is sha256 of its block (the last one hashed at whatever length it has), the
+// leaf count is padded up to a power of two with all-zero hashes, and nodes are
+// sha256(left || right) with no tags. padding is never materialized: a subtree
+// of zero leaves has a fixed hash per height, so the padding of a level is the
+// zero-subtree hash for that level, not 32 zero bytes.
+//
+// the piece layer is the level whose nodes each cover piece_length bytes. a
+// .torrent carries it for files larger than one piece so that a peer can check
+// a whole piece against it, and a single block against its piece's hash. the
+// file root does not depend on the piece length; the piece layer does.
+pub const BLOCK_SIZE: usize = 16 * 1024;
+
+pub type Hash = [u8; 32];
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+// root of a subtree of 2^height zero leaves
+fn zero_hash(height: usize) -> Hash {
+    (0..height).fold([0; 32], |z, _| node_hash(&z, &z))
+}
+
+// folds a proof of sibling hashes upwards from position index at the bottom
+fn fold(mut hash: Hash, mut index: usize, proof: &[Hash]) -> Hash {
+    for sibling in proof {
+        hash = if index & 1 == 1 {
+            node_hash(sibling, &hash)
+        } else {
+            node_hash(&hash, sibling)
+        };
+        index >>= 1;
+    }
+
+    hash
+}
+
+pub struct BtV2Tree {
+    // levels[0] holds the block hashes, each level above the nodes over real data;
+    // anything past the end of a level is a zero subtree
+    levels: Vec<Vec<Hash>>,
+    zeros: Vec<Hash>,
+    piece_length: usize,
+    file_len: u64,
+}
+
+// the root of a file as it goes in the file tree of a v2 .torrent, None for an
+// empty file, which has no pieces root
+pub fn bt_v2_file_root<R: Read>(reader: R, piece_length: usize) -> io::Result<Option<Hash>> {
+    Ok(BtV2Tree::from_reader(reader, piece_length)?.root())
+}
+
+impl BtV2Tree {
+    // piece_length must be a power of two no smaller than a block
+    pub fn from_reader<R: Read>(mut reader: R, piece_length: usize) -> io::Result<Self> {
+        if piece_length < BLOCK_SIZE || !piece_length.is_power_of_two() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "piece length must be a power of two of at least 16 KiB",
+            ));
+        }
+
+        let mut blocks = vec![];
+        let mut file_len = 0;
+        let mut block = Vec::with_capacity(BLOCK_SIZE);
+        loop {
+            block.clear();
+            (&mut reader)
+                .take(BLOCK_SIZE as u64)
+                .read_to_end(&mut block)?;
+            if block.is_empty() {
+                break;
+            }
+
+            file_len += block.len() as u64;
+            blocks.push(Sha256::digest(&block).into());
+        }
+
+        Ok(Self::from_block_hashes(blocks, piece_length, file_len))
+    }
+
+    pub fn from_bytes(data: &[u8], piece_length: usize) -> io::Result<Self> {
+        Self::from_reader(data, piece_length)
+    }
+
+    fn from_block_hashes(blocks: Vec<Hash>, piece_length: usize, file_len: u64) -> Self {
+        let height = blocks.len().next_power_of_two().trailing_zeros() as usize;
+        let zeros = (0..=height).map(zero_hash).collect::<Vec<_>>();
+
+        let mut levels = vec![blocks];
+        for level in 0..height {
+            let next = levels[level]
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], pair.get(1).unwrap_or(&zeros[level])))
+                .collect();
+            levels.push(next);
+        }
+
+        Self {
+            levels,
+            zeros,
+            piece_length,
+            file_len,
+        }
+    }
+
+    #[must_use]
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    #[must_use]
+    pub fn num_blocks(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Hash> {
+        self.levels.last()?.first().copied()
+    }
+
+    fn node(&self, level: usize, index: usize) -> Hash {
+        self.levels[level]
+            .get(index)
+            .copied()
+            .unwrap_or(self.zeros[level])
+    }
+
+    fn blocks_per_piece(&self) -> usize {
+        self.piece_length / BLOCK_SIZE
+    }
+
+    // the level piece hashes sit at, capped at the root for files of one piece
+    fn piece_level(&self) -> usize {
+        (self.blocks_per_piece().trailing_zeros() as usize).min(self.levels.len() - 1)
+    }
+
+    #[must_use]
+    pub fn num_pieces(&self) -> usize {
+        self.num_blocks().div_ceil(self.blocks_per_piece())
+    }
+
+    // the piece layer as stored in a .torrent's piece layers dictionary. files of
+    // at most one piece have none, their root is their only piece hash
+    #[must_use]
+    pub fn piece_layer(&self) -> Vec<Hash> {
+        if self.file_len <= self.piece_length as u64 {
+            return vec![];
+        }
+
+        (0..self.num_pieces())
+            .map(|piece| self.node(self.piece_level(), piece))
+            .collect()
+    }
+
+    // the hash a block is checked against: its piece's entry in the piece layer,
+    // or the root for a file of one piece
+    #[must_use]
+    pub fn piece_hash(&self, piece: usize) -> Option<Hash> {
+        (piece < self.num_pieces()).then(|| self.node(self.piece_level(), piece))
+    }
+
+    // siblings from the block up to its piece hash, zero padding included
+    #[must_use]
+    pub fn block_proof(&self, block: usize) -> Option<Vec<Hash>> {
+        (block < self.num_blocks()).then(|| {
+            (0..self.piece_level())
+                .map(|level| self.node(level, (block >> level) ^ 1))
+                .collect()
+        })
+    }
+
+    // siblings from a piece hash up to the file root
+    #[must_use]
+    pub fn piece_proof(&self, piece: usize) -> Option<Vec<Hash>> {
+        (piece < self.num_pieces()).then(|| {
+            (self.piece_level()..self.levels.len() - 1)
+                .map(|level| self.node(level, (piece >> (level - self.piece_level())) ^ 1))
+                .collect()
+        })
+    }
+
+    // block_index counts blocks within the piece, and the proof must be exactly
+    // as long as the piece's subtree is tall
+    #[must_use]
+    pub fn verify_block(
+        block: &[u8],
+        block_index: usize,
+        proof: &[Hash],
+        piece_hash: &Hash,
+    ) -> bool {
+        block.len() <= BLOCK_SIZE
+            && block_index.checked_shr(proof.len() as u32).unwrap_or(0) == 0
+            && fold(Sha256::digest(block).into(), block_index, proof) == *piece_hash
+    }
+
+    #[must_use]
+    pub fn verify_piece(piece_hash: &Hash, piece: usize, proof: &[Hash], root: &Hash) -> bool {
+        piece.checked_shr(proof.len() as u32).unwrap_or(0) == 0
+            && fold(*piece_hash, piece, proof) == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BLOCK_SIZE, BtV2Tree, bt_v2_file_root};
+    use crate::hex::encode as hex;
+
+    // byte i of a test file is i mod 251, so no two blocks are alike
+    fn file(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    // computed with an independent python implementation of bep 52's merkle
+    // rules (hashlib sha256, leaves padded to a power of two with zero hashes,
+    // each piece hash over a full piece's worth of leaves) over file(len) with
+    // 32 KiB pieces: (len, root, piece layer). the lengths sit on and just past
+    // the power-of-two boundaries of the block count
+    const FIXTURES: [(usize, &str, &[&str]); 7] = [
+        (
+            1,
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            &[],
+        ),
+        (
+            BLOCK_SIZE,
+            "4348e3b98e8a327b34ced39c1da9e67cdb4cd5e48e4d7960607a3ae403d35f0c",
+            &[],
+        ),
+        (
+            BLOCK_SIZE + 1,
+            "9d7887c65d577a0237fb3c0998b87b3a62762d03796889a2caea01db914ccbb8",
+            &[],
+        ),
+        (
+            2 * BLOCK_SIZE,
+            "d9e13d0b676ad681164ef0b7b5910d1328ea83a047cad57e619d76bbe3a08525",
+            &[],
+        ),
+        (
+            3 * BLOCK_SIZE,
+            "c23d35ec942288a7d9b58d1d0446a76104660b7c72e5cf39f38bddb028ff8ca0",
+            &[
+                "d9e13d0b676ad681164ef0b7b5910d1328ea83a047cad57e619d76bbe3a08525",
+                "d5b0e36f05eedd8fea7269f48169e12596a589f6c88593b9279c6327ab4c228b",
+            ],
+        ),
+        (
+            4 * BLOCK_SIZE + 7,
+            "52604412bd171662449ffeb3eb069168f24e050fe40ee755c6bacb3fe1e51d8d",
+            &[
+                "d9e13d0b676ad681164ef0b7b5910d1328ea83a047cad57e619d76bbe3a08525",
+                "e28097eaaa55956702cf8195d1a551dbabb63e3d679b294cf33d506a6b5ef479",
+                "616c5244f32928776eaa73989c9924654f3e5e43cb0482278541b43a2b5d3d32",
+            ],
+        ),
+        (
+            8 * BLOCK_SIZE,
+            "09fcc645f49bdda7719fde41331e0a48e3c6cef3c7e8d812e16dd60c39c7cc99",
+            &[
+                "d9e13d0b676ad681164ef0b7b5910d1328ea83a047cad57e619d76bbe3a08525",
+                "e28097eaaa55956702cf8195d1a551dbabb63e3d679b294cf33d506a6b5ef479",
+                "c652249676984ba0be8db1d26efa9e0c67cd14299b02eaab326419a0f91a1aec",
+                "ac13964b51d3110275d8500b340b92fd2ac4bab61bd18eef21651b1e26ec4a78",
+            ],
+        ),
+    ];
+
+    #[test]
+    fn roots_and_piece_layers_match_the_reference() {
+        for (len, root, layer) in FIXTURES {
+            let data = file(len);
+            let tree = BtV2Tree::from_bytes(&data, 2 * BLOCK_SIZE).unwrap();
+
+            assert_eq!(tree.file_len(), len as u64);
+            assert_eq!(hex(&tree.root().unwrap()), root, "len {len}");
+            assert_eq!(
+                tree.piece_layer()
+                    .iter()
+                    .map(|h| hex(h))
+                    .collect::<Vec<_>>(),
+                layer,
+                "len {len}"
+            );
+
+            // the root does not depend on the piece length
+            assert_eq!(
+                bt_v2_file_root(data.as_slice(), 4 * BLOCK_SIZE).unwrap(),
+                tree.root()
+            );
+        }
+    }
+
+    #[test]
+    fn block_and_piece_proofs() {
+        for len in [
+            BLOCK_SIZE,
+            3 * BLOCK_SIZE,
+            4 * BLOCK_SIZE + 7,
+            11 * BLOCK_SIZE,
+        ] {
+            let data = file(len);
+            let tree = BtV2Tree::from_bytes(&data, 4 * BLOCK_SIZE).unwrap();
+            let root = tree.root().unwrap();
+
+            for (block, bytes) in data.chunks(BLOCK_SIZE).enumerate() {
+                let (piece, within) = (block / 4, block % 4);
+                let piece_hash = tree.piece_hash(piece).unwrap();
+                let proof = tree.block_proof(block).unwrap();
+
+                assert!(BtV2Tree::verify_block(bytes, within, &proof, &piece_hash));
+                assert!(!BtV2Tree::verify_block(
+                    &bytes[1..],
+                    within,
+                    &proof,
+                    &piece_hash
+                ));
+                assert!(!BtV2Tree::verify_block(
+                    bytes,
+                    within + (1 << proof.len()),
+                    &proof,
+                    &piece_hash
+                ));
+            }
+
+            for piece in 0..tree.num_pieces() {
+                let piece_hash = tree.piece_hash(piece).unwrap();
+                let proof = tree.piece_proof(piece).unwrap();
+
+                assert!(BtV2Tree::verify_piece(&piece_hash, piece, &proof, &root));
+                assert!(!BtV2Tree::verify_piece(
+                    &piece_hash,
+                    piece ^ 1,
+                    &proof,
+                    &root
+                ));
+            }
+
+            assert!(tree.block_proof(tree.num_blocks()).is_none());
+            assert!(tree.piece_proof(tree.num_pieces()).is_none());
+        }
+
+        // a file of one piece checks its blocks straight against the root
+        let tree = BtV2Tree::from_bytes(&file(3 * BLOCK_SIZE), 4 * BLOCK_SIZE).unwrap();
+        assert_eq!(tree.piece_hash(0), tree.root());
+        assert!(tree.piece_layer().is_empty());
+    }
+
+    #[test]
+    fn edge_cases() {
+        assert!(
+            BtV2Tree::from_bytes(&[], BLOCK_SIZE)
+                .unwrap()
+                .root()
+                .is_none()
+        );
+        assert_eq!(bt_v2_file_root(&[][..], BLOCK_SIZE).unwrap(), None);
+
+        for piece_length in [0, BLOCK_SIZE / 2, 3 * BLOCK_SIZE] {
+            assert!(BtV2Tree::from_bytes(&[0], piece_length).is_err());
+        }
+    }
+}
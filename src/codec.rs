@@ -0,0 +1,742 @@
+use std::{
+    fmt::Debug,
+    io::{self, Read, Write},
+};
+
+use digest::Digest;
+
+use crate::{
+    error::MerkleError,
+    hex,
+    merkle::{IndexBinding, MerkleTree, Proof, ProofElement, ProofElementDirection, TagMode},
+};
+
+// compact binary encodings for shipping trees and proofs around.
+//
+// proof: u32 big-endian element count, then one direction bit per element
+// (lsb first, set means the sibling sits on the right), then the sibling hashes
+// back to back, N bytes each.
+//
+// indexed proof: the leaf index as a u64, big- or little-endian per
+// IndexEndian, followed by a compact proof. self-describing, so the verifier
+// learns which leaf the proof is for without a side channel.
+//
+// flat proof: per element one direction byte (0 left, 1 right) followed by
+// the N-byte sibling hash. larger than the compact form, but every element sits
+// at a fixed stride, which is what ffi consumers want to walk.
+//
+// zk proof: the sibling hashes back to back with no direction bytes, plus the
+// directions as a u64 with bit i set when element i's sibling sits on the
+// right. the shape constraint systems take a path in; proofs are capped at 64
+// elements, more than any tree below MAX_LEAVES produces.
+//
+// hex proof: one line per element, `left:` or `right:` followed by the sibling
+// hash in lowercase hex. meant for logs, config files and pasting into issues;
+// decoding also takes uppercase hex and a trailing newline.
+//
+// tree: a format version byte (FORMAT_VERSION) and a flags byte, then every
+// node of the flat layout back to back, N bytes each. decoding refuses any other
+// version. bit 0 of the flags is the index binding (set for prefix), bit 1 the tag
+// mode (set for none); the other bits must be clear. a keyed tree sets bit 2:
+// the key is never written out, so decoding refuses such a tree rather than
+// hand back one that hashes new leaves without it.
+//
+// stream: the length of the tree encoding as a u64 big-endian, then the tree
+// encoding, version byte and all, so several trees can share one reader.
+//
+// versioned root: FORMAT_VERSION, the width N as a byte, then the N root bytes.
+// a stored root that records its format and width can still be told apart once
+// either changes; parsing refuses any version it does not know.
+//
+// every versioned encoding carries the same FORMAT_VERSION and reports one it
+// does not know as UnsupportedVersion, so a later layout change cannot be
+// misread as this one.
+//
+// decoders take untrusted input: lengths are checked before anything is
+// allocated and a decoded tree still goes through from_nodes.
+pub const FORMAT_VERSION: u8 = 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexEndian {
+    #[default]
+    Big,
+    Little,
+}
+
+impl IndexEndian {
+    fn encode(self, index: u64) -> [u8; 8] {
+        match self {
+            Self::Big => index.to_be_bytes(),
+            Self::Little => index.to_le_bytes(),
+        }
+    }
+
+    fn decode(self, bytes: [u8; 8]) -> u64 {
+        match self {
+            Self::Big => u64::from_be_bytes(bytes),
+            Self::Little => u64::from_le_bytes(bytes),
+        }
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> MerkleTree<S, N, ND> {
+    pub fn proof_to_bytes(proof: &[ProofElement<S, N, ND>]) -> Result<Vec<u8>, MerkleError> {
+        let count = u32::try_from(proof.len())
+            .map_err(|_| MerkleError::InvalidEncoding("proof has too many elements"))?;
+
+        let mut out = Vec::with_capacity(4 + proof.len().div_ceil(8) + proof.len() * N);
+        out.extend_from_slice(&count.to_be_bytes());
+
+        for chunk in proof.chunks(8) {
+            let bits = chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |bits, (i, e)| match e.direction {
+                    ProofElementDirection::Right => bits | (1 << i),
+                    ProofElementDirection::Left => bits,
+                });
+            out.push(bits);
+        }
+
+        for e in proof {
+            out.extend_from_slice(&e.hash);
+        }
+
+        Ok(out)
+    }
+
+    pub fn proof_from_bytes(bytes: &[u8]) -> Result<Proof<S, N, ND>, MerkleError> {
+        let (count, rest) = bytes
+            .split_first_chunk::<4>()
+            .ok_or(MerkleError::InvalidEncoding("truncated element count"))?;
+        let count = usize::try_from(u32::from_be_bytes(*count))
+            .map_err(|_| MerkleError::InvalidEncoding("element count does not fit in usize"))?;
+
+        let (bitmap, hashes) = rest
+            .split_at_checked(count.div_ceil(8))
+            .ok_or(MerkleError::InvalidEncoding("truncated direction bitmap"))?;
+
+        if count.checked_mul(N) != Some(hashes.len()) {
+            return Err(MerkleError::InvalidEncoding(
+                "hash section does not match the element count",
+            ));
+        }
+
+        // padding bits past the last element must be clear, so every proof has
+        // exactly one encoding
+        if count % 8 != 0 && bitmap.last().is_some_and(|b| b >> (count % 8) != 0) {
+            return Err(MerkleError::InvalidEncoding("stray direction bits"));
+        }
+
+        (0..count)
+            .map(|i| {
+                let direction = if bitmap[i / 8] & (1 << (i % 8)) != 0 {
+                    ProofElementDirection::Right
+                } else {
+                    ProofElementDirection::Left
+                };
+                ProofElement::from_slice(&hashes[i * N..(i + 1) * N], direction)
+            })
+            .collect()
+    }
+
+    pub fn indexed_proof_to_bytes(
+        leaf_index: u64,
+        proof: &[ProofElement<S, N, ND>],
+        endian: IndexEndian,
+    ) -> Result<Vec<u8>, MerkleError> {
+        let mut out = endian.encode(leaf_index).to_vec();
+        out.extend(Self::proof_to_bytes(proof)?);
+
+        Ok(out)
+    }
+
+    pub fn indexed_proof_from_bytes(
+        bytes: &[u8],
+        endian: IndexEndian,
+    ) -> Result<(u64, Proof<S, N, ND>), MerkleError> {
+        let (index, proof) = bytes
+            .split_first_chunk::<8>()
+            .ok_or(MerkleError::InvalidEncoding("truncated leaf index"))?;
+
+        Ok((endian.decode(*index), Self::proof_from_bytes(proof)?))
+    }
+
+    #[must_use]
+    pub fn proof_to_flat(proof: &[ProofElement<S, N, ND>]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(proof.len() * (N + 1));
+
+        for e in proof {
+            out.push(match e.direction {
+                ProofElementDirection::Left => 0,
+                ProofElementDirection::Right => 1,
+            });
+            out.extend_from_slice(&e.hash);
+        }
+
+        out
+    }
+
+    pub fn proof_from_flat(bytes: &[u8]) -> Result<Proof<S, N, ND>, MerkleError> {
+        if !bytes.len().is_multiple_of(N + 1) {
+            return Err(MerkleError::InvalidEncoding(
+                "flat proof is not a whole number of elements",
+            ));
+        }
+
+        bytes
+            .chunks(N + 1)
+            .map(|element| {
+                let direction = match element[0] {
+                    0 => ProofElementDirection::Left,
+                    1 => ProofElementDirection::Right,
+                    _ => return Err(MerkleError::InvalidEncoding("unknown direction byte")),
+                };
+                ProofElement::from_slice(&element[1..], direction)
+            })
+            .collect()
+    }
+
+    pub fn proof_to_zk_flat(
+        proof: &[ProofElement<S, N, ND>],
+    ) -> Result<(Vec<u8>, u64), MerkleError> {
+        if proof.len() > 64 {
+            return Err(MerkleError::InvalidEncoding(
+                "proof has more elements than the direction mask holds",
+            ));
+        }
+
+        let mut siblings = Vec::with_capacity(proof.len() * N);
+        let mut directions = 0u64;
+
+        for (i, e) in proof.iter().enumerate() {
+            siblings.extend_from_slice(&e.hash);
+            if matches!(e.direction, ProofElementDirection::Right) {
+                directions |= 1 << i;
+            }
+        }
+
+        Ok((siblings, directions))
+    }
+
+    pub fn proof_from_zk_flat(
+        siblings: &[u8],
+        directions: u64,
+    ) -> Result<Proof<S, N, ND>, MerkleError> {
+        if !siblings.len().is_multiple_of(N) {
+            return Err(MerkleError::InvalidEncoding(
+                "siblings are not a whole number of hashes",
+            ));
+        }
+
+        let count = siblings.len() / N;
+        if count > 64 || directions.checked_shr(count as u32).unwrap_or(0) != 0 {
+            return Err(MerkleError::InvalidEncoding("stray direction bits"));
+        }
+
+        siblings
+            .chunks(N)
+            .enumerate()
+            .map(|(i, hash)| {
+                let direction = if directions & (1 << i) != 0 {
+                    ProofElementDirection::Right
+                } else {
+                    ProofElementDirection::Left
+                };
+                ProofElement::from_slice(hash, direction)
+            })
+            .collect()
+    }
+
+    #[must_use]
+    pub fn proof_hex(proof: &[ProofElement<S, N, ND>]) -> String {
+        proof
+            .iter()
+            .map(|e| {
+                let direction = match e.direction {
+                    ProofElementDirection::Left => "left",
+                    ProofElementDirection::Right => "right",
+                };
+                format!("{direction}:{}", hex::encode(&e.hash))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn proof_from_hex(s: &str) -> Result<Proof<S, N, ND>, MerkleError> {
+        s.lines()
+            .map(|line| {
+                let (direction, hash) = line
+                    .split_once(':')
+                    .ok_or(MerkleError::InvalidEncoding("expected direction:hash"))?;
+                let direction = match direction {
+                    "left" => ProofElementDirection::Left,
+                    "right" => ProofElementDirection::Right,
+                    _ => return Err(MerkleError::InvalidEncoding("unknown direction token")),
+                };
+
+                ProofElement::from_slice(&hex::decode(hash)?, direction)
+            })
+            .collect()
+    }
+
+    // None for an empty or dirty tree, like root(), and for widths past 255
+    // bytes, which the width byte cannot hold
+    #[must_use]
+    pub fn root_versioned(&self) -> Option<Vec<u8>> {
+        let root = self.root_ref()?;
+
+        let mut out = Vec::with_capacity(2 + N);
+        out.extend_from_slice(&[FORMAT_VERSION, u8::try_from(N).ok()?]);
+        out.extend_from_slice(root);
+
+        Some(out)
+    }
+
+    pub fn parse_versioned_root(bytes: &[u8]) -> Result<Vec<u8>, MerkleError> {
+        let [version, width, root @ ..] = bytes else {
+            return Err(MerkleError::InvalidEncoding("missing root header"));
+        };
+
+        if *version != FORMAT_VERSION {
+            return Err(MerkleError::UnsupportedVersion(*version));
+        }
+        if usize::from(*width) != N {
+            return Err(MerkleError::RootWidthMismatch {
+                expected: N,
+                actual: usize::from(*width),
+            });
+        }
+        if root.len() != N {
+            return Err(MerkleError::InvalidHashLength {
+                expected: N,
+                actual: root.len(),
+            });
+        }
+
+        Ok(root.to_vec())
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(2 + self.as_nodes().len() * N);
+
+        let binding = match self.index_binding() {
+            IndexBinding::None => 0,
+            IndexBinding::Prefix => 1,
+        };
+        let tags = match self.tag_mode() {
+            TagMode::Block => 0,
+            TagMode::None => 2,
+        };
+        let keyed = if self.is_keyed() { 4 } else { 0 };
+        out.extend_from_slice(&[FORMAT_VERSION, binding | tags | keyed]);
+        for node in self.as_nodes() {
+            out.extend_from_slice(node);
+        }
+
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let [version, flags, nodes @ ..] = bytes else {
+            return Err(MerkleError::InvalidEncoding("missing tree header"));
+        };
+
+        if *version != FORMAT_VERSION {
+            return Err(MerkleError::UnsupportedVersion(*version));
+        }
+        if flags & !0b11 != 0 {
+            return Err(MerkleError::InvalidEncoding("unknown tree flags"));
+        }
+
+        let index_binding = match flags & 1 {
+            0 => IndexBinding::None,
+            _ => IndexBinding::Prefix,
+        };
+        let tag_mode = match flags & 2 {
+            0 => TagMode::Block,
+            _ => TagMode::None,
+        };
+
+        if nodes.len().checked_rem(N) != Some(0) {
+            return Err(MerkleError::InvalidEncoding(
+                "node section is not a whole number of hashes",
+            ));
+        }
+
+        let config = Self::builder()
+            .bind_index(index_binding == IndexBinding::Prefix)
+            .tag_mode(tag_mode);
+
+        Self::from_nodes_with(config, nodes.chunks(N).map(<[u8]>::to_vec).collect())
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> MerkleTree<S, N, ND> {
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let bytes = self.to_bytes();
+
+        writer.write_all(&(bytes.len() as u64).to_be_bytes())?;
+        writer.write_all(&bytes)
+    }
+
+    // reads exactly one tree written by write_to and nothing past it. a bad
+    // encoding is an InvalidData error wrapping the MerkleError, e.g.
+    // UnsupportedVersion for a version this build does not know
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let invalid = |e: MerkleError| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        let mut len = [0; 8];
+        reader.read_exact(&mut len)?;
+        let len = u64::from_be_bytes(len);
+
+        // the length is untrusted, so the buffer grows with what actually arrives
+        let mut bytes = vec![];
+        reader.take(len).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        Self::from_bytes(&bytes).map_err(invalid)
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> TryFrom<&[u8]> for MerkleTree<S, N, ND> {
+    type Error = MerkleError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use std::io::ErrorKind;
+
+    use super::{FORMAT_VERSION, IndexEndian};
+    use crate::{
+        error::MerkleError,
+        merkle::{IndexBinding, MerkleTree, TagMode},
+    };
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+
+    #[test]
+    fn proofs_and_trees_round_trip() {
+        let mut tree = Tree::with_index_binding(IndexBinding::Prefix);
+        for i in 0..11u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let root = tree.root().unwrap();
+        for (i, proof) in tree.prove_all().iter().enumerate() {
+            let bytes = Tree::proof_to_bytes(proof).unwrap();
+            assert_eq!(bytes.len(), 4 + proof.len().div_ceil(8) + proof.len() * 32);
+
+            let decoded = Tree::proof_from_bytes(&bytes).unwrap();
+            assert!(Tree::verify_proof_at_index(
+                &[i as u8],
+                i,
+                &decoded,
+                &root,
+                TagMode::Block
+            ));
+        }
+
+        assert!(Tree::from_bytes(&tree.to_bytes()).unwrap() == tree);
+        assert!(Tree::from_bytes(&Tree::new().to_bytes()).unwrap() == Tree::new());
+
+        let mut untagged = Tree::builder().tag_mode(TagMode::None).build();
+        for i in 0..5u8 {
+            untagged.add(&[i]).unwrap();
+        }
+        let bytes = untagged.to_bytes();
+        assert_eq!(bytes[..2], [FORMAT_VERSION, 2]);
+        assert!(Tree::from_bytes(&bytes).unwrap() == untagged);
+    }
+
+    #[test]
+    fn versioned_roots() {
+        let tree = Tree::from_leaves([b"a", b"b", b"c"]).unwrap();
+        let root = tree.root().unwrap();
+
+        let bytes = tree.root_versioned().unwrap();
+        assert_eq!(bytes[..2], [FORMAT_VERSION, 32]);
+        assert_eq!(bytes[2..], root);
+        assert_eq!(Tree::parse_versioned_root(&bytes).unwrap(), root);
+        assert!(Tree::new().root_versioned().is_none());
+
+        let mut unknown = bytes.clone();
+        unknown[0] = FORMAT_VERSION + 1;
+        assert_eq!(
+            Tree::parse_versioned_root(&unknown).unwrap_err(),
+            MerkleError::UnsupportedVersion(FORMAT_VERSION + 1)
+        );
+
+        // a root stored from a narrower tree, a cut short one, and no header
+        let narrow = MerkleTree::<Sha256, 20, 40>::from_leaves([b"a"])
+            .unwrap()
+            .root_versioned()
+            .unwrap();
+        assert_eq!(
+            Tree::parse_versioned_root(&narrow).unwrap_err(),
+            MerkleError::RootWidthMismatch {
+                expected: 32,
+                actual: 20
+            }
+        );
+        assert_eq!(
+            Tree::parse_versioned_root(&bytes[..33]).unwrap_err(),
+            MerkleError::InvalidHashLength {
+                expected: 32,
+                actual: 31
+            }
+        );
+        assert_eq!(
+            Tree::parse_versioned_root(&[1]).unwrap_err(),
+            MerkleError::InvalidEncoding("missing root header")
+        );
+    }
+
+    #[test]
+    fn indexed_proofs_round_trip_in_both_byte_orders() {
+        let tree = Tree::from_leaves((0..300u16).map(u16::to_be_bytes)).unwrap();
+        let root = tree.root().unwrap();
+        let proof = tree.create_proof_by_index(258).unwrap();
+
+        for (endian, prefix) in [
+            (IndexEndian::Big, [0, 0, 0, 0, 0, 0, 1, 2]),
+            (IndexEndian::Little, [2, 1, 0, 0, 0, 0, 0, 0]),
+        ] {
+            let bytes = Tree::indexed_proof_to_bytes(258, &proof, endian).unwrap();
+            assert_eq!(bytes[..8], prefix);
+            assert_eq!(bytes[8..], Tree::proof_to_bytes(&proof).unwrap());
+
+            let (index, decoded) = Tree::indexed_proof_from_bytes(&bytes, endian).unwrap();
+            assert_eq!(index, 258);
+            assert!(Tree::verify_proof(&258u16.to_be_bytes(), &decoded, &root));
+        }
+
+        assert_eq!(IndexEndian::default(), IndexEndian::Big);
+        assert!(Tree::indexed_proof_from_bytes(&[0; 7], IndexEndian::Big).is_err());
+    }
+
+    #[test]
+    fn flat_proofs_round_trip() {
+        let tree = Tree::from_leaves((0..6u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+
+        for (i, proof) in tree.prove_all().iter().enumerate() {
+            let flat = Tree::proof_to_flat(proof);
+            assert_eq!(flat.len(), proof.len() * 33);
+
+            for (element, chunk) in proof.iter().zip(flat.chunks(33)) {
+                assert_eq!(&chunk[1..], element.hash.as_slice());
+            }
+
+            let decoded = Tree::proof_from_flat(&flat).unwrap();
+            assert!(Tree::verify_proof(&[i as u8], &decoded, &root));
+        }
+
+        let mut flat = Tree::proof_to_flat(&tree.prove_all()[0]);
+        assert_eq!(flat[0], 1);
+        assert!(Tree::proof_from_flat(&flat[1..]).is_err());
+
+        flat[0] = 2;
+        assert_eq!(
+            Tree::proof_from_flat(&flat).unwrap_err(),
+            MerkleError::InvalidEncoding("unknown direction byte")
+        );
+    }
+
+    #[test]
+    fn zk_flat_proofs_round_trip() {
+        let tree = Tree::from_leaves((0..13u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+
+        for (i, proof) in tree.prove_all().iter().enumerate() {
+            let (siblings, directions) = Tree::proof_to_zk_flat(proof).unwrap();
+            assert_eq!(siblings.len(), proof.len() * 32);
+            assert!(
+                siblings
+                    .chunks(32)
+                    .eq(proof.iter().map(|e| e.hash.as_slice()))
+            );
+
+            let decoded = Tree::proof_from_zk_flat(&siblings, directions).unwrap();
+            assert!(Tree::verify_proof(&[i as u8], &decoded, &root));
+        }
+
+        // leaf 0 only ever has siblings to its right
+        let (siblings, directions) = Tree::proof_to_zk_flat(&tree.prove_all()[0]).unwrap();
+        assert_eq!(directions, (1 << (siblings.len() / 32)) - 1);
+
+        assert!(Tree::proof_from_zk_flat(&siblings[1..], directions).is_err());
+        assert_eq!(
+            Tree::proof_from_zk_flat(&siblings, directions | 1 << 40).unwrap_err(),
+            MerkleError::InvalidEncoding("stray direction bits")
+        );
+        assert_eq!(
+            Tree::proof_to_zk_flat(&Tree::proof_from_flat(&[0; 65 * 33]).unwrap()).unwrap_err(),
+            MerkleError::InvalidEncoding("proof has more elements than the direction mask holds")
+        );
+    }
+
+    #[test]
+    fn hex_proofs_round_trip() {
+        let tree = Tree::from_leaves((0..6u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+
+        for (i, proof) in tree.prove_all().iter().enumerate() {
+            let hex = Tree::proof_hex(proof);
+            assert_eq!(hex.lines().count(), proof.len());
+
+            let decoded = Tree::proof_from_hex(&hex).unwrap();
+            assert_eq!(Tree::proof_hex(&decoded), hex);
+            assert!(Tree::verify_proof(&[i as u8], &decoded, &root));
+
+            let decoded = Tree::proof_from_hex(&format!("{hex}\n")).unwrap();
+            assert!(Tree::verify_proof(&[i as u8], &decoded, &root));
+        }
+
+        let hex = Tree::proof_hex(&tree.prove_all()[5]);
+        assert!(hex.starts_with("left:"));
+        let (_, hash) = hex.lines().next().unwrap().split_once(':').unwrap();
+        assert_eq!(hash.len(), 64);
+        assert!(Tree::proof_from_hex(&format!("left:{}", hash.to_uppercase())).is_ok());
+        assert_eq!(Tree::proof_from_hex("").unwrap().len(), 0);
+
+        assert_eq!(
+            Tree::proof_from_hex(&format!("up:{hash}")).unwrap_err(),
+            MerkleError::InvalidEncoding("unknown direction token")
+        );
+        assert_eq!(
+            Tree::proof_from_hex(hash).unwrap_err(),
+            MerkleError::InvalidEncoding("expected direction:hash")
+        );
+        assert_eq!(
+            Tree::proof_from_hex(&format!("left:{}", &hash[2..])).unwrap_err(),
+            MerkleError::InvalidHashLength {
+                expected: 32,
+                actual: 31
+            }
+        );
+        for bad in [
+            &hash[1..],
+            &format!("+{}", &hash[2..]),
+            &format!("zz{}", &hash[2..]),
+        ] {
+            assert_eq!(
+                Tree::proof_from_hex(&format!("right:{bad}")).unwrap_err(),
+                MerkleError::InvalidEncoding("not an even-length hex string")
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_bytes_are_rejected() {
+        let mut tree = Tree::new();
+        for i in 0..3u8 {
+            tree.add(&[i]).unwrap();
+        }
+        let proof = Tree::proof_to_bytes(&tree.prove_all()[0]).unwrap();
+
+        assert!(Tree::proof_from_bytes(&proof[..proof.len() - 1]).is_err());
+        assert!(Tree::proof_from_bytes(&[0xff, 0xff, 0xff, 0xff]).is_err());
+
+        let mut stray = proof.clone();
+        stray[4] |= 0x80;
+        assert_eq!(
+            Tree::proof_from_bytes(&stray).unwrap_err(),
+            MerkleError::InvalidEncoding("stray direction bits")
+        );
+
+        let bytes = tree.to_bytes();
+        assert!(Tree::from_bytes(&[]).is_err());
+        assert!(Tree::from_bytes(&bytes[..bytes.len() - 32]).is_err());
+        assert!(Tree::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        assert!(matches!(
+            Tree::from_bytes(&[FORMAT_VERSION]),
+            Err(MerkleError::InvalidEncoding("missing tree header"))
+        ));
+
+        let mut flags = bytes.clone();
+        flags[1] = 4;
+        assert!(matches!(
+            Tree::from_bytes(&flags),
+            Err(MerkleError::InvalidEncoding("unknown tree flags"))
+        ));
+
+        let mut version = bytes;
+        version[0] = FORMAT_VERSION + 1;
+        assert_eq!(
+            Tree::from_bytes(&version).err(),
+            Some(MerkleError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+        assert_eq!(
+            Tree::try_from(version.as_slice()).err(),
+            Some(MerkleError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn try_from_bytes() {
+        let mut tree = Tree::builder()
+            .tag_mode(TagMode::None)
+            .bind_index(true)
+            .build();
+        for i in 0..6u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let bytes = tree.to_bytes();
+        let decoded = Tree::try_from(bytes.as_slice()).unwrap();
+        assert!(decoded == tree);
+        assert_eq!(decoded.tag_mode(), TagMode::None);
+
+        let decoded: Result<Tree, _> = bytes[..bytes.len() - 1].try_into();
+        assert!(matches!(
+            decoded,
+            Err(MerkleError::InvalidEncoding(
+                "node section is not a whole number of hashes"
+            ))
+        ));
+    }
+
+    #[test]
+    fn versioned_streams() {
+        let tree = Tree::from_leaves((0..5u8).map(|i| [i])).unwrap();
+
+        let mut stream = vec![];
+        tree.write_to(&mut stream).unwrap();
+        assert_eq!(stream[8], FORMAT_VERSION);
+
+        // a second tree right behind the first is left for the next read
+        Tree::new().write_to(&mut stream).unwrap();
+        let mut reader = stream.as_slice();
+        assert!(Tree::read_from(&mut reader).unwrap() == tree);
+        assert!(Tree::read_from(&mut reader).unwrap().is_empty());
+        assert!(reader.is_empty());
+
+        let mut bumped = stream.clone();
+        bumped[8] = FORMAT_VERSION + 1;
+        let err = Tree::read_from(bumped.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(
+            *err.into_inner().unwrap().downcast::<MerkleError>().unwrap(),
+            MerkleError::UnsupportedVersion(FORMAT_VERSION + 1)
+        );
+
+        // a length running past the end of the stream
+        let mut short = stream[..8 + tree.to_bytes().len()].to_vec();
+        short[7] += 1;
+        assert_eq!(
+            Tree::read_from(short.as_slice()).err().unwrap().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+}
@@ -0,0 +1,573 @@
+use digest::Digest;
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::{
+    error::MerkleError,
+    flat,
+    merkle::{LEAF_TAG, MerkleTree, NODE_TAG, ProofElementDirection},
+};
+
+// how a CompositeMerkleTree hashes: leaves one way and two children into their
+// parent another, as whatever Output suits the hash. composite commitment schemes
+// hash leaves and nodes under different functions; an arithmetic hash such as
+// poseidon outputs a field element rather than bytes and keeps its parameters
+// (round constants, an mds matrix) in the value.
+//
+// from_leaves hands over each level's pairs in one level call, which a hash
+// with a batched or simd api can override to hash them together; the default
+// hashes them one by one.
+//
+// every Digest is one, hashing exactly as MerkleTree<D, n, 2n> does at the
+// digest's full width n: a leaf is H(0x01 * n || H(data)) and a node
+// H(0x02 * n || H(H(left || right))). Tagged truncates to another width and
+// Untagged drops the tags
+pub trait TreeHasher {
+    type Output: Clone + Eq;
+
+    fn leaf(&self, data: &[u8]) -> Self::Output;
+    fn node(&self, left: &Self::Output, right: &Self::Output) -> Self::Output;
+
+    fn level(&self, pairs: &[(&Self::Output, &Self::Output)]) -> Vec<Self::Output> {
+        pairs
+            .iter()
+            .map(|(left, right)| self.node(left, right))
+            .collect()
+    }
+}
+
+impl<D: Digest> TreeHasher for D {
+    type Output = digest::Output<D>;
+
+    fn leaf(&self, data: &[u8]) -> Self::Output {
+        let tag = vec![LEAF_TAG; <D as Digest>::output_size()];
+        D::new()
+            .chain_update(tag)
+            .chain_update(D::digest(data))
+            .finalize()
+    }
+
+    fn node(&self, left: &Self::Output, right: &Self::Output) -> Self::Output {
+        let tag = vec![NODE_TAG; <D as Digest>::output_size()];
+        let inner = D::new().chain_update(left).chain_update(right).finalize();
+        D::new()
+            .chain_update(tag)
+            .chain_update(D::digest(inner))
+            .finalize()
+    }
+}
+
+// MerkleTree<S, N, ND>'s own hashing, truncated to N bytes, and its default H
+pub struct Tagged<S, const N: usize, const ND: usize>(PhantomData<fn() -> S>);
+
+impl<S, const N: usize, const ND: usize> Default for Tagged<S, N, ND> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> TreeHasher for Tagged<S, N, ND> {
+    type Output = Vec<u8>;
+
+    fn leaf(&self, data: &[u8]) -> Vec<u8> {
+        MerkleTree::<S, N, ND>::tag_hash(LEAF_TAG, data)
+    }
+
+    fn node(&self, left: &Vec<u8>, right: &Vec<u8>) -> Vec<u8> {
+        MerkleTree::<S, N, ND>::node_hash(left, right)
+    }
+}
+
+// a leaf's data hashed as-is and a node as H(left || right) at full width, as
+// TagMode::None and rs_merkle do. without tags an internal node's children pass
+// for a leaf, so this has to be asked for by name
+pub struct Untagged<D>(PhantomData<fn() -> D>);
+
+impl<D> Default for Untagged<D> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<D: Digest> TreeHasher for Untagged<D> {
+    type Output = digest::Output<D>;
+
+    fn leaf(&self, data: &[u8]) -> Self::Output {
+        D::digest(data)
+    }
+
+    fn node(&self, left: &Self::Output, right: &Self::Output) -> Self::Output {
+        D::new().chain_update(left).chain_update(right).finalize()
+    }
+}
+
+// siblings from the leaf upwards
+pub type CompositeProof<T> = Vec<(T, ProofElementDirection)>;
+
+// MerkleTree's flat layout over any TreeHasher, keeping hashes as H::Output so a
+// field-based hasher never round-trips through bytes. CompositeMerkleTree<D>
+// for a Digest D, or over Tagged<S, N, ND>, reproduces the matching MerkleTree
+// root for root and proof for proof.
+//
+// the tree does not check widths; a hasher must accept whatever it produces,
+// since the left-perfect shape can pair a leaf with an internal node.
+pub struct CompositeMerkleTree<H: TreeHasher> {
+    hasher: H,
+    tree: Vec<H::Output>,
+}
+
+impl<H: TreeHasher + Default> Default for CompositeMerkleTree<H> {
+    fn default() -> Self {
+        Self::new(H::default())
+    }
+}
+
+impl<H: TreeHasher> CompositeMerkleTree<H> {
+    // the same cap as MerkleTree::MAX_LEAVES
+    pub const MAX_LEAVES: usize = flat::MAX_LEAVES;
+
+    #[must_use]
+    pub fn new(hasher: H) -> Self {
+        Self {
+            hasher,
+            tree: vec![],
+        }
+    }
+
+    // hashes the leaves, then the internal nodes a level at a time
+    pub fn from_leaves<I>(hasher: H, leaves: I) -> Result<Self, MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut tree = Self::new(hasher);
+        for leaf in leaves {
+            if tree.len() >= Self::MAX_LEAVES {
+                return Err(MerkleError::CapacityExceeded {
+                    max: Self::MAX_LEAVES,
+                });
+            }
+
+            // internal slots hold a copy of the next leaf until their level is hashed
+            let leaf = tree.hasher.leaf(leaf.as_ref());
+            if !tree.tree.is_empty() {
+                tree.tree.push(leaf.clone());
+            }
+            tree.tree.push(leaf);
+        }
+
+        // every odd index is an internal node whose height is its count of
+        // trailing ones, and both its children sit lower, so filling the
+        // heights in increasing order always finds the children done
+        let size = tree.tree.len();
+        let mut height = 1;
+        while let Some(first) = (1usize << height).checked_sub(1).filter(|&f| f < size) {
+            let nodes = (first..size).step_by(2 << height).collect::<Vec<_>>();
+            let hashes = {
+                let pairs = nodes
+                    .iter()
+                    .map(|&node| {
+                        let (Some(left), Some(right)) = (
+                            flat::pbt_left_child(node),
+                            flat::lpbt_right_child(node, size),
+                        ) else {
+                            return Err(MerkleError::Structural("could not get children"));
+                        };
+
+                        Ok((&tree.tree[left], &tree.tree[right]))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                tree.hasher.level(&pairs)
+            };
+
+            if hashes.len() != nodes.len() {
+                return Err(MerkleError::Structural(
+                    "level returned a hash count other than its pair count",
+                ));
+            }
+            for (node, hash) in nodes.into_iter().zip(hashes) {
+                tree.tree[node] = hash;
+            }
+            height += 1;
+        }
+
+        Ok(tree)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tree.len().div_ceil(2)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    #[must_use]
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    pub fn add(&mut self, data: &[u8]) -> Result<(), MerkleError> {
+        if self.len() >= Self::MAX_LEAVES {
+            return Err(MerkleError::CapacityExceeded {
+                max: Self::MAX_LEAVES,
+            });
+        }
+
+        let leaf = self.hasher.leaf(data);
+        if !self.tree.is_empty() {
+            self.tree.push(leaf.clone());
+        }
+        self.tree.push(leaf);
+
+        let mut node = self.tree.len() - 1;
+        while let Some(parent) = flat::lpbt_parent(node, self.tree.len()) {
+            let (Some(left), Some(right)) = (
+                flat::pbt_left_child(parent),
+                flat::lpbt_right_child(parent, self.tree.len()),
+            ) else {
+                return Err(MerkleError::Structural("could not get children"));
+            };
+
+            self.tree[parent] = self.hasher.node(&self.tree[left], &self.tree[right]);
+            node = parent;
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<H::Output> {
+        flat::lpbt_root(self.tree.len()).and_then(|r| self.tree.get(r).cloned())
+    }
+
+    #[must_use]
+    pub fn create_proof_by_index(&self, index: usize) -> Option<CompositeProof<H::Output>> {
+        let mut node = index.checked_mul(2).filter(|&n| n < self.tree.len())?;
+
+        let mut proof = vec![];
+        while let Some(parent) = flat::lpbt_parent(node, self.tree.len()) {
+            let left = flat::pbt_left_child(parent)?;
+            let right = flat::lpbt_right_child(parent, self.tree.len())?;
+
+            proof.push(if node == left {
+                (self.tree.get(right)?.clone(), ProofElementDirection::Right)
+            } else {
+                (self.tree.get(left)?.clone(), ProofElementDirection::Left)
+            });
+            node = parent;
+        }
+
+        Some(proof)
+    }
+
+    #[must_use]
+    pub fn verify_proof(
+        &self,
+        data: &[u8],
+        proof: &[(H::Output, ProofElementDirection)],
+        root: &H::Output,
+    ) -> bool {
+        let implied =
+            proof.iter().fold(
+                self.hasher.leaf(data),
+                |acc, (hash, direction)| match direction {
+                    ProofElementDirection::Left => self.hasher.node(hash, &acc),
+                    ProofElementDirection::Right => self.hasher.node(&acc, hash),
+                },
+            );
+
+        implied == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256, Sha512};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{CompositeMerkleTree, Tagged, TreeHasher, Untagged};
+    use crate::merkle::{MerkleTree, TagMode};
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+
+    // reuses one sha256 state across a level and counts the calls
+    #[derive(Default)]
+    struct BatchedNodes;
+
+    static LEVELS: AtomicUsize = AtomicUsize::new(0);
+
+    impl TreeHasher for BatchedNodes {
+        type Output = Vec<u8>;
+
+        fn leaf(&self, data: &[u8]) -> Vec<u8> {
+            Tagged::<Sha256, 32, 64>::default().leaf(data)
+        }
+
+        fn node(&self, left: &Vec<u8>, right: &Vec<u8>) -> Vec<u8> {
+            Tagged::<Sha256, 32, 64>::default().node(left, right)
+        }
+
+        fn level(&self, pairs: &[(&Vec<u8>, &Vec<u8>)]) -> Vec<Vec<u8>> {
+            LEVELS.fetch_add(1, Ordering::Relaxed);
+
+            let mut hasher = Sha256::new();
+            pairs
+                .iter()
+                .map(|(left, right)| {
+                    Digest::update(&mut hasher, [left.as_slice(), right].concat());
+                    let inner = hasher.finalize_reset();
+                    Digest::update(&mut hasher, inner);
+                    let rehashed = hasher.finalize_reset();
+                    Digest::update(&mut hasher, [2; 32]);
+                    Digest::update(&mut hasher, rehashed);
+                    hasher.finalize_reset().to_vec()
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn levels_are_hashed_in_one_call_each() {
+        type Batched = CompositeMerkleTree<BatchedNodes>;
+
+        for size in [1usize, 2, 5, 8, 13] {
+            let leaves = (0..size as u8).map(|i| [i]).collect::<Vec<_>>();
+
+            LEVELS.store(0, Ordering::Relaxed);
+            let batched = Batched::from_leaves(BatchedNodes, &leaves).unwrap();
+            assert_eq!(
+                LEVELS.load(Ordering::Relaxed),
+                (size - 1).checked_ilog2().map_or(0, |h| h as usize + 1)
+            );
+
+            // the same tree as leaf by leaf appends, which go through node
+            let mut appended = Batched::default();
+            for leaf in &leaves {
+                appended.add(leaf).unwrap();
+            }
+            assert_eq!(batched.tree, appended.tree);
+            assert_eq!(batched.root(), Tree::from_leaves(&leaves).unwrap().root());
+        }
+    }
+
+    #[test]
+    fn tagged_matches_merkle_tree() {
+        let leaves = (0..9u8).map(|i| [i]).collect::<Vec<_>>();
+        let composite =
+            CompositeMerkleTree::from_leaves(Tagged::<Sha256, 32, 64>::default(), &leaves).unwrap();
+        let tree = Tree::from_leaves(&leaves).unwrap();
+        let root = composite.root().unwrap();
+        assert_eq!(Some(root.clone()), tree.root());
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = composite.create_proof_by_index(i).unwrap();
+            let expected = tree.create_proof_by_index(i).unwrap();
+
+            assert!(
+                proof
+                    .iter()
+                    .map(|(hash, _)| hash.as_slice())
+                    .eq(expected.iter().map(|e| e.hash().as_slice()))
+            );
+            assert!(Tree::verify_proof(leaf, &expected, &root));
+            assert!(composite.verify_proof(leaf, &proof, &root));
+        }
+
+        // and at a truncated width
+        let narrow =
+            CompositeMerkleTree::from_leaves(Tagged::<Sha256, 16, 32>::default(), &leaves).unwrap();
+        assert_eq!(
+            narrow.root(),
+            MerkleTree::<Sha256, 16, 32>::from_leaves(&leaves)
+                .unwrap()
+                .root()
+        );
+    }
+
+    // leaves under a different digest than the nodes above them
+    #[derive(Default)]
+    struct Sha512Leaves;
+
+    impl TreeHasher for Sha512Leaves {
+        type Output = Vec<u8>;
+
+        fn leaf(&self, data: &[u8]) -> Vec<u8> {
+            Sha512::digest([&[0], data].concat())[..32].to_vec()
+        }
+
+        fn node(&self, left: &Vec<u8>, right: &Vec<u8>) -> Vec<u8> {
+            Sha256::new()
+                .chain_update([1])
+                .chain_update(left)
+                .chain_update(right)
+                .finalize()
+                .to_vec()
+        }
+    }
+
+    #[test]
+    fn differing_leaf_and_node_hashes_round_trip_proofs() {
+        let leaves = (0..7u8).map(|i| [i]).collect::<Vec<_>>();
+        let tree = CompositeMerkleTree::from_leaves(Sha512Leaves, &leaves).unwrap();
+        let root = tree.root().unwrap();
+
+        assert_eq!(tree.len(), 7);
+        assert_ne!(
+            Some(root.clone()),
+            Tree::from_leaves(&leaves).unwrap().root()
+        );
+
+        // two leaves: the root is the node hash over both leaf hashes
+        let pair = CompositeMerkleTree::from_leaves(Sha512Leaves, [[0], [1]]).unwrap();
+        let hasher = Sha512Leaves;
+        assert_eq!(
+            pair.root().unwrap(),
+            hasher.node(&hasher.leaf(&[0]), &hasher.leaf(&[1]))
+        );
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree.create_proof_by_index(i).unwrap();
+            assert!(tree.verify_proof(leaf, &proof, &root));
+            assert!(!tree.verify_proof(&[i as u8 + 1], &proof, &root));
+        }
+
+        assert!(tree.create_proof_by_index(7).is_none());
+        assert!(
+            CompositeMerkleTree::<Sha512Leaves>::default()
+                .root()
+                .is_none()
+        );
+    }
+
+    // a toy sponge over the field mod 2^61 - 1 in place of poseidon: the round
+    // constants live in the hasher and every hash is a single field element,
+    // with no Digest anywhere
+    struct ToySponge {
+        constants: [u64; 3],
+    }
+
+    impl ToySponge {
+        const P: u128 = (1 << 61) - 1;
+
+        fn permute(&self, mut state: u128) -> u64 {
+            for c in self.constants {
+                state = (state.pow(2) % Self::P * state + u128::from(c)) % Self::P;
+            }
+            state as u64
+        }
+    }
+
+    impl TreeHasher for ToySponge {
+        type Output = u64;
+
+        fn leaf(&self, data: &[u8]) -> u64 {
+            data.iter()
+                .fold(1, |acc, &b| self.permute(u128::from(acc) + u128::from(b)))
+        }
+
+        fn node(&self, left: &u64, right: &u64) -> u64 {
+            self.permute(u128::from(self.permute(u128::from(*left))) + u128::from(*right))
+        }
+    }
+
+    #[test]
+    fn tree_over_field_elements() {
+        let sponge = || ToySponge {
+            constants: [3, 5, 7],
+        };
+        let leaves = (0..6u8).map(|i| [i]).collect::<Vec<_>>();
+        let tree = CompositeMerkleTree::from_leaves(sponge(), &leaves).unwrap();
+        let root = tree.root().unwrap();
+
+        let h = leaves.iter().map(|l| sponge().leaf(l)).collect::<Vec<_>>();
+        let hasher = sponge();
+        let left = hasher.node(&hasher.node(&h[0], &h[1]), &hasher.node(&h[2], &h[3]));
+        assert_eq!(root, hasher.node(&left, &hasher.node(&h[4], &h[5])));
+
+        // appends agree with the bulk build
+        let mut appended = CompositeMerkleTree::new(sponge());
+        for leaf in &leaves {
+            appended.add(leaf).unwrap();
+            assert_eq!(
+                appended.root(),
+                CompositeMerkleTree::from_leaves(sponge(), &leaves[..appended.len()])
+                    .unwrap()
+                    .root()
+            );
+        }
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree.create_proof_by_index(i).unwrap();
+            assert!(tree.verify_proof(leaf, &proof, &root));
+            assert!(!tree.verify_proof(&[9], &proof, &root));
+        }
+        assert!(tree.create_proof_by_index(6).is_none());
+
+        // other constants are another hash function
+        let other = CompositeMerkleTree::from_leaves(
+            ToySponge {
+                constants: [3, 5, 8],
+            },
+            &leaves,
+        )
+        .unwrap();
+        assert_ne!(other.root(), Some(root));
+        assert!(CompositeMerkleTree::new(sponge()).root().is_none());
+    }
+
+    #[test]
+    fn digests_match_merkle_tree_unchanged() {
+        for size in 1..=9u8 {
+            let leaves = (0..size).map(|i| [i]).collect::<Vec<_>>();
+            let typed = CompositeMerkleTree::from_leaves(Sha256::new(), &leaves).unwrap();
+            let tree = Tree::from_leaves(&leaves).unwrap();
+
+            let root = typed.root().unwrap();
+            assert_eq!(Some(root.to_vec()), tree.root());
+
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = typed.create_proof_by_index(i).unwrap();
+                let expected = tree.create_proof_by_index(i).unwrap();
+                assert!(
+                    proof
+                        .iter()
+                        .map(|(hash, _)| hash.as_slice())
+                        .eq(expected.iter().map(|e| e.hash().as_slice()))
+                );
+                assert!(
+                    proof
+                        .iter()
+                        .map(|(_, d)| *d)
+                        .eq(expected.iter().map(|e| e.direction()))
+                );
+                assert!(typed.verify_proof(leaf, &proof, &root));
+            }
+        }
+
+        let sha512 = CompositeMerkleTree::<Sha512>::default();
+        assert!(sha512.is_empty());
+        let sha512 = CompositeMerkleTree::from_leaves(Sha512::new(), [b"a", b"b", b"c"]).unwrap();
+        assert_eq!(
+            Some(sha512.root().unwrap().to_vec()),
+            MerkleTree::<Sha512, 64, 128>::from_leaves([b"a", b"b", b"c"])
+                .unwrap()
+                .root()
+        );
+    }
+
+    #[test]
+    fn untagged_digests_match_untagged_trees() {
+        let leaves = (0..6u8).map(|i| [i]).collect::<Vec<_>>();
+        let composite =
+            CompositeMerkleTree::from_leaves(Untagged::<Sha256>::default(), &leaves).unwrap();
+
+        let mut untagged = Tree::builder().tag_mode(TagMode::None).build();
+        for leaf in &leaves {
+            untagged.add(leaf).unwrap();
+        }
+        assert_eq!(composite.root().map(|r| r.to_vec()), untagged.root());
+    }
+}
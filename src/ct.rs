@@ -0,0 +1,250 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{error::MerkleError, rfc6962::Rfc6962Tree};
+
+// certificate transparency responses as serde types, serialized with serde_json
+// or any other serde format. audit paths run from the leaf upwards.
+
+// the tree head fields of a get-sth response. rfc 6962 logs name the root
+// sha256_root_hash, which is accepted too. logs also send a timestamp and a
+// signature; those are ignored here, checking the signature is up to the caller
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedTreeHead {
+    pub tree_size: u64,
+    #[serde(alias = "sha256_root_hash")]
+    pub root_hash: String,
+}
+
+// a get-proof-by-hash response
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub leaf_index: u64,
+    pub audit_path: Vec<String>,
+}
+
+// the single object ct tooling takes an inclusion proof as, with the tree size
+// the path was made for beside the leaf index
+#[derive(Serialize)]
+struct CtProof<'a> {
+    leaf_index: u64,
+    tree_size: u64,
+    audit_path: &'a [String],
+}
+
+fn decode(field: &str) -> Result<Vec<u8>, MerkleError> {
+    STANDARD
+        .decode(field)
+        .map_err(|_| MerkleError::InvalidEncoding("invalid base64"))
+}
+
+impl SignedTreeHead {
+    #[must_use]
+    pub fn new(tree_size: u64, root: &[u8]) -> Self {
+        Self {
+            tree_size,
+            root_hash: STANDARD.encode(root),
+        }
+    }
+
+    #[must_use]
+    pub fn from_tree(tree: &Rfc6962Tree<Sha256>) -> Self {
+        Self::new(tree.len() as u64, &tree.root())
+    }
+
+    pub fn root(&self) -> Result<Vec<u8>, MerkleError> {
+        decode(&self.root_hash)
+    }
+}
+
+impl InclusionProof {
+    pub fn new<I>(leaf_index: u64, audit_path: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        Self {
+            leaf_index,
+            audit_path: audit_path
+                .into_iter()
+                .map(|hash| STANDARD.encode(hash))
+                .collect(),
+        }
+    }
+
+    // an audit path as Rfc6962Tree::audit_path gives it, for a leaf_index the
+    // caller already split off. MerkleTree proofs are hashed under other tags and
+    // no ct client would accept them, so only sha-256 widths are taken
+    pub fn from_proof(audit_path: &[Vec<u8>], leaf_index: u64) -> Result<Self, MerkleError> {
+        if let Some(hash) = audit_path.iter().find(|hash| hash.len() != 32) {
+            return Err(MerkleError::InvalidHashLength {
+                expected: 32,
+                actual: hash.len(),
+            });
+        }
+
+        Ok(Self::new(leaf_index, audit_path))
+    }
+
+    #[must_use]
+    pub fn from_tree(tree: &Rfc6962Tree<Sha256>, leaf_index: usize) -> Option<Self> {
+        Some(Self::new(leaf_index as u64, tree.audit_path(leaf_index)?))
+    }
+
+    pub fn audit_path(&self) -> Result<Vec<Vec<u8>>, MerkleError> {
+        self.audit_path.iter().map(|hash| decode(hash)).collect()
+    }
+}
+
+// renders an audit path as Rfc6962Tree::audit_path gives it as
+// {"leaf_index":..,"tree_size":..,"audit_path":[base64..]}. the object parses
+// as an InclusionProof too, which ignores the size
+#[must_use]
+pub fn proof_to_ct_json(audit_path: &[Vec<u8>], leaf_index: u64, tree_size: u64) -> String {
+    let proof = InclusionProof::new(leaf_index, audit_path);
+
+    serde_json::to_string(&CtProof {
+        leaf_index,
+        tree_size,
+        audit_path: &proof.audit_path,
+    })
+    .expect("a struct of integers and strings always serializes")
+}
+
+// checks that leaf_input (the decoded MerkleTreeLeaf a log serves from
+// get-entries) is included in the tree described by sth. Ok(false) is a proof
+// that does not verify; Err is a response that is not valid base64
+pub fn verify_ct_inclusion(
+    leaf_input: &[u8],
+    sth: &SignedTreeHead,
+    proof: &InclusionProof,
+) -> Result<bool, MerkleError> {
+    Ok(Rfc6962Tree::<Sha256>::verify_inclusion(
+        leaf_input,
+        proof.leaf_index,
+        sth.tree_size,
+        &proof.audit_path()?,
+        &sth.root()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use sha2::Sha256;
+
+    use super::{InclusionProof, SignedTreeHead, proof_to_ct_json, verify_ct_inclusion};
+    use crate::{error::MerkleError, rfc6962::Rfc6962Tree};
+
+    #[test]
+    fn ct_json_empty_audit_path() {
+        let tree = Rfc6962Tree::<Sha256>::from_leaves([[0x00]]);
+
+        let proof = InclusionProof::from_tree(&tree, 0).unwrap();
+        assert_eq!(
+            serde_json::to_string(&proof).unwrap(),
+            r#"{"leaf_index":0,"audit_path":[]}"#
+        );
+    }
+
+    #[test]
+    fn ct_json_shape() {
+        let tree = Rfc6962Tree::<Sha256>::from_leaves((0..5u8).map(|i| [i]));
+        let path = tree.audit_path(1).unwrap();
+        let json = proof_to_ct_json(&path, 1, 5);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["leaf_index"], 1);
+        assert_eq!(value["tree_size"], 5);
+
+        let entries = value["audit_path"].as_array().unwrap();
+        assert_eq!(entries.len(), path.len());
+        for (entry, hash) in entries.iter().zip(&path) {
+            assert_eq!(STANDARD.decode(entry.as_str().unwrap()).unwrap(), *hash);
+        }
+
+        let proof: InclusionProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(proof, InclusionProof::from_tree(&tree, 1).unwrap());
+        assert_eq!(
+            proof_to_ct_json(&[], 0, 1),
+            r#"{"leaf_index":0,"tree_size":1,"audit_path":[]}"#
+        );
+    }
+
+    // shaped like live get-sth / get-proof-by-hash / get-entries responses, over
+    // the eight leaf tree from the ct reference test vectors
+    const STH: &str = r#"{
+        "tree_size": 8,
+        "timestamp": 1700000000000,
+        "sha256_root_hash": "XcnaeacGWamtVZy3Ad7ZoqudgjqtL0lgz+Nw7/RgQyg=",
+        "tree_head_signature": "BAMARjBEAiA="
+    }"#;
+
+    const PROOF: &str = r#"{
+        "leaf_index": 5,
+        "audit_path": [
+            "vBoGQ7EuTS18d5GPROD095qDi2z57FtcKD4fTYhZnms=",
+            "yoVOoSjtBQtBs1/8G4e46yveRh6eO1WW7Oa51ZdaCuA=",
+            "037kGJdt2VdTwcc4Yrk5j6Kiz5tP8P3+izDNlSCWFLc="
+        ]
+    }"#;
+
+    const LEAF_INPUT: &str = "QEFCQw==";
+
+    #[test]
+    fn verifies_log_shaped_json() {
+        let sth: SignedTreeHead = serde_json::from_str(STH).unwrap();
+        let proof: InclusionProof = serde_json::from_str(PROOF).unwrap();
+        let leaf = STANDARD.decode(LEAF_INPUT).unwrap();
+
+        assert_eq!(verify_ct_inclusion(&leaf, &sth, &proof), Ok(true));
+        assert_eq!(verify_ct_inclusion(b"other", &sth, &proof), Ok(false));
+
+        let moved = InclusionProof {
+            leaf_index: 4,
+            ..proof.clone()
+        };
+        assert_eq!(verify_ct_inclusion(&leaf, &sth, &moved), Ok(false));
+
+        let mut garbled = proof;
+        garbled.audit_path[0].push('!');
+        assert_eq!(
+            verify_ct_inclusion(&leaf, &sth, &garbled),
+            Err(MerkleError::InvalidEncoding("invalid base64"))
+        );
+    }
+
+    #[test]
+    fn round_trips_through_serde_json() {
+        let leaves = (0..7u8).map(|i| [i]).collect::<Vec<_>>();
+        let tree = Rfc6962Tree::<Sha256>::from_leaves(&leaves);
+
+        let sth = SignedTreeHead::from_tree(&tree);
+        let decoded: SignedTreeHead =
+            serde_json::from_str(&serde_json::to_string(&sth).unwrap()).unwrap();
+        assert_eq!(decoded, sth);
+        assert_eq!(decoded.root().unwrap(), tree.root());
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = InclusionProof::from_tree(&tree, i).unwrap();
+            let decoded: InclusionProof =
+                serde_json::from_value(serde_json::to_value(&proof).unwrap()).unwrap();
+
+            assert_eq!(decoded.audit_path().unwrap(), tree.audit_path(i).unwrap());
+            assert_eq!(verify_ct_inclusion(leaf, &sth, &decoded), Ok(true));
+        }
+        assert!(InclusionProof::from_tree(&tree, 7).is_none());
+
+        let path = tree.audit_path(3).unwrap();
+        let proof = InclusionProof::from_proof(&path, 3).unwrap();
+        assert_eq!(proof, InclusionProof::from_tree(&tree, 3).unwrap());
+        assert_eq!(
+            InclusionProof::from_proof(&[vec![0; 20]], 3),
+            Err(MerkleError::InvalidHashLength {
+                expected: 32,
+                actual: 20
+            })
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{
+    error::MerkleError,
+    flat,
+    merkle::{LEAF_TAG, NODE_TAG, ProofElementDirection},
+};
+
+pub type Hasher = Box<dyn Fn(&[u8]) -> Vec<u8>>;
+
+// same layout and tagging as MerkleTree, but the hash function is a boxed closure
+// picked at runtime instead of a type parameter. hash outputs are truncated to n
+// bytes, which plays the role of MerkleTree's N.
+pub struct DynMerkleTree {
+    tree: Vec<Vec<u8>>,
+    hasher: Hasher,
+    n: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct DynProofElement {
+    pub(crate) hash: Vec<u8>,
+    pub(crate) direction: ProofElementDirection,
+}
+
+impl DynProofElement {
+    // for proofs assembled by hand. the width is only known to a tree, so
+    // verify_proof rejects elements that are not n bytes
+    #[must_use]
+    pub fn new(hash: Vec<u8>, direction: ProofElementDirection) -> Self {
+        Self { hash, direction }
+    }
+
+    #[must_use]
+    pub fn hash(&self) -> &[u8] {
+        &self.hash
+    }
+
+    #[must_use]
+    pub fn direction(&self) -> ProofElementDirection {
+        self.direction
+    }
+}
+
+impl DynMerkleTree {
+    // the same cap as MerkleTree::MAX_LEAVES
+    pub const MAX_LEAVES: usize = flat::MAX_LEAVES;
+
+    // n must be at least 1 and no wider than what hasher returns
+    pub fn new(hasher: Hasher, n: usize) -> Result<Self, MerkleError> {
+        Self::check_width(&hasher, n)?;
+
+        Ok(Self {
+            tree: vec![],
+            hasher,
+            n,
+        })
+    }
+
+    fn check_width(hasher: &Hasher, n: usize) -> Result<(), MerkleError> {
+        let max = hasher(&[]).len();
+        if n == 0 || n > max {
+            return Err(MerkleError::UnsupportedWidth { width: n, max });
+        }
+
+        Ok(())
+    }
+
+    // drops every leaf and switches to n-byte hashes, keeping the node
+    // allocation for reuse. on error the tree is left as it was
+    pub fn reinit(&mut self, n: usize) -> Result<(), MerkleError> {
+        Self::check_width(&self.hasher, n)?;
+
+        self.tree.clear();
+        self.n = n;
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tree.len().div_ceil(2)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    fn hash(&self, data: &[u8]) -> Vec<u8> {
+        let mut out = (self.hasher)(data);
+        out.truncate(self.n);
+        out
+    }
+
+    fn tag_hash(&self, tag: u8, data: &[u8]) -> Vec<u8> {
+        let mut block = vec![tag; self.n];
+        block.extend_from_slice(&self.hash(data));
+
+        self.hash(&block)
+    }
+
+    fn node_hash(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.tag_hash(NODE_TAG, &self.hash(&[left, right].concat()))
+    }
+
+    pub fn add(&mut self, data: &[u8]) -> Result<(), MerkleError> {
+        if self.len() >= Self::MAX_LEAVES {
+            return Err(MerkleError::CapacityExceeded {
+                max: Self::MAX_LEAVES,
+            });
+        }
+
+        let leaf = self.tag_hash(LEAF_TAG, data);
+
+        if !self.tree.is_empty() {
+            self.tree.push(vec![0; self.n]);
+        }
+        self.tree.push(leaf);
+
+        let mut node = self.tree.len() - 1;
+        while let Some(parent) = flat::lpbt_parent(node, self.tree.len()) {
+            let (Some(left), Some(right)) = (
+                flat::pbt_left_child(parent),
+                flat::lpbt_right_child(parent, self.tree.len()),
+            ) else {
+                return Err(MerkleError::Structural("could not get children"));
+            };
+
+            self.tree[parent] = self.node_hash(&self.tree[left], &self.tree[right]);
+            node = parent;
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Vec<u8>> {
+        if self.tree.is_empty() {
+            return None;
+        }
+
+        flat::lpbt_root(self.tree.len()).and_then(|r| self.tree.get(r).cloned())
+    }
+
+    #[must_use]
+    pub fn create_proof(&self, data: &[u8]) -> Option<Vec<DynProofElement>> {
+        let leaf = self.tag_hash(LEAF_TAG, data);
+        let mut node = (0..self.tree.len())
+            .step_by(2)
+            .find(|&i| self.tree[i] == leaf)?;
+
+        let mut proof = vec![];
+        while let Some(parent) = flat::lpbt_parent(node, self.tree.len()) {
+            let left = flat::pbt_left_child(parent)?;
+            let right = flat::lpbt_right_child(parent, self.tree.len())?;
+
+            proof.push(if node == left {
+                DynProofElement {
+                    hash: self.tree.get(right)?.clone(),
+                    direction: ProofElementDirection::Right,
+                }
+            } else {
+                DynProofElement {
+                    hash: self.tree.get(left)?.clone(),
+                    direction: ProofElementDirection::Left,
+                }
+            });
+            node = parent;
+        }
+
+        Some(proof)
+    }
+
+    #[must_use]
+    pub fn verify_proof(&self, data: &[u8], proof: &[DynProofElement], to_match: &[u8]) -> bool {
+        if proof.iter().any(|e| e.hash.len() != self.n) {
+            return false;
+        }
+
+        let leaf = self.tag_hash(LEAF_TAG, data);
+        let generated = proof.iter().fold(leaf, |acc, e| match e.direction {
+            ProofElementDirection::Left => self.node_hash(&e.hash, &acc),
+            ProofElementDirection::Right => self.node_hash(&acc, &e.hash),
+        });
+
+        generated == to_match
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+
+    use super::{DynMerkleTree, DynProofElement, Hasher};
+    use crate::{error::MerkleError, merkle::MerkleTree};
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+
+    fn sha256() -> Hasher {
+        Box::new(|data| Sha256::digest(data).to_vec())
+    }
+
+    #[test]
+    fn matches_static_tree() {
+        let mut dynamic = DynMerkleTree::new(sha256(), 32).unwrap();
+        let mut fixed = Tree::new();
+        assert!(dynamic.root().is_none());
+
+        for i in 0..7u8 {
+            dynamic.add(&[i]).unwrap();
+            fixed.add(&[i]).unwrap();
+
+            assert_eq!(dynamic.root(), fixed.root());
+        }
+
+        let root = dynamic.root().unwrap();
+        for i in 0..7u8 {
+            let proof = dynamic.create_proof(&[i]).unwrap();
+
+            assert!(dynamic.verify_proof(&[i], &proof, &root));
+            assert!(!dynamic.verify_proof(&[i + 1], &proof, &root));
+        }
+
+        assert!(dynamic.create_proof(&[0x07]).is_none());
+    }
+
+    #[test]
+    fn proofs_rebuilt_from_parts() {
+        let mut tree = DynMerkleTree::new(sha256(), 16).unwrap();
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+        let root = tree.root().unwrap();
+
+        let proof = tree.create_proof(&[3]).unwrap();
+        let rebuilt = proof
+            .iter()
+            .map(|e| DynProofElement::new(e.hash().to_vec(), e.direction()))
+            .collect::<Vec<_>>();
+        assert!(tree.verify_proof(&[3], &rebuilt, &root));
+
+        let mut widened = rebuilt;
+        widened[0] = DynProofElement::new(vec![0; 17], widened[0].direction());
+        assert!(!tree.verify_proof(&[3], &widened, &root));
+    }
+
+    #[test]
+    fn widths_are_checked() {
+        for n in [0, 33] {
+            assert_eq!(
+                DynMerkleTree::new(sha256(), n).err(),
+                Some(MerkleError::UnsupportedWidth { width: n, max: 32 })
+            );
+        }
+        assert!(DynMerkleTree::new(sha256(), 1).is_ok());
+    }
+
+    #[test]
+    fn reinit_changes_width() {
+        let mut tree = DynMerkleTree::new(sha256(), 16).unwrap();
+
+        for n in [16, 32] {
+            tree.reinit(n).unwrap();
+            assert!(tree.is_empty());
+            assert!(tree.root().is_none());
+
+            for i in 0..5u8 {
+                tree.add(&[i]).unwrap();
+            }
+            let root = tree.root().unwrap();
+            assert_eq!(root.len(), n);
+
+            for i in 0..5u8 {
+                let proof = tree.create_proof(&[i]).unwrap();
+                assert!(proof.iter().all(|e| e.hash.len() == n));
+                assert!(tree.verify_proof(&[i], &proof, &root));
+                assert!(!tree.verify_proof(&[i + 1], &proof, &root));
+            }
+        }
+        assert_eq!(
+            tree.root(),
+            Tree::from_leaves((0..5u8).map(|i| [i])).unwrap().root()
+        );
+
+        // a rejected width leaves the tree untouched
+        assert_eq!(
+            tree.reinit(33),
+            Err(MerkleError::UnsupportedWidth { width: 33, max: 32 })
+        );
+        assert_eq!(tree.len(), 5);
+    }
+}
@@ -0,0 +1,81 @@
+use std::{error::Error, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleError {
+    CapacityExceeded { max: usize },
+    EmptyLeaf,
+    EmptyTree,
+    IntegrityViolation { index: usize },
+    InvalidEncoding(&'static str),
+    InvalidHashLength { expected: usize, actual: usize },
+    InvalidNodeCount(usize),
+    LeafOutOfBounds { index: usize, len: usize },
+    PendingPlaceholders(usize),
+    RandomnessUnavailable,
+    RootWidthMismatch { expected: usize, actual: usize },
+    Structural(&'static str),
+    SumOverflow,
+    UnsupportedAlgorithm(String),
+    UnsupportedVersion(u8),
+    UnsupportedWidth { width: usize, max: usize },
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityExceeded { max } => {
+                write!(f, "tree is full, it can hold at most {max} leaves")
+            }
+            Self::EmptyLeaf => write!(f, "strict trees refuse empty leaf data"),
+            Self::EmptyTree => write!(f, "tree has no leaves"),
+            Self::IntegrityViolation { index } => {
+                write!(f, "node {index} is not the hash of its children")
+            }
+            Self::InvalidEncoding(msg) => write!(f, "invalid encoding: {msg}"),
+            Self::InvalidHashLength { expected, actual } => {
+                write!(
+                    f,
+                    "invalid hash length: expected {expected} bytes, got {actual}"
+                )
+            }
+            Self::InvalidNodeCount(count) => {
+                write!(
+                    f,
+                    "{count} nodes cannot form a flat tree, expected 0 or an odd count"
+                )
+            }
+            Self::LeafOutOfBounds { index, len } => {
+                write!(
+                    f,
+                    "leaf index {index} out of bounds for tree of {len} leaves"
+                )
+            }
+            Self::PendingPlaceholders(count) => {
+                write!(
+                    f,
+                    "tree has {count} unfilled placeholder nodes and needs a repair"
+                )
+            }
+            Self::RandomnessUnavailable => {
+                write!(f, "the operating system could not supply random bytes")
+            }
+            Self::RootWidthMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "root is {actual} bytes wide but the tree uses {expected}-byte hashes"
+                )
+            }
+            Self::Structural(msg) => write!(f, "structural error: {msg}"),
+            Self::SumOverflow => write!(f, "the sum of leaf values overflows a u64"),
+            Self::UnsupportedAlgorithm(name) => write!(f, "unsupported hash algorithm {name:?}"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            Self::UnsupportedWidth { width, max } => {
+                write!(f, "hash width {width} is outside 1..={max} bytes")
+            }
+        }
+    }
+}
+
+impl Error for MerkleError {}
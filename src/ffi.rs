@@ -0,0 +1,355 @@
+use sha2::Sha256;
+use std::{
+    panic::{AssertUnwindSafe, catch_unwind},
+    ptr, slice,
+};
+
+use crate::{error::MerkleError, merkle::MerkleTree};
+
+// c api over MerkleTree<Sha256, 32, 64>, declared in include/merkle.h, which
+// cbindgen generates from this file (see cbindgen.toml). proofs cross the
+// boundary in the flat format of codec.rs, one direction byte and 32 hash bytes
+// per element, so c code can walk them at a fixed stride.
+//
+// every function returns MT_OK or a negative MT_ERR_* code and never unwinds
+// into c: a panic is caught and reported as MT_ERR_PANIC. pointers may be null
+// only where their length is 0. trees are owned by the caller from mt_tree_new
+// until the single matching mt_tree_free; every buffer stays owned by the
+// caller and is only borrowed for the duration of the call.
+pub type FfiTree = MerkleTree<Sha256, 32, 64>;
+
+pub const MT_HASH_LEN: usize = 32;
+pub const MT_PROOF_ELEMENT_LEN: usize = 1 + MT_HASH_LEN;
+
+pub const MT_OK: i32 = 0;
+pub const MT_ERR_NULL_POINTER: i32 = -1;
+pub const MT_ERR_PROOF_MISMATCH: i32 = -2;
+pub const MT_ERR_INVALID_ENCODING: i32 = -3;
+pub const MT_ERR_INVALID_HASH_LENGTH: i32 = -4;
+pub const MT_ERR_LEAF_OUT_OF_BOUNDS: i32 = -5;
+pub const MT_ERR_CAPACITY_EXCEEDED: i32 = -6;
+pub const MT_ERR_EMPTY_TREE: i32 = -7;
+pub const MT_ERR_BUFFER_TOO_SMALL: i32 = -8;
+pub const MT_ERR_OTHER: i32 = -9;
+pub const MT_ERR_PANIC: i32 = -100;
+
+// an opaque handle, only ever seen through a pointer on the c side
+pub struct MtTree(FfiTree);
+
+fn error_code(e: &MerkleError) -> i32 {
+    match e {
+        MerkleError::InvalidEncoding(_) => MT_ERR_INVALID_ENCODING,
+        MerkleError::InvalidHashLength { .. } | MerkleError::RootWidthMismatch { .. } => {
+            MT_ERR_INVALID_HASH_LENGTH
+        }
+        MerkleError::LeafOutOfBounds { .. } => MT_ERR_LEAF_OUT_OF_BOUNDS,
+        MerkleError::CapacityExceeded { .. } => MT_ERR_CAPACITY_EXCEEDED,
+        MerkleError::EmptyTree => MT_ERR_EMPTY_TREE,
+        _ => MT_ERR_OTHER,
+    }
+}
+
+// runs body with every panic turned into MT_ERR_PANIC
+fn guarded(body: impl FnOnce() -> Result<(), i32>) -> i32 {
+    match catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => MT_OK,
+        Ok(Err(code)) => code,
+        Err(_) => MT_ERR_PANIC,
+    }
+}
+
+// a borrowed byte buffer; null is only accepted for an empty one
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], i32> {
+    match (data.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err(MT_ERR_NULL_POINTER),
+        // SAFETY: the caller vouches for len readable bytes at data
+        (false, _) => Ok(unsafe { slice::from_raw_parts(data, len) }),
+    }
+}
+
+/// Checks a flat proof for `data` against a 32-byte root.
+///
+/// Returns `MT_OK` if the proof reaches the root, `MT_ERR_PROOF_MISMATCH` if a
+/// well-formed proof does not, and another negative code for malformed input.
+///
+/// # Safety
+///
+/// Each pointer must be valid for reads of its length in bytes for the duration
+/// of the call. A pointer may only be null when its length is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_verify_proof(
+    data_ptr: *const u8,
+    data_len: usize,
+    proof_ptr: *const u8,
+    proof_len: usize,
+    root_ptr: *const u8,
+    root_len: usize,
+) -> i32 {
+    guarded(|| {
+        // SAFETY: forwarded from this function's contract
+        let (data, proof, root) = unsafe {
+            (
+                bytes(data_ptr, data_len)?,
+                bytes(proof_ptr, proof_len)?,
+                bytes(root_ptr, root_len)?,
+            )
+        };
+
+        let proof = FfiTree::proof_from_flat(proof).map_err(|e| error_code(&e))?;
+        match FfiTree::verify_proof_detailed(data, &proof, root) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(MT_ERR_PROOF_MISMATCH),
+            Err(e) => Err(error_code(&e)),
+        }
+    })
+}
+
+/// Creates an empty tree, or returns null if that fails.
+///
+/// The tree must be released with `mt_tree_free` exactly once.
+#[unsafe(no_mangle)]
+pub extern "C" fn mt_tree_new() -> *mut MtTree {
+    catch_unwind(|| Box::into_raw(Box::new(MtTree(FfiTree::new())))).unwrap_or(ptr::null_mut())
+}
+
+/// Releases a tree from `mt_tree_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `tree` must be null or a pointer from `mt_tree_new` that has not been freed.
+/// It must not be used again afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_tree_free(tree: *mut MtTree) {
+    if !tree.is_null() {
+        // SAFETY: the pointer came from Box::into_raw in mt_tree_new and is freed once
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(tree) })));
+    }
+}
+
+/// Appends a leaf holding `data_len` bytes of `data_ptr`.
+///
+/// # Safety
+///
+/// `tree` must be a live pointer from `mt_tree_new` not used concurrently, and
+/// `data_ptr` valid for reads of `data_len` bytes (or null if it is 0).
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_tree_add(
+    tree: *mut MtTree,
+    data_ptr: *const u8,
+    data_len: usize,
+) -> i32 {
+    guarded(|| {
+        // SAFETY: forwarded from this function's contract
+        let tree = unsafe { tree.as_mut() }.ok_or(MT_ERR_NULL_POINTER)?;
+        let data = unsafe { bytes(data_ptr, data_len)? };
+
+        tree.0.add(data).map_err(|e| error_code(&e))
+    })
+}
+
+/// The number of leaves in the tree, or 0 for a null tree.
+///
+/// # Safety
+///
+/// `tree` must be null or a live pointer from `mt_tree_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_tree_len(tree: *const MtTree) -> usize {
+    // SAFETY: forwarded from this function's contract
+    unsafe { tree.as_ref() }.map_or(0, |tree| tree.0.len())
+}
+
+/// Writes the tree's 32-byte root to `out`.
+///
+/// # Safety
+///
+/// `tree` must be a live pointer from `mt_tree_new`, and `out` valid for
+/// writes of `out_len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_tree_root(tree: *const MtTree, out: *mut u8, out_len: usize) -> i32 {
+    guarded(|| {
+        // SAFETY: forwarded from this function's contract
+        let tree = unsafe { tree.as_ref() }.ok_or(MT_ERR_NULL_POINTER)?;
+        let root = tree.0.root().ok_or(MT_ERR_EMPTY_TREE)?;
+
+        // SAFETY: forwarded from this function's contract
+        unsafe { write_out(&root, out, out_len) }
+    })
+}
+
+/// Writes the flat proof for the leaf at `index` to `out` and its length in
+/// bytes, `MT_PROOF_ELEMENT_LEN` per element, to `*written`.
+///
+/// If `out_len` is too small nothing is written to `out`, `*written` is set to
+/// the length needed and `MT_ERR_BUFFER_TOO_SMALL` returned, so a caller can
+/// size its buffer with a first call passing a null `out` and 0.
+///
+/// # Safety
+///
+/// `tree` must be a live pointer from `mt_tree_new`, `out` valid for writes of
+/// `out_len` bytes (or null if it is 0), and `written` valid for one write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_tree_proof(
+    tree: *const MtTree,
+    index: usize,
+    out: *mut u8,
+    out_len: usize,
+    written: *mut usize,
+) -> i32 {
+    guarded(|| {
+        // SAFETY: forwarded from this function's contract
+        let tree = unsafe { tree.as_ref() }.ok_or(MT_ERR_NULL_POINTER)?;
+        let written = unsafe { written.as_mut() }.ok_or(MT_ERR_NULL_POINTER)?;
+
+        let proof = tree
+            .0
+            .try_create_proof_by_index(index)
+            .map_err(|e| error_code(&e))?;
+        let flat = FfiTree::proof_to_flat(&proof);
+
+        *written = flat.len();
+        // SAFETY: forwarded from this function's contract
+        unsafe { write_out(&flat, out, out_len) }
+    })
+}
+
+unsafe fn write_out(bytes: &[u8], out: *mut u8, out_len: usize) -> Result<(), i32> {
+    if out_len < bytes.len() {
+        return Err(MT_ERR_BUFFER_TOO_SMALL);
+    }
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    if out.is_null() {
+        return Err(MT_ERR_NULL_POINTER);
+    }
+
+    // SAFETY: out holds at least out_len >= bytes.len() writable bytes
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len()) };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+
+    // the c signatures, called through function pointers of exactly those types
+    type VerifyFn =
+        unsafe extern "C" fn(*const u8, usize, *const u8, usize, *const u8, usize) -> i32;
+    type ProofFn = unsafe extern "C" fn(*const MtTree, usize, *mut u8, usize, *mut usize) -> i32;
+
+    const VERIFY: VerifyFn = mt_verify_proof;
+    const PROOF: ProofFn = mt_tree_proof;
+
+    // leaves 00..04, the root merkle.rs pins its zeroize test to
+    const ROOT: &str = "43680581585abed181b1eec574d755598bd9773593cb15dda0898c6110a65909";
+
+    fn verify(data: &[u8], proof: &[u8], root: &[u8]) -> i32 {
+        unsafe {
+            VERIFY(
+                data.as_ptr(),
+                data.len(),
+                proof.as_ptr(),
+                proof.len(),
+                root.as_ptr(),
+                root.len(),
+            )
+        }
+    }
+
+    #[test]
+    fn trees_and_proofs_through_the_c_abi() {
+        let tree = mt_tree_new();
+        assert!(!tree.is_null());
+
+        let mut root = [0; MT_HASH_LEN];
+        unsafe {
+            assert_eq!(
+                mt_tree_root(tree, root.as_mut_ptr(), root.len()),
+                MT_ERR_EMPTY_TREE
+            );
+            let mut len = 0;
+            assert_eq!(
+                PROOF(tree, 0, ptr::null_mut(), 0, &mut len),
+                MT_ERR_EMPTY_TREE
+            );
+            for i in 0..5u8 {
+                assert_eq!(mt_tree_add(tree, &i, 1), MT_OK);
+            }
+            assert_eq!(mt_tree_len(tree), 5);
+            assert_eq!(mt_tree_root(tree, root.as_mut_ptr(), root.len()), MT_OK);
+            assert_eq!(
+                mt_tree_root(tree, root.as_mut_ptr(), 31),
+                MT_ERR_BUFFER_TOO_SMALL
+            );
+        }
+        assert_eq!(crate::hex::encode(&root), ROOT);
+
+        for i in 0..5u8 {
+            // sized by a first call with no buffer
+            let mut len = 0;
+            assert_eq!(
+                unsafe { PROOF(tree, i.into(), ptr::null_mut(), 0, &mut len) },
+                MT_ERR_BUFFER_TOO_SMALL
+            );
+            assert_eq!(len % MT_PROOF_ELEMENT_LEN, 0);
+
+            let mut proof = vec![0; len];
+            assert_eq!(
+                unsafe { PROOF(tree, i.into(), proof.as_mut_ptr(), len, &mut len) },
+                MT_OK
+            );
+
+            assert_eq!(verify(&[i], &proof, &root), MT_OK);
+            assert_eq!(verify(&[9], &proof, &root), MT_ERR_PROOF_MISMATCH);
+            assert_eq!(
+                verify(&[i], &proof, &root[..31]),
+                MT_ERR_INVALID_HASH_LENGTH
+            );
+            assert_eq!(verify(&[i], &proof[1..], &root), MT_ERR_INVALID_ENCODING);
+        }
+
+        let mut len = 0;
+        assert_eq!(
+            unsafe { PROOF(tree, 5, ptr::null_mut(), 0, &mut len) },
+            MT_ERR_LEAF_OUT_OF_BOUNDS
+        );
+        unsafe { mt_tree_free(tree) };
+    }
+
+    #[test]
+    fn null_pointers_are_refused() {
+        let root = [0; MT_HASH_LEN];
+        unsafe {
+            assert_eq!(
+                VERIFY(ptr::null(), 1, ptr::null(), 0, root.as_ptr(), 32),
+                MT_ERR_NULL_POINTER
+            );
+            assert_eq!(
+                mt_tree_add(ptr::null_mut(), ptr::null(), 0),
+                MT_ERR_NULL_POINTER
+            );
+            assert_eq!(mt_tree_len(ptr::null()), 0);
+            mt_tree_free(ptr::null_mut());
+
+            // an empty leaf may come as a null pointer
+            let tree = mt_tree_new();
+            assert_eq!(mt_tree_add(tree, ptr::null(), 0), MT_OK);
+            assert_eq!(
+                PROOF(tree, 0, ptr::null_mut(), 0, ptr::null_mut()),
+                MT_ERR_NULL_POINTER
+            );
+            mt_tree_free(tree);
+        }
+
+        // a single empty leaf's root verifies an empty proof with null buffers
+        let tree = FfiTree::from_leaves([b""]).unwrap();
+        let root = tree.root().unwrap();
+        assert_eq!(
+            unsafe { VERIFY(ptr::null(), 0, ptr::null(), 0, root.as_ptr(), root.len()) },
+            MT_OK
+        );
+    }
+}
@@ -0,0 +1,84 @@
+// all inlined functions related to flat binary trees are from this article:
+// https://mmapped.blog/posts/22-flat-in-order-trees
+
+// these helpers are written so they never wrap: anything that would leave the
+// usize range comes back as None. trees are capped at MAX_LEAVES, which keeps
+// every index they produce for a real tree in range.
+
+// 2^(usize::BITS - 2) leaves. past this the layout's node indices (2 * leaves - 1,
+// rounded up to a power of two) would no longer fit in a usize
+pub(crate) const MAX_LEAVES: usize = 1 << (usize::BITS - 2);
+
+#[inline]
+pub(crate) fn last_set_bit(n: usize) -> usize {
+    n & n.wrapping_neg()
+}
+
+#[inline]
+pub(crate) fn last_zero_bit(n: usize) -> usize {
+    last_set_bit(!n)
+}
+
+#[inline]
+pub(crate) fn pbt_parent(n: usize) -> Option<usize> {
+    let up = last_zero_bit(n).checked_mul(2).filter(|&b| b != 0)?;
+    Some((last_zero_bit(n) | n) & !up)
+}
+
+#[inline]
+pub(crate) fn pbt_left_child(n: usize) -> Option<usize> {
+    if n & 1 == 1 {
+        Some(n & !(last_zero_bit(n) >> 1))
+    } else {
+        None
+    }
+}
+
+#[inline]
+pub(crate) fn pbt_right_child(n: usize) -> Option<usize> {
+    if n & 1 == 1 {
+        Some((n | last_zero_bit(n)) & !(last_zero_bit(n) >> 1))
+    } else {
+        None
+    }
+}
+
+#[inline]
+pub(crate) fn lpbt_root(size: usize) -> Option<usize> {
+    Some((size.checked_add(1)?.checked_next_power_of_two()? - 1) >> 1)
+}
+
+#[inline]
+pub(crate) fn pbt_leftmost_leaf(n: usize) -> Option<usize> {
+    Some(n & n.checked_add(1)?)
+}
+
+#[inline]
+pub(crate) fn lpbt_parent(n: usize, size: usize) -> Option<usize> {
+    if n == lpbt_root(size)? {
+        None
+    } else {
+        let p = pbt_parent(n)?;
+        if p < size {
+            Some(p)
+        } else {
+            pbt_leftmost_leaf(n)?.checked_sub(1)
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn lpbt_right_child(n: usize, size: usize) -> Option<usize> {
+    if n & 1 == 1 {
+        let r = pbt_right_child(n)?;
+
+        if r < size {
+            Some(r)
+        } else {
+            let rest = size.checked_sub(n)?.checked_sub(1)?;
+            n.checked_add(1)?.checked_add(lpbt_root(rest)?)
+        }
+    } else {
+        None
+    }
+}
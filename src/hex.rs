@@ -0,0 +1,49 @@
+use crate::error::MerkleError;
+
+// the hex codec behind every text format in the crate: lowercase out, either
+// case in, and nothing but hex digits in between
+
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn decode(hex: &str) -> Result<Vec<u8>, MerkleError> {
+    // from_str_radix alone would also take a leading '+'
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(MerkleError::InvalidEncoding(
+            "not an even-length hex string",
+        ));
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| MerkleError::InvalidEncoding("not an even-length hex string"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode};
+    use crate::error::MerkleError;
+
+    #[test]
+    fn round_trips_and_rejects_non_hex() {
+        let bytes = [0x00, 0x7f, 0xab, 0xff];
+        assert_eq!(encode(&bytes), "007fabff");
+        assert_eq!(decode("007fabff").unwrap(), bytes);
+        assert_eq!(decode("007FABFF").unwrap(), bytes);
+        assert!(decode("").unwrap().is_empty());
+
+        for bad in ["0", "zz", "+1", "-1", " 1", "é0"] {
+            assert_eq!(
+                decode(bad),
+                Err(MerkleError::InvalidEncoding(
+                    "not an even-length hex string"
+                ))
+            );
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use ::ics23::{
+    CommitmentProof, ExistenceProof, HashOp, InnerOp, InnerSpec, LeafOp, LengthOp, ProofSpec,
+    commitment_proof,
+};
+use sha2::Sha256;
+
+use crate::{
+    error::MerkleError,
+    merkle::{MerkleTree, ProofElement, ProofElementDirection},
+};
+
+// exports MerkleTree<Sha256, 32, 64> proofs as ics23 (cosmos.ics23.v1)
+// existence proofs, so standard ics23 verifiers can check them against the
+// tree's root with proof_spec().
+//
+// ics23 hashes a leaf as H(prefix || key || H(value)) and every other step as
+// H(prefix || child || suffix). this tree's leaf is H([1; 32] || H(data)), so
+// the leaf prefix is 31 bytes of the tag and the key the 32nd: the tree commits
+// to no key, and ics23 refuses an empty one, so every proof uses LEAF_KEY. a
+// node is H([2; 32] || H(H(left || right))), which takes three inner ops per
+// level: one combining the children, a bare rehash, then one applying the tag.
+//
+// only the full-width sha256 tree maps onto ics23's hash ops, and leaves with
+// empty data cannot be exported since ics23 also refuses an empty value.
+pub type Ics23Tree = MerkleTree<Sha256, 32, 64>;
+
+pub const LEAF_KEY: &[u8] = &[Ics23Tree::LEAF_TAG];
+
+// the spec the exported proofs verify under. a function rather than a constant
+// since the proto types own their buffers, as with ics23's own tendermint_spec()
+#[must_use]
+pub fn proof_spec() -> ProofSpec {
+    ProofSpec {
+        leaf_spec: Some(leaf_op()),
+        inner_spec: Some(InnerSpec {
+            child_order: vec![0, 1],
+            child_size: 32,
+            // a right child's left sibling and the tag block are a single child's
+            // worth of prefix, the other steps have none
+            min_prefix_length: 0,
+            max_prefix_length: 0,
+            empty_child: vec![],
+            hash: HashOp::Sha256.into(),
+        }),
+        min_depth: 0,
+        max_depth: 0,
+        prehash_key_before_comparison: false,
+    }
+}
+
+fn leaf_op() -> LeafOp {
+    LeafOp {
+        hash: HashOp::Sha256.into(),
+        prehash_key: HashOp::NoHash.into(),
+        prehash_value: HashOp::Sha256.into(),
+        length: LengthOp::NoPrefix.into(),
+        prefix: vec![Ics23Tree::LEAF_TAG; 31],
+    }
+}
+
+fn inner_op(prefix: Vec<u8>, suffix: Vec<u8>) -> InnerOp {
+    InnerOp {
+        hash: HashOp::Sha256.into(),
+        prefix,
+        suffix,
+    }
+}
+
+pub fn existence_proof(
+    data: &[u8],
+    proof: &[ProofElement<Sha256, 32, 64>],
+) -> Result<ExistenceProof, MerkleError> {
+    if data.is_empty() {
+        return Err(MerkleError::InvalidEncoding(
+            "ics23 cannot carry an empty leaf value",
+        ));
+    }
+
+    let mut path = Vec::with_capacity(proof.len() * 3);
+    for e in proof {
+        path.push(match e.direction {
+            ProofElementDirection::Left => inner_op(e.hash.to_vec(), vec![]),
+            ProofElementDirection::Right => inner_op(vec![], e.hash.to_vec()),
+        });
+        path.push(inner_op(vec![], vec![]));
+        path.push(inner_op(vec![Ics23Tree::NODE_TAG; 32], vec![]));
+    }
+
+    Ok(ExistenceProof {
+        key: LEAF_KEY.to_vec(),
+        value: data.to_vec(),
+        leaf: Some(leaf_op()),
+        path,
+    })
+}
+
+// the existence proof wrapped the way verify_membership takes it
+pub fn commitment_proof(
+    data: &[u8],
+    proof: &[ProofElement<Sha256, 32, 64>],
+) -> Result<CommitmentProof, MerkleError> {
+    Ok(CommitmentProof {
+        proof: Some(commitment_proof::Proof::Exist(existence_proof(
+            data, proof,
+        )?)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ::ics23::{HostFunctionsProvider, verify_membership};
+    use sha2::{Digest, Sha256};
+
+    use super::{Ics23Tree, LEAF_KEY, commitment_proof, existence_proof, proof_spec};
+    use crate::error::MerkleError;
+
+    // the exported proofs only ever ask for sha256
+    struct Sha256Only;
+
+    impl HostFunctionsProvider for Sha256Only {
+        fn sha2_256(message: &[u8]) -> [u8; 32] {
+            Sha256::digest(message).into()
+        }
+
+        fn sha2_512(_: &[u8]) -> [u8; 64] {
+            unimplemented!()
+        }
+
+        fn sha2_512_truncated(_: &[u8]) -> [u8; 32] {
+            unimplemented!()
+        }
+
+        fn keccak_256(_: &[u8]) -> [u8; 32] {
+            unimplemented!()
+        }
+
+        fn ripemd160(_: &[u8]) -> [u8; 20] {
+            unimplemented!()
+        }
+
+        fn blake2b_512(_: &[u8]) -> [u8; 64] {
+            unimplemented!()
+        }
+
+        fn blake2s_256(_: &[u8]) -> [u8; 32] {
+            unimplemented!()
+        }
+
+        fn blake3(_: &[u8]) -> [u8; 32] {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn exported_proofs_pass_the_ics23_verifier() {
+        let leaves = (0..7u8).map(|i| [b'v', i]).collect::<Vec<_>>();
+        let tree = Ics23Tree::from_leaves(&leaves).unwrap();
+        let root = tree.root().unwrap();
+        let spec = proof_spec();
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = commitment_proof(leaf, &tree.create_proof_by_index(i).unwrap()).unwrap();
+
+            assert!(verify_membership::<Sha256Only>(
+                &proof, &spec, &root, LEAF_KEY, leaf
+            ));
+
+            // tampered value, tampered root, and another leaf's data
+            assert!(!verify_membership::<Sha256Only>(
+                &proof, &spec, &root, LEAF_KEY, b"other"
+            ));
+            assert!(!verify_membership::<Sha256Only>(
+                &proof,
+                &spec,
+                &vec![0; 32],
+                LEAF_KEY,
+                leaf
+            ));
+            assert!(!verify_membership::<Sha256Only>(
+                &proof,
+                &spec,
+                &root,
+                LEAF_KEY,
+                &leaves[(i + 1) % 7]
+            ));
+        }
+
+        // a single-leaf tree's root is the leaf op alone
+        let single = Ics23Tree::from_leaves([b"only"]).unwrap();
+        let proof = commitment_proof(b"only", &single.create_proof_by_index(0).unwrap()).unwrap();
+        assert!(verify_membership::<Sha256Only>(
+            &proof,
+            &spec,
+            &single.root().unwrap(),
+            LEAF_KEY,
+            b"only"
+        ));
+    }
+
+    #[test]
+    fn unexportable_proofs_are_rejected() {
+        let tree = Ics23Tree::from_leaves([b"a", b"b"]).unwrap();
+        let proof = tree.create_proof_by_index(0).unwrap();
+        assert_eq!(existence_proof(b"a", &proof).unwrap().path.len(), 3);
+
+        assert_eq!(
+            existence_proof(b"", &proof).unwrap_err(),
+            MerkleError::InvalidEncoding("ics23 cannot carry an empty leaf value")
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use digest::Digest;
+use std::{fmt::Debug, sync::OnceLock};
+
+use crate::{
+    error::MerkleError,
+    merkle::{IndexBinding, MerkleTree, Proof},
+};
+
+// a tree for write-heavy, read-light workloads. add and update only record the
+// raw leaf bytes; the first query hashes everything and builds the tree in one
+// pass, and that tree is cached until the next mutation. the cost is keeping the
+// leaf data itself around instead of just its N-byte hash.
+pub struct LazyMerkleTree<S: Digest, const N: usize, const ND: usize> {
+    leaves: Vec<Vec<u8>>,
+    index_binding: IndexBinding,
+    built: OnceLock<MerkleTree<S, N, ND>>,
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> Default for LazyMerkleTree<S, N, ND> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> LazyMerkleTree<S, N, ND> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_index_binding(IndexBinding::None)
+    }
+
+    #[must_use]
+    pub fn with_index_binding(index_binding: IndexBinding) -> Self {
+        Self {
+            leaves: vec![],
+            index_binding,
+            built: OnceLock::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn add(&mut self, data: &[u8]) -> Result<(), MerkleError> {
+        if self.leaves.len() >= MerkleTree::<S, N, ND>::MAX_LEAVES {
+            return Err(MerkleError::CapacityExceeded {
+                max: MerkleTree::<S, N, ND>::MAX_LEAVES,
+            });
+        }
+
+        self.leaves.push(data.to_vec());
+        self.built.take();
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, index: usize, data: &[u8]) -> Result<(), MerkleError> {
+        let len = self.leaves.len();
+        let leaf = self
+            .leaves
+            .get_mut(index)
+            .ok_or(MerkleError::LeafOutOfBounds { index, len })?;
+
+        *leaf = data.to_vec();
+        self.built.take();
+
+        Ok(())
+    }
+
+    // the materialized tree, built on first use after a mutation
+    pub fn tree(&self) -> &MerkleTree<S, N, ND> {
+        self.built.get_or_init(|| {
+            // add already enforces MAX_LEAVES, the only way building can fail
+            MerkleTree::from_bound_leaves(self.index_binding, &self.leaves).unwrap_or_default()
+        })
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.tree().root()
+    }
+
+    pub fn create_proof(&self, data: &[u8]) -> Option<Proof<S, N, ND>> {
+        self.tree().create_proof(data)
+    }
+
+    #[must_use]
+    pub fn into_tree(mut self) -> MerkleTree<S, N, ND> {
+        self.tree();
+        self.built.take().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::LazyMerkleTree;
+    use crate::merkle::{IndexBinding, MerkleTree};
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+    type Lazy = LazyMerkleTree<Sha256, 32, 64>;
+
+    #[test]
+    fn lazy_matches_eager() {
+        for binding in [IndexBinding::None, IndexBinding::Prefix] {
+            let mut eager = Tree::with_index_binding(binding);
+            let mut lazy = Lazy::with_index_binding(binding);
+            assert_eq!(lazy.root(), None);
+
+            for i in 0..13u8 {
+                eager.add(&[i]).unwrap();
+                lazy.add(&[i]).unwrap();
+
+                if i % 4 == 0 {
+                    eager.update(i as usize / 2, &[i, i]).unwrap();
+                    lazy.update(i as usize / 2, &[i, i]).unwrap();
+                }
+
+                assert_eq!(lazy.root(), eager.root());
+                assert!(*lazy.tree() == eager);
+
+                for leaf in [vec![i], vec![0], vec![2, 2]] {
+                    let (l, e) = (lazy.create_proof(&leaf), eager.create_proof(&leaf));
+                    assert_eq!(
+                        l.map(|p| Tree::proof_to_bytes(&p).unwrap()),
+                        e.map(|p| Tree::proof_to_bytes(&p).unwrap())
+                    );
+                }
+            }
+
+            assert!(lazy.update(13, &[0]).is_err());
+            assert!(lazy.into_tree() == eager);
+        }
+    }
+}
@@ -1 +1,38 @@
+pub mod bitcoin;
+pub mod bittorrent;
+pub mod codec;
+pub mod composite;
+#[cfg(feature = "ct")]
+pub mod ct;
+pub mod dynamic;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod flat;
+mod hex;
+#[cfg(feature = "ics23")]
+pub mod ics23;
+pub mod lazy;
 pub mod merkle;
+#[cfg(feature = "openzeppelin")]
+pub mod openzeppelin;
+#[cfg(feature = "python")]
+mod python;
+pub mod range;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod reference;
+pub mod rfc6962;
+#[cfg(feature = "rs-merkle")]
+pub mod rs_merkle;
+#[cfg(feature = "salted")]
+pub mod salted;
+pub mod set;
+pub mod sparse;
+#[cfg(feature = "ssz")]
+pub mod ssz;
+pub mod stream;
+pub mod sum;
+pub mod thex;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod xof;
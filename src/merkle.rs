@@ -1,26 +1,193 @@
-use digest::{Digest, FixedOutputReset};
-use std::{error::Error, fmt::Debug, marker::PhantomData};
+use digest::Digest;
+use std::{collections::BTreeSet, fmt::Debug, marker::PhantomData, ops::Range};
 
-pub struct MerkleTree<S: Digest + FixedOutputReset, const N: usize, const ND: usize> {
+use crate::{
+    composite::{Tagged, TreeHasher},
+    error::MerkleError,
+    flat, hex,
+};
+
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+#[cfg(feature = "keyed")]
+use digest::core_api::BlockSizeUser;
+#[cfg(feature = "keyed")]
+use hmac::{Mac, SimpleHmac};
+
+// the bytes a leaf's and an internal node's tag blocks are filled with, shared
+// by every tree type that hashes the way MerkleTree does
+pub(crate) const LEAF_TAG: u8 = 1;
+pub(crate) const NODE_TAG: u8 = 2;
+
+// (node index, new value) pairs for a pending path update
+type StagedNodes = Vec<(usize, Vec<u8>)>;
+
+// no panics on any input: the query functions (root*, len, path_indices,
+// create_proof*, prove_all) and every verify* function return None/false/an error
+// for empty trees, out-of-range indices and malformed proofs or roots. mutations
+// report MerkleError instead of panicking, except debug-build assertions that
+// fire when the tree's internal nodes no longer hash their children, which only
+// happens if nodes handed to from_nodes were inconsistent to begin with.
+//
+// H hashes the leaves and nodes of a tree in TagMode::Block, MerkleTree's own
+// tagged scheme unless another is named. it must output N-byte Vec<u8>s, as
+// nodes, proofs and the codecs all assume that width; hashers over field
+// elements or other outputs belong in a CompositeMerkleTree
+pub struct MerkleTree<S: Digest, const N: usize, const ND: usize, H = Tagged<S, N, ND>> {
     tree: Vec<Vec<u8>>,
-    _s: PhantomData<S>,
+    index_binding: IndexBinding,
+    tag_mode: TagMode,
+    // indices of the nodes add() has not filled in yet, or that from_nodes found
+    // unfilled. while any are left the tree refuses to report a root or hand out
+    // proofs. a node is never taken for one just for being all zeros
+    placeholders: BTreeSet<usize>,
+    // see MerkleTreeBuilder::strict
+    strict: bool,
+    #[cfg(feature = "keyed")]
+    key: Option<LeafKey>,
+    _s: PhantomData<fn() -> (S, H)>,
 }
 
-#[derive(Debug)]
-enum ProofElementDirection {
-    LEFT,
-    RIGHT,
+// the key of a tree built with MerkleTreeBuilder::hmac_key. the tree's own impl
+// knows nothing of the digest's block size, so the mac is captured as a function
+// pointer where it does. the key bytes are wiped when the tree is dropped
+#[cfg(feature = "keyed")]
+struct LeafKey {
+    key: zeroize::Zeroizing<Vec<u8>>,
+    mac: fn(&[u8], &[u8]) -> Vec<u8>,
 }
 
-pub struct ProofElement<S: Digest + FixedOutputReset, const N: usize, const ND: usize> {
-    hash: Vec<u8>,
-    direction: ProofElementDirection,
-    _s: PhantomData<S>,
+#[cfg(feature = "keyed")]
+impl LeafKey {
+    fn mac(&self, data: &[u8]) -> Vec<u8> {
+        (self.mac)(&self.key, data)
+    }
 }
 
-impl<S: Digest + FixedOutputReset, const N: usize, const ND: usize> std::fmt::Debug
-    for ProofElement<S, N, ND>
-{
+#[cfg(feature = "keyed")]
+fn hmac<S: Digest + BlockSizeUser>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <SimpleHmac<S> as Mac>::new_from_slice(key).expect("hmac takes keys of any length");
+    Mac::update(&mut mac, data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// whether a leaf's position is mixed into its hash. Prefix prepends the leaf
+// index as an 8-byte little-endian integer to the data before leaf hashing, so a
+// tagged leaf is H(0x01 * N || H(index_le_u64 || data)) and identical data at
+// different positions gets different leaf hashes. that changes every root
+// compared to None, and a verifier must know the index of each leaf. the byte
+// order is part of the format: little-endian, as the binding was specified, and
+// any other order would give every bound tree a different root
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexBinding {
+    #[default]
+    None,
+    Prefix,
+}
+
+// the domain separation between leaf and node hashes. Block hashes a leaf as
+// H(0x01 * N || H(data)) and a node as H(0x02 * N || H(H(left || right))), all
+// truncated to N. None drops the tags for interop with plain merkle trees: a
+// leaf is H(data) and a node H(left || right). without tags an internal node is
+// indistinguishable from a leaf over the 2N bytes of its children, so a proof
+// for that node passes as a proof for a leaf that was never added; this is the
+// second preimage attack rfc 6962 tags against. only use None where the other
+// side requires it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TagMode {
+    #[default]
+    Block,
+    None,
+}
+
+// the side of the path a proof element's hash sits on. Left means the element
+// is the left child and the running hash the right one, so the parent is
+// node(element, running); Right is the mirror image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofElementDirection {
+    Left,
+    Right,
+}
+
+pub struct ProofElement<S: Digest, const N: usize, const ND: usize> {
+    pub(crate) hash: [u8; N],
+    pub(crate) direction: ProofElementDirection,
+    _s: PhantomData<fn() -> S>,
+}
+
+// an inclusion proof, ordered from the leaf's sibling up to the root's child. it
+// is empty only for a single-leaf tree, where the leaf hash is the root itself.
+pub type Proof<S, const N: usize, const ND: usize> = Vec<ProofElement<S, N, ND>>;
+
+// a proof for the contiguous leaves start..end at once: the hashes of the
+// largest subtrees outside the range, left to right, which together with the
+// leaves rebuild the root. tree_size fixes the shape they are folded in
+pub struct MultiProof<S: Digest, const N: usize, const ND: usize> {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) tree_size: usize,
+    pub(crate) hashes: Vec<Vec<u8>>,
+    _s: PhantomData<fn() -> S>,
+}
+
+impl<S: Digest, const N: usize, const ND: usize> MultiProof<S, N, ND> {
+    #[must_use]
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    #[must_use]
+    pub fn tree_size(&self) -> usize {
+        self.tree_size
+    }
+
+    #[must_use]
+    pub fn hashes(&self) -> &[Vec<u8>] {
+        &self.hashes
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Root<const N: usize>([u8; N]);
+
+impl<const N: usize> From<[u8; N]> for Root<N> {
+    fn from(bytes: [u8; N]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl<const N: usize> Root<N> {
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for Root<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> std::fmt::Display for Root<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&hex::encode(&self.0))
+    }
+}
+
+impl<S: Digest, const N: usize, const ND: usize> Clone for ProofElement<S, N, ND> {
+    fn clone(&self) -> Self {
+        Self {
+            hash: self.hash,
+            direction: self.direction,
+            _s: PhantomData,
+        }
+    }
+}
+
+impl<S: Digest, const N: usize, const ND: usize> std::fmt::Debug for ProofElement<S, N, ND> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ProofElement")
             .field("hash", &self.hash)
@@ -29,288 +196,4295 @@ impl<S: Digest + FixedOutputReset, const N: usize, const ND: usize> std::fmt::De
     }
 }
 
-impl<S: Debug + Digest + FixedOutputReset, const N: usize, const ND: usize> Default
-    for MerkleTree<S, N, ND>
-{
-    fn default() -> Self {
-        Self::new()
+impl<S: Digest, const N: usize, const ND: usize> Clone for MultiProof<S, N, ND> {
+    fn clone(&self) -> Self {
+        Self {
+            start: self.start,
+            end: self.end,
+            tree_size: self.tree_size,
+            hashes: self.hashes.clone(),
+            _s: PhantomData,
+        }
     }
 }
 
-impl<S: Debug + Digest + FixedOutputReset, const N: usize, const ND: usize> MerkleTree<S, N, ND> {
-    const LEAF_TAG: u8 = 1;
-    const NODE_TAG: u8 = 2;
+impl<S: Digest, const N: usize, const ND: usize> std::fmt::Debug for MultiProof<S, N, ND> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiProof")
+            .field("start", &self.start)
+            .field("end", &self.end)
+            .field("tree_size", &self.tree_size)
+            .field("hashes", &self.hashes)
+            .finish()
+    }
+}
 
+impl<S: Digest, const N: usize, const ND: usize> ProofElement<S, N, ND> {
+    // for proofs assembled by hand; the array keeps the hash at the tree's width
     #[must_use]
-    pub fn new() -> Self {
-        assert!(N < 8 * <S as Digest>::output_size());
-        assert!(ND < 2 * 8 * <S as Digest>::output_size());
+    pub fn new(hash: [u8; N], direction: ProofElementDirection) -> Self {
+        Self::from_parts(hash, direction)
+    }
 
+    #[must_use]
+    pub fn hash(&self) -> &[u8; N] {
+        &self.hash
+    }
+
+    #[must_use]
+    pub fn direction(&self) -> ProofElementDirection {
+        self.direction
+    }
+
+    pub(crate) fn from_parts(hash: [u8; N], direction: ProofElementDirection) -> Self {
         Self {
-            tree: vec![],
+            hash,
+            direction,
             _s: PhantomData,
         }
     }
 
-    fn hash(data: &[u8]) -> Vec<u8> {
-        let mut strategy = S::new();
-        Digest::update(&mut strategy, data);
+    // for decoders and other sources of hashes of unchecked width
+    pub(crate) fn from_slice(
+        hash: &[u8],
+        direction: ProofElementDirection,
+    ) -> Result<Self, MerkleError> {
+        let hash = hash
+            .try_into()
+            .map_err(|_| MerkleError::InvalidHashLength {
+                expected: N,
+                actual: hash.len(),
+            })?;
 
-        let out = strategy.finalize_reset();
+        Ok(Self::from_parts(hash, direction))
+    }
+}
 
-        out[..N].to_vec()
+// fuzzing inputs. trees are grown through add, so they are always well-formed;
+// fuzz the decoders to reach hashes of the wrong width.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for IndexBinding {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(if u.arbitrary()? {
+            Self::Prefix
+        } else {
+            Self::None
+        })
     }
+}
 
-    fn concat_hash(first: &[u8], second: &[u8]) -> Vec<u8> {
-        let mut data: [u8; ND] = [0; ND];
-        data[0..N].copy_from_slice(first);
-        data[N..ND].copy_from_slice(second);
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for ProofElementDirection {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(if u.arbitrary()? {
+            Self::Right
+        } else {
+            Self::Left
+        })
+    }
+}
 
-        Self::hash(&data)
+#[cfg(feature = "arbitrary")]
+impl<'a, S: Digest, const N: usize, const ND: usize> arbitrary::Arbitrary<'a>
+    for ProofElement<S, N, ND>
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self::from_parts(u.arbitrary()?, u.arbitrary()?))
     }
+}
 
-    fn tag_hash(tag: u8, data: &[u8]) -> Vec<u8> {
-        let tag_block: Vec<u8> = vec![tag; N];
-        let hashed_data = Self::hash(data);
+#[cfg(feature = "arbitrary")]
+impl<
+    'a,
+    S: Debug + Digest,
+    const N: usize,
+    const ND: usize,
+    H: TreeHasher<Output = Vec<u8>> + Default,
+> arbitrary::Arbitrary<'a> for MerkleTree<S, N, ND, H>
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut tree = Self::with_index_binding(u.arbitrary()?);
+
+        for leaf in u.arbitrary_iter::<&[u8]>()? {
+            tree.add(leaf?)
+                .map_err(|_| arbitrary::Error::IncorrectFormat)?;
+        }
 
-        Self::concat_hash(&tag_block, &hashed_data)
+        Ok(tree)
     }
+}
 
-    // all inlined functions related to flat binary trees are from this article:
-    // https://mmapped.blog/posts/22-flat-in-order-trees
+// zeroize() wipes every node in place but keeps the layout, so the tree is
+// still indexable but its root is all zeros and proofs against it are useless.
+// dropping the tree wipes and frees the nodes.
+#[cfg(feature = "zeroize")]
+impl<S: Digest, const N: usize, const ND: usize, H> Zeroize for MerkleTree<S, N, ND, H> {
+    fn zeroize(&mut self) {
+        for node in &mut self.tree {
+            node.as_mut_slice().zeroize();
+        }
+    }
+}
 
-    #[inline]
-    fn last_set_bit(n: usize) -> usize {
-        n - ((n - 1) & n)
+#[cfg(feature = "zeroize")]
+impl<S: Digest, const N: usize, const ND: usize, H> Drop for MerkleTree<S, N, ND, H> {
+    fn drop(&mut self) {
+        self.tree.zeroize();
     }
+}
 
-    #[inline]
-    fn last_zero_bit(n: usize) -> usize {
-        Self::last_set_bit(n + 1)
+#[cfg(feature = "zeroize")]
+impl<S: Digest, const N: usize, const ND: usize, H> ZeroizeOnDrop for MerkleTree<S, N, ND, H> {}
+
+#[cfg(feature = "zeroize")]
+impl<S: Digest, const N: usize, const ND: usize> Zeroize for ProofElement<S, N, ND> {
+    fn zeroize(&mut self) {
+        self.hash.as_mut_slice().zeroize();
     }
+}
 
-    #[inline]
-    fn pbt_parent(n: usize) -> usize {
-        (Self::last_zero_bit(n) | n) & !(Self::last_zero_bit(n) << 1)
+#[cfg(feature = "zeroize")]
+impl<S: Digest, const N: usize, const ND: usize> Drop for ProofElement<S, N, ND> {
+    fn drop(&mut self) {
+        self.hash.zeroize();
     }
+}
 
-    #[inline]
-    fn pbt_left_child(n: usize) -> Option<usize> {
-        if n & 1 == 1 {
-            Some(n & !(Self::last_zero_bit(n) >> 1))
-        } else {
-            None
-        }
+#[cfg(feature = "zeroize")]
+impl<S: Digest, const N: usize, const ND: usize> ZeroizeOnDrop for ProofElement<S, N, ND> {}
+
+// roots are compared first so unequal trees are usually rejected after a single
+// N-byte comparison. only when the roots match are all nodes compared, which
+// catches trees that share a root but not a structure (a hash collision).
+impl<S: Debug + Digest, const N: usize, const ND: usize, H: TreeHasher<Output = Vec<u8>> + Default>
+    PartialEq for MerkleTree<S, N, ND, H>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.root_ref() == other.root_ref()
+            && self.index_binding == other.index_binding
+            && self.tag_mode == other.tag_mode
+            && self.same_key(other)
+            && self.tree == other.tree
     }
+}
 
-    #[inline]
-    fn pbt_right_child(n: usize) -> Option<usize> {
-        if n & 1 == 1 {
-            Some((n | Self::last_zero_bit(n)) & !(Self::last_zero_bit(n) >> 1))
-        } else {
-            None
-        }
+impl<S: Debug + Digest, const N: usize, const ND: usize, H: TreeHasher<Output = Vec<u8>> + Default>
+    Eq for MerkleTree<S, N, ND, H>
+{
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize, H: TreeHasher<Output = Vec<u8>> + Default>
+    Default for MerkleTree<S, N, ND, H>
+{
+    fn default() -> Self {
+        Self::new()
     }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize, H: TreeHasher<Output = Vec<u8>> + Default>
+    MerkleTree<S, N, ND, H>
+{
+    pub(crate) const LEAF_TAG: u8 = LEAF_TAG;
+    pub(crate) const NODE_TAG: u8 = NODE_TAG;
+
+    // the most leaves a tree can hold: 2^(usize::BITS - 2). past this the flat
+    // layout's node indices (2 * leaves - 1, rounded up to a power of two) would
+    // no longer fit in a usize. that is 2^30 leaves on 32-bit targets.
+    pub const MAX_LEAVES: usize = flat::MAX_LEAVES;
 
-    #[inline]
-    fn lpbt_root(size: usize) -> usize {
-        ((size + 1).next_power_of_two() - 1) >> 1
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_index_binding(IndexBinding::None)
     }
 
-    #[inline]
-    fn pbt_leftmost_leaf(n: usize) -> usize {
-        n & (n + 1)
+    #[must_use]
+    pub fn builder() -> MerkleTreeBuilder<S, N, ND, H> {
+        MerkleTreeBuilder::new()
     }
 
-    #[inline]
-    fn lpbt_parent(n: usize, size: usize) -> Option<usize> {
-        if n == Self::lpbt_root(size) {
-            None
-        } else {
-            let p = Self::pbt_parent(n);
-            Some(if p < size {
-                p
-            } else {
-                Self::pbt_leftmost_leaf(n) - 1
-            })
+    #[must_use]
+    pub fn with_index_binding(index_binding: IndexBinding) -> Self {
+        // hash() truncates the digest to N bytes and concat_hash packs two nodes
+        // into ND bytes, so anything else would panic on first use instead. zero
+        // width hashes would make every node, and so every root, the empty string
+        assert!(
+            N > 0,
+            "N must be at least 1, zero-width hashes commit to nothing"
+        );
+        assert!(
+            N <= <S as Digest>::output_size(),
+            "N must not exceed the digest output size"
+        );
+        assert!(ND == 2 * N, "ND must be exactly 2 * N");
+
+        Self {
+            tree: vec![],
+            index_binding,
+            tag_mode: TagMode::Block,
+            placeholders: BTreeSet::new(),
+            strict: false,
+            #[cfg(feature = "keyed")]
+            key: None,
+            _s: PhantomData,
         }
     }
 
-    #[inline]
-    fn lpbt_right_child(n: usize, size: usize) -> Option<usize> {
-        if n & 1 == 1 {
-            let r = Self::pbt_right_child(n)?;
-
-            Some(if r < size {
-                r
-            } else {
-                n + 1 + Self::lpbt_root(size - n - 1)
-            })
-        } else {
-            None
+    /// An empty tree, like `new()`, without the parameter checks, so it can be
+    /// built in a const context.
+    ///
+    /// # Safety
+    ///
+    /// The caller must uphold what `new()` asserts: `0 < N`, `N` no larger than
+    /// the digest output size, and `ND == 2 * N`. Every hashing method relies on
+    /// those; with them broken the tree panics on first use or, for `N = 0`,
+    /// commits to nothing.
+    #[must_use]
+    pub const unsafe fn new_unchecked() -> Self {
+        Self {
+            tree: Vec::new(),
+            index_binding: IndexBinding::None,
+            tag_mode: TagMode::Block,
+            placeholders: BTreeSet::new(),
+            strict: false,
+            #[cfg(feature = "keyed")]
+            key: None,
+            _s: PhantomData,
         }
     }
 
-    fn lpbt_set(&mut self, leaf_pos: usize, data: &[u8]) -> Result<(), Box<dyn Error>> {
-        if leaf_pos > (self.tree.len() / 2) {
-            return Err("Leaf position out of bounds".into());
+    pub fn from_nodes(nodes: Vec<Vec<u8>>) -> Result<Self, MerkleError> {
+        Self::from_nodes_with(MerkleTreeBuilder::new(), nodes)
+    }
+
+    // from_nodes for a tree that was not built with the defaults. the nodes are
+    // taken as they are, so config must be the one they were hashed under: its
+    // binding, tag mode and key, if any, only shape what later adds and proofs do
+    pub fn from_nodes_with(
+        config: MerkleTreeBuilder<S, N, ND, H>,
+        nodes: Vec<Vec<u8>>,
+    ) -> Result<Self, MerkleError> {
+        if nodes.len().is_multiple_of(2) && !nodes.is_empty() {
+            return Err(MerkleError::InvalidNodeCount(nodes.len()));
         }
 
-        let pos = leaf_pos * 2;
-        self.tree[pos].copy_from_slice(data);
+        Self::ensure_capacity(nodes.len().div_ceil(2).saturating_sub(1))?;
 
-        let mut parent = Self::lpbt_parent(pos, self.tree.len());
-        if parent.is_none() {
-            return Err("structural error".into());
+        for node in &nodes {
+            Self::check_len(node)?;
         }
 
-        while let Some(parent_pos) = parent {
-            // update as hash of children
-            if let (Some(left), Some(right)) = (
-                Self::pbt_left_child(parent_pos),
-                Self::lpbt_right_child(parent_pos, self.tree.len()),
-            ) {
-                let hash = {
-                    let hashed_data = Self::concat_hash(&self.tree[left], &self.tree[right]);
-                    Self::tag_hash(Self::NODE_TAG, &hashed_data)
-                };
+        let mut tree = config.build();
+        tree.tree = nodes;
 
-                self.tree[parent_pos].copy_from_slice(&hash[..]);
-            } else {
-                return Err("could not get children".into());
-            }
+        // an all-zero node is only unfilled where it cannot be a real hash: an
+        // internal node that does not hash its children, or a leaf right under
+        // one, as a snapshot taken in the middle of an add has them. zero hashes
+        // are likely enough at small N that a clean tree may well hold some
+        let size = tree.tree.len();
+        let internal: BTreeSet<usize> = (1..size)
+            .step_by(2)
+            .filter(|&idx| Self::is_placeholder(&tree.tree[idx]) && !tree.node_is_consistent(idx))
+            .collect();
+        let leaves: Vec<usize> = (0..size)
+            .step_by(2)
+            .filter(|&idx| {
+                Self::is_placeholder(&tree.tree[idx])
+                    && flat::lpbt_parent(idx, size).is_some_and(|p| internal.contains(&p))
+            })
+            .collect();
+        tree.placeholders = internal;
+        tree.placeholders.extend(leaves);
 
-            parent = Self::lpbt_parent(parent_pos, self.tree.len());
-        }
+        Ok(tree)
+    }
 
-        Ok(())
+    pub fn from_leaves<I>(leaves: I) -> Result<Self, MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        Self::from_bound_leaves(IndexBinding::None, leaves)
     }
 
-    pub fn add(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
-        if self.tree.is_empty() {
-            self.tree.push(Self::tag_hash(Self::LEAF_TAG, data));
-        } else {
-            self.tree.push(vec![0; N]);
-            self.tree.push(vec![0; N]);
+    pub(crate) fn from_bound_leaves<I>(
+        index_binding: IndexBinding,
+        leaves: I,
+    ) -> Result<Self, MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut tree = Self::with_index_binding(index_binding);
 
-            self.lpbt_set(
-                self.tree.len() / 2,
-                Self::tag_hash(Self::LEAF_TAG, data).as_slice(),
-            )?;
+        for (i, leaf) in leaves.into_iter().enumerate() {
+            Self::ensure_capacity(i)?;
+
+            if i > 0 {
+                tree.tree.push(vec![0; N]);
+            }
+            tree.tree.push(tree.leaf_hash(i, leaf.as_ref()));
         }
 
-        Ok(())
+        tree.rebuild();
+
+        Ok(tree)
     }
 
-    #[must_use]
-    pub fn root(&self) -> Option<Vec<u8>> {
-        self.tree.get(Self::lpbt_root(self.tree.len())).cloned()
+    pub fn build_with_proofs<I>(leaves: I) -> Result<(Self, Vec<Proof<S, N, ND>>), MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let tree = Self::from_leaves(leaves)?;
+        let proofs = tree.prove_all();
+
+        Ok((tree, proofs))
     }
 
-    fn create_proof_route(
-        &self,
-        idx: usize,
-        hash: &[u8],
-        route: &mut Vec<ProofElement<S, N, ND>>,
-    ) -> bool {
-        if self.tree[idx] == hash {
-            return true;
-        }
+    // entry i is the root over the first i + 1 leaves. each append only rehashes
+    // one path, so the whole sequence costs O(n log n) rather than a rebuild per prefix
+    pub fn roots_by_prefix<I>(leaves: I) -> Result<Vec<Vec<u8>>, MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut tree = Self::new();
 
-        if let (Some(left), Some(right)) = (
-            Self::pbt_left_child(idx),
-            Self::lpbt_right_child(idx, self.tree.len()),
-        ) {
-            {
-                route.push(ProofElement {
-                    hash: self.tree[right].clone(),
-                    direction: ProofElementDirection::RIGHT,
-                    _s: PhantomData,
-                });
+        leaves
+            .into_iter()
+            .map(|leaf| {
+                tree.add(leaf.as_ref())?;
+                tree.root()
+                    .ok_or(MerkleError::Structural("non-empty tree has no root"))
+            })
+            .collect()
+    }
 
-                let new_sz = route.len();
+    // recomputes every internal node a level at a time, as CompositeMerkleTree
+    // does: an odd index's height is its count of trailing ones and its children
+    // sit lower, so going up by height always finds them done. each level goes to
+    // H::level in one call, which a batched hasher hashes together
+    fn rebuild(&mut self) {
+        let size = self.tree.len();
+        let mut height = 1;
+        while let Some(first) = (1usize << height).checked_sub(1).filter(|&f| f < size) {
+            let nodes = (first..size)
+                .step_by(2 << height)
+                .filter_map(|node| Some((node, self.children(node)?)))
+                .collect::<Vec<_>>();
+            let hashes = {
+                let pairs = nodes
+                    .iter()
+                    .map(|&(_, (left, right))| (&self.tree[left], &self.tree[right]))
+                    .collect::<Vec<_>>();
 
-                if self.create_proof_route(left, hash, route) {
-                    return true;
+                match self.tag_mode {
+                    TagMode::Block => H::default().level(&pairs),
+                    TagMode::None => pairs
+                        .iter()
+                        .map(|(left, right)| Self::concat_hash(left, right))
+                        .collect(),
                 }
+            };
 
-                route.remove(new_sz - 1);
+            // a level that returns too few hashes leaves the rest stale, for
+            // verify_integrity to find
+            debug_assert_eq!(hashes.len(), nodes.len(), "level returned a short level");
+            for ((node, _), hash) in nodes.into_iter().zip(hashes) {
+                self.tree[node] = hash;
             }
+            height += 1;
+        }
+    }
+
+    #[must_use]
+    pub fn as_nodes(&self) -> &[Vec<u8>] {
+        &self.tree
+    }
 
-            {
-                route.push(ProofElement {
-                    hash: self.tree[left].clone(),
-                    direction: ProofElementDirection::LEFT,
-                    _s: PhantomData,
-                });
+    // in the flat in-order layout leaves sit at even node indices and internal
+    // nodes at odd ones
+    #[must_use]
+    pub fn is_leaf(index: usize) -> bool {
+        index & 1 == 0
+    }
 
-                let new_sz = route.len();
+    #[must_use]
+    pub fn is_internal(index: usize) -> bool {
+        !Self::is_leaf(index)
+    }
 
-                if self.create_proof_route(right, hash, route) {
-                    return true;
-                }
+    #[must_use]
+    pub fn into_nodes(mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.tree)
+    }
 
-                route.remove(new_sz - 1);
-            }
-        }
+    // the stored hash of the leaf at index, not the data it was built from
+    #[must_use]
+    pub fn get_leaf(&self, index: usize) -> Option<&[u8]> {
+        self.tree.get(index.checked_mul(2)?).map(Vec::as_slice)
+    }
+
+    #[must_use]
+    pub fn index_binding(&self) -> IndexBinding {
+        self.index_binding
+    }
+
+    #[must_use]
+    pub fn tag_mode(&self) -> TagMode {
+        self.tag_mode
+    }
+
+    #[must_use]
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    // whether leaves are hashed under an hmac key, see MerkleTreeBuilder::hmac_key
+    #[cfg(feature = "keyed")]
+    #[must_use]
+    pub fn is_keyed(&self) -> bool {
+        self.key.is_some()
+    }
 
+    #[cfg(not(feature = "keyed"))]
+    #[must_use]
+    pub fn is_keyed(&self) -> bool {
         false
     }
 
-    pub fn create_proof(&self, data: &[u8]) -> Option<Vec<ProofElement<S, N, ND>>> {
-        let hash = Self::tag_hash(Self::LEAF_TAG, data);
-        let mut route = vec![];
+    #[cfg(feature = "keyed")]
+    fn same_key(&self, other: &Self) -> bool {
+        self.key.as_ref().map(|k| k.key.as_slice()) == other.key.as_ref().map(|k| k.key.as_slice())
+    }
 
-        let root = Self::lpbt_root(self.tree.len());
-        if self.create_proof_route(root, hash.as_slice(), &mut route) {
-            route.reverse();
-            Some(route)
-        } else {
-            None
-        }
+    #[cfg(not(feature = "keyed"))]
+    fn same_key(&self, _: &Self) -> bool {
+        true
     }
 
-    pub fn verify_proof(data: &[u8], proof: &Vec<ProofElement<S, N, ND>>, to_match: &[u8]) -> bool {
-        let hash = Self::tag_hash(Self::LEAF_TAG, data);
-        let generated = proof.iter().fold(hash, |acc, e| {
-            Self::tag_hash(
-                Self::NODE_TAG,
-                &match e.direction {
-                    ProofElementDirection::LEFT => {
-                        Self::concat_hash(e.hash.as_slice(), acc.as_slice())
-                    }
-                    ProofElementDirection::RIGHT => {
-                        Self::concat_hash(acc.as_slice(), e.hash.as_slice())
-                    }
-                },
-            )
-        });
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tree.len().div_ceil(2)
+    }
 
-        generated.iter().eq(to_match)
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use sha2::Sha256;
+    // the birthday bound on N-byte nodes: by around 2^(4 * N) leaves two nodes
+    // are likely to collide, and a proof through one then passes for the other.
+    // capped at MAX_LEAVES, which every N from 16 bytes up reaches
+    #[must_use]
+    pub fn recommended_max_leaves() -> usize {
+        u32::try_from(4 * N)
+            .ok()
+            .and_then(|bits| 1usize.checked_shl(bits))
+            .map_or(Self::MAX_LEAVES, |max| max.min(Self::MAX_LEAVES))
+    }
 
-    use super::MerkleTree;
+    // past half of recommended_max_leaves: time to move to a wider N
+    #[must_use]
+    pub fn is_near_capacity(&self) -> bool {
+        self.len() > Self::recommended_max_leaves() / 2
+    }
 
-    type Tree = MerkleTree<Sha256, 32, 64>;
+    // the odd-indexed slots of the flat layout: len() - 1 for a non-empty tree
+    #[must_use]
+    pub fn internal_node_count(&self) -> usize {
+        self.tree.len() - self.len()
+    }
 
-    #[test]
-    fn add() {
-        let mut tree = Tree::new();
+    // bytes of hash data held by the nodes, N per node. the per-node Vec headers
+    // and any spare capacity are not counted
+    #[must_use]
+    pub fn size_bytes(&self) -> usize {
+        self.tree.len() * N
+    }
 
-        assert!(tree.add(&[0x01]).is_ok());
-        assert!(tree.add(&[0x02]).is_ok());
-        assert!(tree.add(&[0x03]).is_ok());
-        assert!(tree.add(&[0x04]).is_ok());
-        assert!(tree.add(&[0x05]).is_ok());
+    // size_bytes plus what the storage costs around it: the tree itself, a Vec
+    // header per node and spare capacity in the outer and inner vectors. an
+    // estimate, since the allocator's own bookkeeping is not visible from here
+    #[must_use]
+    pub fn memory_footprint(&self) -> usize {
+        size_of::<Self>()
+            + self.tree.capacity() * size_of::<Vec<u8>>()
+            + self.tree.iter().map(Vec::capacity).sum::<usize>()
+    }
 
-        let root = tree.root().unwrap();
-        let proof = tree.create_proof(&[0x04]).unwrap();
+    #[must_use]
+    pub fn has_leaf(&self, index: usize) -> bool {
+        index < self.len()
+    }
 
-        assert!(Tree::verify_proof(&[0x04], &proof, &root));
+    fn hash(data: &[u8]) -> Vec<u8> {
+        let mut strategy = S::new();
+        Digest::update(&mut strategy, data);
+
+        #[allow(unused_mut)]
+        let mut out = strategy.finalize();
+        let hash = out[..N].to_vec();
+
+        #[cfg(feature = "zeroize")]
+        out.as_mut_slice().zeroize();
+
+        hash
+    }
+
+    fn concat_hash(first: &[u8], second: &[u8]) -> Vec<u8> {
+        let mut data: [u8; ND] = [0; ND];
+        data[0..N].copy_from_slice(first);
+        data[N..ND].copy_from_slice(second);
+
+        let hash = Self::hash(&data);
+
+        #[cfg(feature = "zeroize")]
+        data.zeroize();
+
+        hash
+    }
+
+    pub(crate) fn tag_hash(tag: u8, data: &[u8]) -> Vec<u8> {
+        let tag_block: Vec<u8> = vec![tag; N];
+        #[allow(unused_mut)]
+        let mut hashed_data = Self::hash(data);
+
+        let hash = Self::concat_hash(&tag_block, &hashed_data);
+
+        #[cfg(feature = "zeroize")]
+        hashed_data.zeroize();
+
+        hash
+    }
+
+    pub(crate) fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+        Self::tag_hash(Self::NODE_TAG, &Self::concat_hash(left, right))
+    }
+
+    // tagged hashing goes through H, untagged hashing is always plain S
+    fn mode_leaf_hash(tag_mode: TagMode, data: &[u8]) -> Vec<u8> {
+        match tag_mode {
+            TagMode::Block => H::default().leaf(data),
+            TagMode::None => Self::hash(data),
+        }
+    }
+
+    fn mode_node_hash(tag_mode: TagMode, left: &[u8], right: &[u8]) -> Vec<u8> {
+        match tag_mode {
+            TagMode::Block => H::default().node(&left.to_vec(), &right.to_vec()),
+            TagMode::None => Self::concat_hash(left, right),
+        }
+    }
+
+    // a keyed leaf is H(0x01 * N || HMAC(key, data)), the mac standing in for the
+    // inner H(data), or the bare mac without tags. that is S's tagged form whatever
+    // the tree's hasher, which has no say in the mac. nodes are hashed as usual:
+    // the key already makes every leaf, and so every root, unpredictable without it
+    #[cfg(feature = "keyed")]
+    fn keyed_leaf_hash(tag_mode: TagMode, mac: Vec<u8>) -> Vec<u8> {
+        let mac = zeroize::Zeroizing::new(mac);
+        match tag_mode {
+            TagMode::Block => Self::concat_hash(&[Self::LEAF_TAG; N], &mac[..N]),
+            TagMode::None => mac[..N].to_vec(),
+        }
+    }
+
+    fn leaf_hash(&self, index: usize, data: &[u8]) -> Vec<u8> {
+        Self::bind_index(self.index_binding, index, data, |d| {
+            self.unbound_leaf_hash(d)
+        })
+    }
+
+    // the leaf hash of data before any index binding, under the tree's key if any
+    fn unbound_leaf_hash(&self, data: &[u8]) -> Vec<u8> {
+        #[cfg(feature = "keyed")]
+        if let Some(key) = &self.key {
+            return Self::keyed_leaf_hash(self.tag_mode, key.mac(data));
+        }
+
+        Self::mode_leaf_hash(self.tag_mode, data)
+    }
+
+    pub(crate) fn bound_leaf_hash(
+        index_binding: IndexBinding,
+        tag_mode: TagMode,
+        index: usize,
+        data: &[u8],
+    ) -> Vec<u8> {
+        Self::bind_index(index_binding, index, data, |d| {
+            Self::mode_leaf_hash(tag_mode, d)
+        })
+    }
+
+    fn bind_index(
+        index_binding: IndexBinding,
+        index: usize,
+        data: &[u8],
+        leaf_hash: impl FnOnce(&[u8]) -> Vec<u8>,
+    ) -> Vec<u8> {
+        match index_binding {
+            IndexBinding::None => leaf_hash(data),
+            IndexBinding::Prefix => {
+                let mut bound = Vec::with_capacity(8 + data.len());
+                bound.extend_from_slice(&(index as u64).to_le_bytes());
+                bound.extend_from_slice(data);
+
+                let hash = leaf_hash(&bound);
+
+                #[cfg(feature = "zeroize")]
+                bound.zeroize();
+
+                hash
+            }
+        }
+    }
+
+    fn root_index(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            flat::lpbt_root(self.tree.len())
+        }
+    }
+
+    fn ensure_capacity(len: usize) -> Result<(), MerkleError> {
+        if len < Self::MAX_LEAVES {
+            Ok(())
+        } else {
+            Err(MerkleError::CapacityExceeded {
+                max: Self::MAX_LEAVES,
+            })
+        }
+    }
+
+    // computes every node on the path from the leaf to the root without touching
+    // the tree, so a failure part way up leaves nothing half-written
+    fn lpbt_stage(&self, leaf_pos: usize, data: &[u8]) -> Result<StagedNodes, MerkleError> {
+        if leaf_pos > (self.tree.len() / 2) {
+            return Err(MerkleError::LeafOutOfBounds {
+                index: leaf_pos,
+                len: self.len(),
+            });
+        }
+
+        Self::check_len(data)?;
+
+        let pos = leaf_pos * 2;
+        let mut staged = vec![(pos, data.to_vec())];
+
+        let mut parent = flat::lpbt_parent(pos, self.tree.len());
+        if parent.is_none() && self.tree.len() > 1 {
+            return Err(MerkleError::Structural("leaf has no parent"));
+        }
+
+        while let Some(parent_pos) = parent {
+            // update as hash of children, reading the freshly staged child on the path
+            if let Some((left, right)) = self.children(parent_pos) {
+                let node = |idx: usize| match staged.last() {
+                    Some((staged_idx, value)) if *staged_idx == idx => Ok(value.as_slice()),
+                    _ => self.node(idx),
+                };
+
+                let (left_node, right_node) = (node(left)?, node(right)?);
+                Self::check_len(left_node)?;
+                Self::check_len(right_node)?;
+
+                // the sibling off the path is trusted as-is, so it had better be sound
+                debug_assert!(
+                    self.node_is_consistent(if staged.last().is_some_and(|(i, _)| *i == left) {
+                        right
+                    } else {
+                        left
+                    }),
+                    "sibling node is not the hash of its children"
+                );
+
+                let hash = Self::mode_node_hash(self.tag_mode, left_node, right_node);
+                staged.push((parent_pos, hash));
+            } else {
+                return Err(MerkleError::Structural("could not get children"));
+            }
+
+            parent = flat::lpbt_parent(parent_pos, self.tree.len());
+        }
+
+        Ok(staged)
+    }
+
+    fn check_leaf_data(&self, data: &[u8]) -> Result<(), MerkleError> {
+        if self.strict && data.is_empty() {
+            Err(MerkleError::EmptyLeaf)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_len(node: &[u8]) -> Result<(), MerkleError> {
+        if node.len() == N {
+            Ok(())
+        } else {
+            Err(MerkleError::InvalidHashLength {
+                expected: N,
+                actual: node.len(),
+            })
+        }
+    }
+
+    fn node(&self, idx: usize) -> Result<&[u8], MerkleError> {
+        self.tree
+            .get(idx)
+            .map(Vec::as_slice)
+            .ok_or(MerkleError::Structural("node index out of range"))
+    }
+
+    // child indices of an internal node, provided both exist in the tree
+    fn children(&self, idx: usize) -> Option<(usize, usize)> {
+        let left = flat::pbt_left_child(idx)?;
+        let right = flat::lpbt_right_child(idx, self.tree.len())?;
+
+        (right < self.tree.len()).then_some((left, right))
+    }
+
+    // leaves are trivially consistent; internal nodes must hash their children
+    fn node_is_consistent(&self, idx: usize) -> bool {
+        match self.children(idx) {
+            Some((left, right)) => match (self.node(idx), self.node(left), self.node(right)) {
+                (Ok(node), Ok(left), Ok(right)) => {
+                    left.len() == N
+                        && right.len() == N
+                        && node == Self::mode_node_hash(self.tag_mode, left, right)
+                }
+                _ => false,
+            },
+            None => true,
+        }
+    }
+
+    fn lpbt_set(&mut self, leaf_pos: usize, data: &[u8]) -> Result<(), MerkleError> {
+        let staged = self.lpbt_stage(leaf_pos, data)?;
+
+        if staged.iter().any(|(idx, _)| *idx >= self.tree.len()) {
+            return Err(MerkleError::Structural("node index out of range"));
+        }
+
+        for (idx, value) in staged {
+            self.tree[idx] = value;
+            debug_assert!(
+                self.node_is_consistent(idx),
+                "updated node is not the hash of its children"
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn add(&mut self, data: &[u8]) -> Result<(), MerkleError> {
+        self.ensure_clean()?;
+        self.check_leaf_data(data)?;
+        Self::ensure_capacity(self.len())?;
+
+        let leaf = self.leaf_hash(self.len(), data);
+
+        if self.tree.is_empty() {
+            self.tree.push(leaf);
+        } else {
+            let size = self.tree.len();
+
+            self.tree.push(vec![0; N]);
+            self.tree.push(vec![0; N]);
+            self.placeholders.extend([size, size + 1]);
+
+            // lpbt_set is all-or-nothing, so dropping the placeholders restores the tree
+            let res = self.lpbt_set(self.tree.len() / 2, leaf.as_slice());
+            self.placeholders.clear();
+
+            if let Err(e) = res {
+                self.tree.truncate(size);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn update(&mut self, index: usize, data: &[u8]) -> Result<(), MerkleError> {
+        self.ensure_clean()?;
+
+        if index >= self.len() {
+            return Err(MerkleError::LeafOutOfBounds {
+                index,
+                len: self.len(),
+            });
+        }
+
+        self.check_leaf_data(data)?;
+        let leaf = self.leaf_hash(index, data);
+        self.lpbt_set(index, leaf.as_slice())
+    }
+
+    // drops every leaf from len onwards. nodes over a complete left subtree keep
+    // their value in the flat layout, so only the path from the new last leaf
+    // needs rehashing
+    pub fn truncate(&mut self, len: usize) -> Result<(), MerkleError> {
+        self.ensure_clean()?;
+
+        if len >= self.len() {
+            return Ok(());
+        }
+
+        if len == 0 {
+            self.tree.clear();
+            return Ok(());
+        }
+
+        self.tree.truncate(len * 2 - 1);
+
+        let last = self.node(len * 2 - 2)?.to_vec();
+        self.lpbt_set(len - 1, &last)
+    }
+
+    // self's leaves followed by other's, e.g. when merging shards. the stored leaf
+    // hashes are reused as they are, so both trees must hash leaves the same way,
+    // and index-bound leaves can only be appended to an empty tree: other's are
+    // bound to the positions they would leave. internal nodes are rebuilt over
+    // the combined leaves
+    pub fn concat(mut self, other: Self) -> Result<Self, MerkleError> {
+        self.ensure_clean()?;
+        other.ensure_clean()?;
+
+        if self.tag_mode != other.tag_mode
+            || self.index_binding != other.index_binding
+            || !self.same_key(&other)
+        {
+            return Err(MerkleError::Structural(
+                "trees hash their leaves differently",
+            ));
+        }
+        if self.index_binding == IndexBinding::Prefix && !self.is_empty() && !other.is_empty() {
+            return Err(MerkleError::Structural(
+                "index-bound leaves cannot change position",
+            ));
+        }
+        if let Some(last) = (self.len() + other.len()).checked_sub(1) {
+            Self::ensure_capacity(last)?;
+        }
+
+        for leaf in other.tree.iter().step_by(2) {
+            if !self.tree.is_empty() {
+                self.tree.push(vec![0; N]);
+            }
+            self.tree.push(leaf.clone());
+        }
+
+        self.rebuild();
+
+        Ok(self)
+    }
+
+    // full audit of the flat layout: every node must be N bytes wide and every
+    // internal node the hash of its children. O(n), meant for untrusted input and tests
+    pub fn verify_integrity(&self) -> Result<(), MerkleError> {
+        self.ensure_clean()?;
+
+        if self.tree.len().is_multiple_of(2) && !self.tree.is_empty() {
+            return Err(MerkleError::InvalidNodeCount(self.tree.len()));
+        }
+
+        for node in &self.tree {
+            Self::check_len(node)?;
+        }
+
+        match (0..self.tree.len()).find(|&idx| !self.node_is_consistent(idx)) {
+            Some(index) => Err(MerkleError::IntegrityViolation { index }),
+            None => Ok(()),
+        }
+    }
+
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        !self.placeholders.is_empty()
+    }
+
+    // brings a dirty tree back into shape. a trailing placeholder leaf is what an
+    // interrupted add leaves behind and its data is gone, so that leaf is dropped;
+    // every internal node is then recomputed from the leaves. a placeholder
+    // anywhere else among the leaves cannot be recovered and is reported instead
+    pub fn repair(&mut self) -> Result<(), MerkleError> {
+        let last = self.tree.len().checked_sub(1);
+        if last.is_some_and(|idx| self.placeholders.contains(&idx)) {
+            self.tree.truncate(self.tree.len().saturating_sub(2));
+            let size = self.tree.len();
+            self.placeholders.retain(|&idx| idx < size);
+        }
+
+        if let Some(&index) = self.placeholders.iter().find(|&&idx| Self::is_leaf(idx)) {
+            return Err(MerkleError::IntegrityViolation { index });
+        }
+
+        self.rebuild();
+        self.placeholders.clear();
+
+        self.verify_integrity()
+    }
+
+    // recomputes every internal node from the stored leaf hashes and leaves the
+    // leaves alone, e.g. after the internal nodes came from an untrusted source or
+    // a leaf was swapped in place; verify_integrity passes afterwards. unlike
+    // repair it drops nothing, so a placeholder leaf keeps the tree dirty
+    pub fn recompute(&mut self) {
+        self.rebuild();
+
+        self.placeholders.retain(|&idx| Self::is_leaf(idx));
+    }
+
+    fn is_placeholder(node: &[u8]) -> bool {
+        node.iter().all(|&b| b == 0)
+    }
+
+    fn ensure_clean(&self) -> Result<(), MerkleError> {
+        if self.is_dirty() {
+            Err(MerkleError::PendingPlaceholders(self.placeholders.len()))
+        } else {
+            Ok(())
+        }
+    }
+
+    // root_index for queries that hand data out, which a dirty tree must not do
+    fn clean_root_index(&self) -> Option<usize> {
+        self.ensure_clean().ok()?;
+        self.root_index()
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.clean_root_index()
+            .and_then(|r| self.tree.get(r))
+            .cloned()
+    }
+
+    // the root reported for a tree with no leaves: H's leaf hash of the empty
+    // string, by default H(0x01 * N || H("")) truncated to N. it is one constant
+    // per tree type, whatever the binding, tag mode or key, so it only equals the
+    // root of a one-leaf tree over empty data when that tree is unbound, tagged
+    // and unkeyed; then verify_proof(b"", &[], &empty_root()) succeeds
+    #[must_use]
+    pub fn empty_root() -> Vec<u8> {
+        H::default().leaf(b"")
+    }
+
+    // the root, or empty_root() for a tree with no leaves
+    #[must_use]
+    pub fn root_or_empty(&self) -> Vec<u8> {
+        self.root().unwrap_or_else(Self::empty_root)
+    }
+
+    // the root over the first size leaves as they are stored now. that is the
+    // root the tree had at that size only if none of them was replaced since, by
+    // update or by truncate and add. None for size 0 or past len()
+    #[must_use]
+    pub fn root_at_size(&self, size: usize) -> Option<Vec<u8>> {
+        self.ensure_clean().ok()?;
+        if size == 0 || size > self.len() {
+            return None;
+        }
+
+        self.subtree_hash(0, size)
+    }
+
+    // the hash over leaves first..first + count as a tree of its own. a perfect
+    // subtree starting at a multiple of its width never changes once complete, so
+    // it is read from the layout (its root sits midway between its first and last
+    // leaf); the rest of a smaller tree's right spine is hashed again
+    fn subtree_hash(&self, first: usize, count: usize) -> Option<Vec<u8>> {
+        if count.is_power_of_two() && first.is_multiple_of(count) {
+            return self.tree.get(2 * first + count - 1).cloned();
+        }
+
+        let left_count = 1 << (count - 1).ilog2();
+        let left = self.subtree_hash(first, left_count)?;
+        let right = self.subtree_hash(first + left_count, count - left_count)?;
+
+        Some(Self::mode_node_hash(self.tag_mode, &left, &right))
+    }
+
+    #[must_use]
+    pub fn root_ref(&self) -> Option<&[u8]> {
+        self.clean_root_index()
+            .and_then(|r| self.tree.get(r))
+            .map(Vec::as_slice)
+    }
+
+    #[must_use]
+    pub fn root_array(&self) -> Option<Root<N>> {
+        self.clean_root_index()
+            .and_then(|r| self.tree.get(r))
+            .and_then(|r| <[u8; N]>::try_from(r.as_slice()).ok())
+            .map(Root)
+    }
+
+    // the root in hex as stored, first byte first: what ethereum, certificate
+    // transparency logs and most other tooling print, and what Root displays as
+    #[must_use]
+    pub fn root_hex_be(&self) -> Option<String> {
+        self.root_ref().map(hex::encode)
+    }
+
+    // the root in hex with its bytes reversed, the way bitcoin block explorers
+    // and rpc print txids and merkle roots
+    #[must_use]
+    pub fn root_hex_le(&self) -> Option<String> {
+        self.root_ref().map(|root| {
+            let reversed: Vec<u8> = root.iter().rev().copied().collect();
+            hex::encode(&reversed)
+        })
+    }
+
+    // a flat commitment to the leaf sequence: H(leaf_0 || leaf_1 || ...) over the
+    // leaf hashes in order, truncated to N. no tree structure, so no proofs, but
+    // a single hash is enough to tell whether two trees hold the same leaves in
+    // the same order
+    #[must_use]
+    pub fn order_commitment(&self) -> Vec<u8> {
+        let mut strategy = S::new();
+        for leaf in self.tree.iter().step_by(2) {
+            Digest::update(&mut strategy, leaf);
+        }
+
+        #[allow(unused_mut)]
+        let mut out = strategy.finalize();
+        let commitment = out[..N].to_vec();
+
+        #[cfg(feature = "zeroize")]
+        out.as_mut_slice().zeroize();
+
+        commitment
+    }
+
+    // physical node indices from the leaf up to and including the root
+    #[must_use]
+    pub fn path_indices(&self, leaf_index: usize) -> Option<Vec<usize>> {
+        if leaf_index >= self.len() {
+            return None;
+        }
+
+        let mut path = vec![leaf_index * 2];
+        while let Some(parent) = path
+            .last()
+            .and_then(|&n| flat::lpbt_parent(n, self.tree.len()))
+        {
+            path.push(parent);
+        }
+
+        Some(path)
+    }
+
+    // physical index of the lowest node above both leaves, the leaf itself when
+    // a == b. both paths end at the root, so walking them down from there the
+    // last node they share is the ancestor
+    #[must_use]
+    pub fn lca(&self, a: usize, b: usize) -> Option<usize> {
+        let (path_a, path_b) = (self.path_indices(a)?, self.path_indices(b)?);
+
+        path_a
+            .iter()
+            .rev()
+            .zip(path_b.iter().rev())
+            .take_while(|(x, y)| x == y)
+            .last()
+            .map(|(&node, _)| node)
+    }
+
+    // left-first depth-first search for the leaf, kept on an explicit stack so
+    // tree height never translates into call depth. on success route holds the
+    // siblings from the root down; on failure it is left as it was
+    fn create_proof_route(
+        &self,
+        idx: usize,
+        hash: &[u8],
+        route: &mut Vec<ProofElement<S, N, ND>>,
+    ) -> bool {
+        let base = route.len();
+        // (node, route length above it, sibling that leads into it)
+        let mut stack = vec![(idx, base, None)];
+
+        while let Some((node, depth, element)) = stack.pop() {
+            route.truncate(depth);
+            route.extend(element);
+
+            // only leaves (even indices in the flat layout) are valid match points
+            if node & 1 == 0 && self.tree.get(node).is_some_and(|n| n == hash) {
+                return true;
+            }
+
+            if let Some((left, right)) = self.children(node) {
+                let depth = route.len();
+                let left_sibling = self.proof_element(left, ProofElementDirection::Left);
+                let right_sibling = self.proof_element(right, ProofElementDirection::Right);
+
+                // nothing below a corrupted sibling can be proven
+                if left_sibling.is_some() && right_sibling.is_some() {
+                    stack.push((right, depth, left_sibling));
+                    stack.push((left, depth, right_sibling));
+                }
+            }
+        }
+
+        route.truncate(base);
+        false
+    }
+
+    // the proof for the leaf at index, read straight off its path to the root.
+    // no search and no recursion, so this is the path to prefer for large trees
+    // or when the caller already knows where the leaf is
+    #[must_use]
+    pub fn create_proof_by_index(&self, index: usize) -> Option<Proof<S, N, ND>> {
+        self.ensure_clean().ok()?;
+        let path = self.path_indices(index)?;
+
+        path.windows(2)
+            .map(|w| {
+                let (left, right) = self.children(w[1])?;
+                if left == w[0] {
+                    self.proof_element(right, ProofElementDirection::Right)
+                } else {
+                    self.proof_element(left, ProofElementDirection::Left)
+                }
+            })
+            .collect()
+    }
+
+    // None only for a node that was corrupted to the wrong width
+    fn proof_element(
+        &self,
+        idx: usize,
+        direction: ProofElementDirection,
+    ) -> Option<ProofElement<S, N, ND>> {
+        ProofElement::from_slice(self.tree.get(idx)?, direction).ok()
+    }
+
+    // create_proof_by_index, saying why there is no proof
+    pub fn try_create_proof_by_index(&self, index: usize) -> Result<Proof<S, N, ND>, MerkleError> {
+        if self.is_empty() {
+            return Err(MerkleError::EmptyTree);
+        }
+        if index >= self.len() {
+            return Err(MerkleError::LeafOutOfBounds {
+                index,
+                len: self.len(),
+            });
+        }
+        self.ensure_clean()?;
+
+        self.create_proof_by_index(index)
+            .ok_or(MerkleError::Structural(
+                "leaf path runs past the node array",
+            ))
+    }
+
+    pub fn create_proof(&self, data: &[u8]) -> Option<Vec<ProofElement<S, N, ND>>> {
+        let mut route = vec![];
+
+        let root = self.clean_root_index()?;
+        let found = match self.index_binding {
+            IndexBinding::None => {
+                let hash = self.unbound_leaf_hash(data);
+                self.create_proof_route(root, hash.as_slice(), &mut route)
+            }
+            // leaf hashes depend on position, so try each leaf slot in turn
+            IndexBinding::Prefix => (0..self.len()).any(|i| {
+                let hash = self.leaf_hash(i, data);
+                self.tree.get(i * 2).is_some_and(|node| *node == hash)
+                    && self.create_proof_route(root, hash.as_slice(), &mut route)
+            }),
+        };
+
+        if found {
+            route.reverse();
+            Some(route)
+        } else {
+            None
+        }
+    }
+
+    // the proof leaf index would have had when the tree held its first size
+    // leaves, checked against root_at_size(size). None unless index < size <= len()
+    #[must_use]
+    pub fn create_proof_at_size(
+        &self,
+        index: usize,
+        size: usize,
+    ) -> Option<Vec<ProofElement<S, N, ND>>> {
+        self.ensure_clean().ok()?;
+        if index >= size || size > self.len() {
+            return None;
+        }
+
+        let mut proof = vec![];
+        let (mut first, mut count) = (0, size);
+        while count > 1 {
+            let left_count = 1 << (count - 1).ilog2();
+            if index < first + left_count {
+                let sibling = self.subtree_hash(first + left_count, count - left_count)?;
+                proof.push(ProofElement::from_slice(&sibling, ProofElementDirection::Right).ok()?);
+                count = left_count;
+            } else {
+                let sibling = self.subtree_hash(first, left_count)?;
+                proof.push(ProofElement::from_slice(&sibling, ProofElementDirection::Left).ok()?);
+                first += left_count;
+                count -= left_count;
+            }
+        }
+        proof.reverse();
+
+        Some(proof)
+    }
+
+    // a single proof for leaves start..end, None for an empty or out of range
+    // span. the walk only descends into subtrees the range cuts through, so the
+    // proof holds at most two hashes per level. verify_multiproof knows no key,
+    // so a keyed tree gives None too
+    #[must_use]
+    pub fn prove_range(&self, start: usize, end: usize) -> Option<MultiProof<S, N, ND>> {
+        let root = self.clean_root_index()?;
+        if start >= end || end > self.len() {
+            return None;
+        }
+        #[cfg(feature = "keyed")]
+        if self.key.is_some() {
+            return None;
+        }
+
+        let mut hashes = vec![];
+        // (node, first leaf under it, leaf count), left subtrees popped first
+        let mut stack = vec![(root, 0, self.len())];
+        while let Some((node, first, count)) = stack.pop() {
+            if first + count <= start || end <= first {
+                hashes.push(self.tree.get(node)?.clone());
+            } else if count > 1 {
+                let (left, right) = self.children(node)?;
+                let left_count = 1 << (count - 1).ilog2();
+                stack.push((right, first + left_count, count - left_count));
+                stack.push((left, first, left_count));
+            }
+        }
+
+        Some(MultiProof {
+            start,
+            end,
+            tree_size: self.len(),
+            hashes,
+            _s: PhantomData,
+        })
+    }
+
+    // a proof that the tree starts with leaves 0..n, e.g. that a log was only
+    // ever appended to since it held n entries
+    #[must_use]
+    pub fn prove_first_n(&self, n: usize) -> Option<MultiProof<S, N, ND>> {
+        if n > self.len() {
+            return None;
+        }
+
+        self.prove_range(0, n)
+    }
+
+    // checks that leaves are, in order, the leaves proof.range() of a tree
+    // with root to_match, hashed under the tree's binding and tag mode
+    #[must_use]
+    pub fn verify_multiproof<I>(
+        leaves: I,
+        proof: &MultiProof<S, N, ND>,
+        to_match: &[u8],
+        index_binding: IndexBinding,
+        tag_mode: TagMode,
+    ) -> bool
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        if proof.start >= proof.end || proof.end > proof.tree_size {
+            return false;
+        }
+
+        let mut leaves = leaves.into_iter();
+        let mut hashes = proof.hashes.iter();
+        let root = Self::multiproof_root(
+            (index_binding, tag_mode),
+            0,
+            proof.tree_size,
+            proof,
+            &mut leaves,
+            &mut hashes,
+        );
+
+        leaves.next().is_none() && hashes.next().is_none() && root.is_some_and(|r| r == to_match)
+    }
+
+    // the hash of the subtree over leaves first..first + count, recursing only
+    // as deep as the tree is high
+    fn multiproof_root<I>(
+        mode: (IndexBinding, TagMode),
+        first: usize,
+        count: usize,
+        proof: &MultiProof<S, N, ND>,
+        leaves: &mut I,
+        hashes: &mut std::slice::Iter<'_, Vec<u8>>,
+    ) -> Option<Vec<u8>>
+    where
+        I: Iterator,
+        I::Item: AsRef<[u8]>,
+    {
+        if first + count <= proof.start || proof.end <= first {
+            return hashes.next().filter(|h| h.len() == N).cloned();
+        }
+        if count == 1 {
+            let (index_binding, tag_mode) = mode;
+            return Some(Self::bound_leaf_hash(
+                index_binding,
+                tag_mode,
+                first,
+                leaves.next()?.as_ref(),
+            ));
+        }
+
+        let left_count = 1 << (count - 1).ilog2();
+        let left = Self::multiproof_root(mode, first, left_count, proof, leaves, hashes)?;
+        let right = Self::multiproof_root(
+            mode,
+            first + left_count,
+            count - left_count,
+            proof,
+            leaves,
+            hashes,
+        )?;
+
+        Some(Self::mode_node_hash(mode.1, &left, &right))
+    }
+
+    // one proof per leaf, in leaf order, or none at all for a dirty tree. a single
+    // walk over the tree keeps the shared upper part of every route on a stack
+    // instead of re-searching it
+    #[must_use]
+    pub fn prove_all(&self) -> Vec<Vec<ProofElement<S, N, ND>>> {
+        if self.is_dirty() {
+            return vec![];
+        }
+
+        let mut proofs = vec![vec![]; self.len()];
+
+        if let Some(root) = self.root_index() {
+            let mut route = vec![];
+            self.prove_all_route(root, &mut route, &mut proofs);
+        }
+
+        proofs
+    }
+
+    fn prove_all_route(
+        &self,
+        idx: usize,
+        route: &mut Vec<ProofElement<S, N, ND>>,
+        proofs: &mut [Vec<ProofElement<S, N, ND>>],
+    ) {
+        if let Some((left, right)) = self.children(idx) {
+            // a corrupted sibling leaves the proofs below it empty
+            let (Some(left_sibling), Some(right_sibling)) = (
+                self.proof_element(left, ProofElementDirection::Left),
+                self.proof_element(right, ProofElementDirection::Right),
+            ) else {
+                return;
+            };
+
+            route.push(right_sibling);
+            self.prove_all_route(left, route, proofs);
+            route.pop();
+
+            route.push(left_sibling);
+            self.prove_all_route(right, route, proofs);
+            route.pop();
+        } else if let Some(proof) = proofs.get_mut(idx / 2) {
+            *proof = route.iter().rev().cloned().collect();
+        }
+    }
+
+    // hash operations verify_proof performs: one leaf hash for the data plus one
+    // node hash per proof element. at the digest level a leaf hash is two digest
+    // calls (over the data, then over tag || hash) and a node hash three (over
+    // left || right, that result, then tag || hash), with the first leaf call
+    // scaling with the data length and every other call over at most 2 * N bytes
+    #[must_use]
+    pub fn verify_cost(proof: &[ProofElement<S, N, ND>]) -> usize {
+        proof.len() + 1
+    }
+
+    // the leaf position a proof from a tree of tree_size leaves speaks for, read
+    // off its directions from the root down: a node over n > 1 leaves puts the
+    // largest power of two below n on its left, and a right sibling means the
+    // path goes left. nothing is hashed, so this says nothing about whether the
+    // proof verifies; a proof too short for its leaf gives the first leaf of the
+    // subtree it stops at, and elements past a leaf are ignored
+    #[must_use]
+    pub fn leaf_index_from_proof(proof: &[ProofElement<S, N, ND>], tree_size: usize) -> usize {
+        let (mut first, mut size) = (0, tree_size);
+
+        for e in proof.iter().rev() {
+            if size <= 1 {
+                break;
+            }
+
+            let left = 1 << (size - 1).ilog2();
+            match e.direction {
+                ProofElementDirection::Right => size = left,
+                ProofElementDirection::Left => (first, size) = (first + left, size - left),
+            }
+        }
+
+        first
+    }
+
+    // an empty proof verifies iff the tagged leaf hash of data is to_match itself,
+    // which only holds for the root of a single-leaf tree
+    pub fn verify_proof(data: &[u8], proof: &[ProofElement<S, N, ND>], to_match: &[u8]) -> bool {
+        matches!(Self::verify_proof_detailed(data, proof, to_match), Ok(true))
+    }
+
+    // verify_proof for a root held as an array, e.g. from root_array: a root of
+    // the wrong width cannot be passed at all
+    #[must_use]
+    pub fn verify_proof_arr(data: &[u8], proof: &[ProofElement<S, N, ND>], root: &[u8; N]) -> bool {
+        Self::verify_proof(data, proof, root)
+    }
+
+    // verify_proof over elements produced on the fly, e.g. by a decoder, without
+    // collecting them first. a one-call ProofAccumulator
+    #[must_use]
+    pub fn verify_proof_iter<I>(data: &[u8], proof: I, to_match: &[u8], tag_mode: TagMode) -> bool
+    where
+        I: IntoIterator<Item = ProofElement<S, N, ND>>,
+    {
+        let mut acc = ProofAccumulator::new(data, tag_mode);
+        for element in proof {
+            acc.push(element);
+        }
+
+        acc.finish(to_match)
+    }
+
+    // verify_proof that also refuses any all-zero sibling. that is the placeholder
+    // add() writes before filling a node in, so it can only come from a tree read
+    // mid-construction; a real hash is all zeros with negligible probability
+    #[must_use]
+    pub fn verify_proof_strict(
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+    ) -> bool {
+        !proof.iter().any(|e| Self::is_placeholder(&e.hash))
+            && Self::verify_proof(data, proof, to_match)
+    }
+
+    // verify_proof behind a cheap screen for a common malformation: two equal
+    // consecutive siblings that both sit on the right. an honest route can still
+    // produce that (identical subtrees), so this is a heuristic for filtering junk
+    // proofs early, not a soundness guarantee either way
+    #[must_use]
+    pub fn verify_proof_screened(
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+    ) -> bool {
+        let repeated_right = proof.windows(2).any(|w| {
+            matches!(
+                (w[0].direction, w[1].direction),
+                (ProofElementDirection::Right, ProofElementDirection::Right)
+            ) && w[0].hash == w[1].hash
+        });
+
+        !repeated_right && Self::verify_proof(data, proof, to_match)
+    }
+
+    // like verify_proof, but tells a malformed proof or a root of the wrong width
+    // (e.g. a full digest handed to a truncated tree) apart from a plain mismatch
+    pub fn verify_proof_detailed(
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+    ) -> Result<bool, MerkleError> {
+        Self::fold_proof(
+            TagMode::Block,
+            Self::mode_leaf_hash(TagMode::Block, data),
+            proof,
+            to_match,
+        )
+    }
+
+    // the root a proof implies for data, whether or not any tree has it
+    #[must_use]
+    pub fn implied_root(
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        tag_mode: TagMode,
+    ) -> Vec<u8> {
+        Self::proof_root(tag_mode, Self::mode_leaf_hash(tag_mode, data), proof)
+    }
+
+    // verify_proof that hands back the leaf hash the proof was checked from, for
+    // looking the leaf up elsewhere, e.g. in a map keyed by leaf hash
+    #[must_use]
+    pub fn verify_and_get_leaf_hash(
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        root: &[u8],
+        tag_mode: TagMode,
+    ) -> Option<Vec<u8>> {
+        let leaf = Self::mode_leaf_hash(tag_mode, data);
+
+        matches!(
+            Self::fold_proof(tag_mode, leaf.clone(), proof, root),
+            Ok(true)
+        )
+        .then_some(leaf)
+    }
+
+    // verify_proof against several acceptable roots, e.g. the last few around a
+    // reorg or key rotation: the index of the first root the proof reaches. the
+    // implied root is computed once, however many candidates there are
+    #[must_use]
+    pub fn verify_proof_any(
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        roots: &[&[u8]],
+        tag_mode: TagMode,
+    ) -> Option<usize> {
+        let implied = Self::implied_root(data, proof, tag_mode);
+
+        roots.iter().position(|root| *root == implied)
+    }
+
+    // verify_proof for one datum against many (proof, root) pairs, e.g. the same
+    // record committed on several chains: the leaf is hashed once, then each
+    // proof folded from it. false for a pair verify_proof would reject
+    #[must_use]
+    pub fn verify_same_leaf(
+        data: &[u8],
+        proofs_and_roots: &[(Vec<ProofElement<S, N, ND>>, Vec<u8>)],
+        tag_mode: TagMode,
+    ) -> Vec<bool> {
+        let leaf = Self::mode_leaf_hash(tag_mode, data);
+
+        proofs_and_roots
+            .iter()
+            .map(|(proof, root)| {
+                matches!(
+                    Self::fold_proof(tag_mode, leaf.clone(), proof, root),
+                    Ok(true)
+                )
+            })
+            .collect()
+    }
+
+    // for a tree whose leaves are the roots of other trees, e.g. one per shard:
+    // data must reach inner_root, which as a leaf datum must then reach outer_root.
+    // the inner root is hashed as leaf data like any other, so the outer tree is
+    // built with from_leaves over the shard roots
+    #[must_use]
+    pub fn verify_nested_proof(
+        data: &[u8],
+        inner_proof: &[ProofElement<S, N, ND>],
+        inner_root: &[u8],
+        outer_proof: &[ProofElement<S, N, ND>],
+        outer_root: &[u8],
+    ) -> bool {
+        Self::verify_proof(data, inner_proof, inner_root)
+            && Self::verify_proof(inner_root, outer_proof, outer_root)
+    }
+
+    // verify_proof for a tree built with the given tag mode
+    #[must_use]
+    pub fn verify_proof_with_tag_mode(
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+        tag_mode: TagMode,
+    ) -> bool {
+        let leaf = Self::mode_leaf_hash(tag_mode, data);
+        matches!(Self::fold_proof(tag_mode, leaf, proof, to_match), Ok(true))
+    }
+
+    // verification entry point for trees built with index binding: the leaf is
+    // only accepted at the position it was committed to
+    #[must_use]
+    pub fn verify_proof_at_index(
+        data: &[u8],
+        index: usize,
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+        tag_mode: TagMode,
+    ) -> bool {
+        let leaf = Self::bound_leaf_hash(IndexBinding::Prefix, tag_mode, index, data);
+        matches!(Self::fold_proof(tag_mode, leaf, proof, to_match), Ok(true))
+    }
+
+    pub(crate) fn fold_proof(
+        tag_mode: TagMode,
+        hash: Vec<u8>,
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+    ) -> Result<bool, MerkleError> {
+        if to_match.len() != N {
+            return Err(MerkleError::RootWidthMismatch {
+                expected: N,
+                actual: to_match.len(),
+            });
+        }
+
+        Ok(Self::proof_root(tag_mode, hash, proof) == to_match)
+    }
+
+    fn proof_root(tag_mode: TagMode, hash: Vec<u8>, proof: &[ProofElement<S, N, ND>]) -> Vec<u8> {
+        proof.iter().fold(hash, |acc, e| match e.direction {
+            ProofElementDirection::Left => Self::mode_node_hash(tag_mode, &e.hash, &acc),
+            ProofElementDirection::Right => Self::mode_node_hash(tag_mode, &acc, &e.hash),
+        })
+    }
+
+    // verify_proof against a root kept at only its first M bytes, e.g. one stored
+    // compactly on chain. the fold still runs at full width: every node is hashed
+    // from its children's full N bytes, so siblings cut down to M bytes could never
+    // reproduce their parent, and the root is the only place bytes can be dropped.
+    //
+    // a shorter root is a weaker commitment. a forged proof only has to match M
+    // bytes, so second preimage resistance falls to 8 * M bits and collision
+    // resistance to 4 * M; below M = 16 collisions are within reach
+    #[must_use]
+    pub fn verify_proof_truncated<const M: usize>(
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+        tag_mode: TagMode,
+    ) -> bool {
+        if M == 0 || M > N || to_match.len() != M {
+            return false;
+        }
+
+        let leaf = Self::mode_leaf_hash(tag_mode, data);
+        Self::proof_root(tag_mode, leaf, proof)[..M] == *to_match
+    }
+
+    // like verify_proof, but the proof carries only sibling hashes; the side each
+    // sibling sits on is recomputed from the leaf's position in the flat layout
+    #[must_use]
+    pub fn verify_proof_derived(
+        data: &[u8],
+        leaf_index: usize,
+        tree_size: usize,
+        sibling_hashes: &[[u8; N]],
+        to_match: &[u8],
+        tag_mode: TagMode,
+    ) -> bool {
+        // only a single-leaf tree has an empty route; the walk below also enforces this
+        if leaf_index >= tree_size || (sibling_hashes.is_empty() && tree_size > 1) {
+            return false;
+        }
+
+        let Some(size) = tree_size.checked_mul(2).map(|s| s - 1) else {
+            return false;
+        };
+        let mut node = leaf_index * 2;
+        let mut siblings = sibling_hashes.iter();
+        let mut acc = Self::mode_leaf_hash(tag_mode, data);
+
+        while let Some(parent) = flat::lpbt_parent(node, size) {
+            let Some(sibling) = siblings.next() else {
+                return false;
+            };
+
+            acc = if flat::pbt_left_child(parent) == Some(node) {
+                Self::mode_node_hash(tag_mode, &acc, sibling)
+            } else {
+                Self::mode_node_hash(tag_mode, sibling, &acc)
+            };
+            node = parent;
+        }
+
+        siblings.next().is_none() && acc.iter().eq(to_match)
+    }
+
+    // local audit of sampled leaves: each (index, data) must hash to the stored
+    // leaf at index. no proofs are involved since the tree is at hand, so this
+    // vouches for the leaves, not for the internal nodes above them
+    #[must_use]
+    pub fn verify_sample(&self, samples: &[(usize, &[u8])]) -> bool {
+        samples.iter().all(|&(index, data)| {
+            self.get_leaf(index)
+                .is_some_and(|leaf| leaf == self.leaf_hash(index, data))
+        })
+    }
+
+    // in strict mode an empty proof is only accepted from a single-leaf tree. any
+    // other root is an internal node, which without tags an empty proof over the
+    // concatenated children would otherwise pass for. an index-bound leaf is
+    // hashed at the position the proof's directions lead to
+    #[must_use]
+    pub fn verify(&self, data: &[u8], proof: &[ProofElement<S, N, ND>]) -> bool {
+        if self.strict && proof.is_empty() && self.len() > 1 {
+            return false;
+        }
+
+        self.root().is_some_and(|root| {
+            let index = Self::leaf_index_from_proof(proof, self.len());
+            let leaf = self.leaf_hash(index, data);
+            matches!(
+                Self::fold_proof(self.tag_mode, leaf, proof, &root),
+                Ok(true)
+            )
+        })
+    }
+}
+
+#[cfg(feature = "keyed")]
+impl<
+    S: Debug + Digest + BlockSizeUser,
+    const N: usize,
+    const ND: usize,
+    H: TreeHasher<Output = Vec<u8>> + Default,
+> MerkleTree<S, N, ND, H>
+{
+    // verify_proof for a tree built with hmac_key(key). verify_proof itself
+    // rejects these proofs, since it hashes the leaf without the key
+    #[must_use]
+    pub fn verify_proof_keyed(
+        key: &[u8],
+        data: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+        tag_mode: TagMode,
+    ) -> bool {
+        let leaf = Self::keyed_leaf_hash(tag_mode, hmac::<S>(key, data));
+        matches!(Self::fold_proof(tag_mode, leaf, proof, to_match), Ok(true))
+    }
+
+    // verify_proof_keyed for a keyed tree that also binds each leaf to its index
+    #[must_use]
+    pub fn verify_proof_keyed_at_index(
+        key: &[u8],
+        data: &[u8],
+        index: usize,
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+        tag_mode: TagMode,
+    ) -> bool {
+        let leaf = Self::bind_index(IndexBinding::Prefix, index, data, |d| {
+            Self::keyed_leaf_hash(tag_mode, hmac::<S>(key, d))
+        });
+        matches!(Self::fold_proof(tag_mode, leaf, proof, to_match), Ok(true))
+    }
+}
+
+// folds a proof one element at a time, for verifiers that receive elements from
+// a streaming parser
+pub struct ProofAccumulator<S: Digest, const N: usize, const ND: usize> {
+    acc: Vec<u8>,
+    tag_mode: TagMode,
+    _s: PhantomData<fn() -> S>,
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> ProofAccumulator<S, N, ND> {
+    #[must_use]
+    pub fn new(data: &[u8], tag_mode: TagMode) -> Self {
+        Self {
+            acc: MerkleTree::<S, N, ND>::mode_leaf_hash(tag_mode, data),
+            tag_mode,
+            _s: PhantomData,
+        }
+    }
+
+    pub fn push(&mut self, element: ProofElement<S, N, ND>) {
+        self.acc = match element.direction {
+            ProofElementDirection::Left => {
+                MerkleTree::<S, N, ND>::mode_node_hash(self.tag_mode, &element.hash, &self.acc)
+            }
+            ProofElementDirection::Right => {
+                MerkleTree::<S, N, ND>::mode_node_hash(self.tag_mode, &self.acc, &element.hash)
+            }
+        };
+    }
+
+    #[must_use]
+    pub fn finish(self, expected_root: &[u8]) -> bool {
+        self.acc == expected_root
+    }
+}
+
+pub struct MerkleTreeBuilder<S: Digest, const N: usize, const ND: usize, H = Tagged<S, N, ND>> {
+    index_binding: IndexBinding,
+    tag_mode: TagMode,
+    strict: bool,
+    #[cfg(feature = "keyed")]
+    key: Option<LeafKey>,
+    _s: PhantomData<fn() -> (S, H)>,
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize, H: TreeHasher<Output = Vec<u8>> + Default>
+    Default for MerkleTreeBuilder<S, N, ND, H>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize, H: TreeHasher<Output = Vec<u8>> + Default>
+    MerkleTreeBuilder<S, N, ND, H>
+{
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            index_binding: IndexBinding::None,
+            tag_mode: TagMode::Block,
+            strict: false,
+            #[cfg(feature = "keyed")]
+            key: None,
+            _s: PhantomData,
+        }
+    }
+
+    // commit each leaf to its position by hashing index_le_u64 || data in place of
+    // the data, see IndexBinding::Prefix. proofs from such a tree are checked with
+    // MerkleTree::verify_proof_at_index
+    #[must_use]
+    pub fn bind_index(mut self, bind: bool) -> Self {
+        self.index_binding = if bind {
+            IndexBinding::Prefix
+        } else {
+            IndexBinding::None
+        };
+        self
+    }
+
+    // see TagMode::None before turning the tags off
+    #[must_use]
+    pub fn tag_mode(mut self, tag_mode: TagMode) -> Self {
+        self.tag_mode = tag_mode;
+        self
+    }
+
+    // for security-sensitive callers: add and update refuse empty leaf data, and
+    // verify refuses an empty proof unless the tree has a single leaf
+    #[must_use]
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> MerkleTree<S, N, ND, H> {
+        let mut tree = MerkleTree::with_index_binding(self.index_binding);
+        tree.tag_mode = self.tag_mode;
+        tree.strict = self.strict;
+        #[cfg(feature = "keyed")]
+        {
+            tree.key = self.key;
+        }
+        tree
+    }
+}
+
+#[cfg(feature = "keyed")]
+impl<
+    S: Debug + Digest + BlockSizeUser,
+    const N: usize,
+    const ND: usize,
+    H: TreeHasher<Output = Vec<u8>> + Default,
+> MerkleTreeBuilder<S, N, ND, H>
+{
+    // hash every leaf as HMAC(key, data), so the roots and proofs of the tree say
+    // nothing about its leaves to anyone without the key. the tree keeps a copy of
+    // the key, wiped on drop, and proofs are checked with verify_proof_keyed, or
+    // verify_proof_keyed_at_index if the tree binds indices too
+    #[must_use]
+    pub fn hmac_key(mut self, key: &[u8]) -> Self {
+        self.key = Some(LeafKey {
+            key: zeroize::Zeroizing::new(key.to_vec()),
+            mac: hmac::<S>,
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{
+        composite::{Tagged, TreeHasher},
+        error::MerkleError,
+        flat,
+        rfc6962::Rfc6962Tree,
+    };
+
+    use super::{
+        IndexBinding, MerkleTree, Proof, ProofAccumulator, ProofElement, ProofElementDirection,
+        Root, TagMode,
+    };
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+
+    fn proof_for(tree: &Tree, index: u8) -> Vec<ProofElement<Sha256, 32, 64>> {
+        tree.create_proof(&[index]).unwrap()
+    }
+
+    #[test]
+    fn add() {
+        let mut tree = Tree::new();
+
+        assert!(tree.add(&[0x01]).is_ok());
+        assert!(tree.add(&[0x02]).is_ok());
+        assert!(tree.add(&[0x03]).is_ok());
+        assert!(tree.add(&[0x04]).is_ok());
+        assert!(tree.add(&[0x05]).is_ok());
+
+        let root = tree.root().unwrap();
+        let proof = tree.create_proof(&[0x04]).unwrap();
+
+        assert!(Tree::verify_proof(&[0x04], &proof, &root));
+    }
+
+    #[test]
+    fn proof_elements_have_the_tree_width() {
+        // elements of the wrong width cannot be built at all
+        for len in [31, 33] {
+            assert_eq!(
+                ProofElement::<Sha256, 32, 64>::from_slice(
+                    &vec![0; len],
+                    ProofElementDirection::Left
+                )
+                .err(),
+                Some(MerkleError::InvalidHashLength {
+                    expected: 32,
+                    actual: len
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn root_array() {
+        let mut tree = Tree::new();
+        assert!(tree.root_array().is_none());
+
+        tree.add(&[0x01]).unwrap();
+        tree.add(&[0x02]).unwrap();
+
+        let root = tree.root_array().unwrap();
+        assert_eq!(root.as_ref(), tree.root().unwrap().as_slice());
+
+        let hex = root.to_string();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(
+            hex,
+            tree.root()
+                .unwrap()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>()
+        );
+
+        let mut other = Tree::new();
+        other.add(&[0x01]).unwrap();
+        assert_ne!(other.root_array().unwrap(), root);
+
+        other.add(&[0x02]).unwrap();
+        assert_eq!(other.root_array().unwrap(), root);
+
+        assert_eq!(Root::from([0xab; 2]).to_string(), "abab");
+        assert_eq!(Root::from([0xab; 2]).as_bytes(), &[0xab; 2]);
+    }
+
+    #[test]
+    fn verify_proof_against_an_array_root() {
+        let tree = Tree::from_leaves((0..5u8).map(|i| [i])).unwrap();
+        let root = tree.root_array().unwrap();
+
+        for i in 0..5u8 {
+            let proof = tree.create_proof_by_index(i.into()).unwrap();
+            assert!(Tree::verify_proof_arr(&[i], &proof, root.as_bytes()));
+            assert!(!Tree::verify_proof_arr(&[i + 1], &proof, root.as_bytes()));
+        }
+
+        let single = Tree::from_leaves([[7]]).unwrap();
+        assert!(Tree::verify_proof_arr(
+            &[7],
+            &[],
+            single.root_array().unwrap().as_bytes()
+        ));
+    }
+
+    #[test]
+    fn root_hex_byte_orders() {
+        let tree = Tree::from_leaves([[0u8], [1], [2]]).unwrap();
+        let be = tree.root_hex_be().unwrap();
+        let le = tree.root_hex_le().unwrap();
+
+        assert_eq!(be, tree.root_array().unwrap().to_string());
+        assert_ne!(be, le);
+
+        // the same bytes, one hex pair at a time, in opposite orders
+        let pairs = |hex: &str| {
+            hex.as_bytes()
+                .chunks(2)
+                .map(|pair| pair.to_vec())
+                .collect::<Vec<_>>()
+        };
+        let mut reversed = pairs(&le);
+        reversed.reverse();
+        assert_eq!(pairs(&be), reversed);
+
+        assert!(Tree::new().root_hex_be().is_none());
+        assert!(Tree::new().root_hex_le().is_none());
+    }
+
+    #[test]
+    fn proof_route_skips_internal_nodes() {
+        let mut tree = Tree::new();
+
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let root = tree.root_index().unwrap();
+
+        for idx in (1..tree.tree.len()).step_by(2) {
+            let internal = tree.tree[idx].clone();
+            let mut route = vec![];
+
+            assert!(!tree.create_proof_route(root, &internal, &mut route));
+            assert!(route.is_empty());
+        }
+
+        let leaf = tree.tree[4].clone();
+        let mut route = vec![];
+        assert!(tree.create_proof_route(root, &leaf, &mut route));
+    }
+
+    #[test]
+    fn proof_for_leaf_equal_to_internal_hash() {
+        let mut tree = Tree::new();
+
+        for i in 0..4u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        // leaf data that is byte-identical to an internal node
+        let internal = tree.tree[1].clone();
+        tree.add(&internal).unwrap();
+
+        let root = tree.root().unwrap();
+        let proof = tree.create_proof(&internal).unwrap();
+
+        assert_eq!(proof.len(), 1);
+        assert!(Tree::verify_proof(&internal, &proof, &root));
+    }
+
+    #[test]
+    fn index_binding_prefix() {
+        let mut bound = Tree::with_index_binding(IndexBinding::Prefix);
+        let mut plain = Tree::new();
+
+        for _ in 0..3 {
+            bound.add(&[0x07]).unwrap();
+            plain.add(&[0x07]).unwrap();
+        }
+
+        assert_eq!(bound.len(), 3);
+        assert_ne!(bound.tree[0], bound.tree[2]);
+        assert_ne!(bound.tree[2], bound.tree[4]);
+        assert_eq!(plain.tree[0], plain.tree[2]);
+        assert_ne!(bound.root(), plain.root());
+
+        // the index goes in as 8 little-endian bytes, see IndexBinding
+        assert_eq!(
+            crate::hex::encode(&bound.root().unwrap()),
+            "e5fdf22a4635169b2534b063a514a7c341cdfd8a061fb677af2ff75c89c628c7"
+        );
+
+        assert!(bound.create_proof(&[0x07]).is_some());
+        assert!(bound.create_proof(&[0x08]).is_none());
+    }
+
+    #[test]
+    fn verify_rejects_malformed_elements() {
+        let mut tree = Tree::new();
+
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let root = tree.root().unwrap();
+
+        let mut proof = tree.create_proof(&[0x01]).unwrap();
+        proof[1].hash = [0xaa; 32];
+        assert!(!Tree::verify_proof(&[0x01], &proof, &root));
+
+        // a sibling of the wrong width is stopped by the decoder instead
+        let mut flat = Tree::proof_to_flat(&proof);
+        for len in [31, 33, 0] {
+            flat.resize(len + 1, 0xaa);
+            assert!(Tree::proof_from_flat(&flat).is_err());
+        }
+    }
+
+    #[test]
+    fn verify_against_tree() {
+        let mut tree = Tree::new();
+        assert!(!tree.verify(&[0x01], &[]));
+
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let proof = tree.create_proof(&[0x03]).unwrap();
+        assert!(tree.verify(&[0x03], &proof));
+        assert!(!tree.verify(&[0x04], &proof));
+    }
+
+    #[test]
+    fn verify_against_bound_tree() {
+        let mut tree = Tree::builder().bind_index(true).build();
+        for i in 0..4u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        for i in 0..4u8 {
+            let proof = tree.create_proof_by_index(i.into()).unwrap();
+            assert!(tree.verify(&[i], &proof));
+            assert!(!tree.verify(&[i ^ 1], &proof));
+        }
+
+        // a leaf's data proven at another position is a different leaf
+        assert!(!tree.verify(&[2], &tree.create_proof_by_index(3).unwrap()));
+    }
+
+    // rfc 6962's one-byte domain prefixes, plugged in where the tag blocks go
+    #[derive(Default)]
+    struct Rfc6962Hashes;
+
+    impl TreeHasher for Rfc6962Hashes {
+        type Output = Vec<u8>;
+
+        fn leaf(&self, data: &[u8]) -> Vec<u8> {
+            Rfc6962Tree::<Sha256>::leaf_hash(data)
+        }
+
+        fn node(&self, left: &Vec<u8>, right: &Vec<u8>) -> Vec<u8> {
+            Rfc6962Tree::<Sha256>::node_hash(left, right)
+        }
+    }
+
+    // MerkleTree's own hashing, counting the levels handed over in bulk
+    #[derive(Default)]
+    struct CountedLevels(Tagged<Sha256, 32, 64>);
+
+    static LEVELS: AtomicUsize = AtomicUsize::new(0);
+
+    impl TreeHasher for CountedLevels {
+        type Output = Vec<u8>;
+
+        fn leaf(&self, data: &[u8]) -> Vec<u8> {
+            self.0.leaf(data)
+        }
+
+        fn node(&self, left: &Vec<u8>, right: &Vec<u8>) -> Vec<u8> {
+            self.0.node(left, right)
+        }
+
+        fn level(&self, pairs: &[(&Vec<u8>, &Vec<u8>)]) -> Vec<Vec<u8>> {
+            LEVELS.fetch_add(1, Ordering::Relaxed);
+            self.0.level(pairs)
+        }
+    }
+
+    #[test]
+    fn bulk_builds_hash_a_level_at_a_time() {
+        type Counted = MerkleTree<Sha256, 32, 64, CountedLevels>;
+
+        for size in [1usize, 2, 5, 8, 13] {
+            let leaves = (0..size as u8).map(|i| [i]).collect::<Vec<_>>();
+
+            LEVELS.store(0, Ordering::Relaxed);
+            let mut tree = Counted::from_leaves(&leaves).unwrap();
+            assert_eq!(
+                LEVELS.load(Ordering::Relaxed),
+                (size - 1).checked_ilog2().map_or(0, |h| h as usize + 1)
+            );
+            assert_eq!(tree.root(), Tree::from_leaves(&leaves).unwrap().root());
+
+            let mut added = Tree::new();
+            for leaf in &leaves {
+                added.add(leaf).unwrap();
+            }
+            assert_eq!(tree.root(), added.root());
+
+            tree.tree[0] = vec![0xaa; 32];
+            tree.recompute();
+            assert!(tree.verify_integrity().is_ok());
+        }
+
+        // untagged trees hash plain S and never reach the hasher
+        let mut untagged = Counted::builder().tag_mode(TagMode::None).build();
+        let mut reference = Tree::builder().tag_mode(TagMode::None).build();
+        for i in 0..13u8 {
+            untagged.add(&[i]).unwrap();
+            reference.add(&[i]).unwrap();
+        }
+        LEVELS.store(0, Ordering::Relaxed);
+        untagged.recompute();
+        assert_eq!(LEVELS.load(Ordering::Relaxed), 0);
+        assert_eq!(untagged.root(), reference.root());
+        assert!(untagged.verify_integrity().is_ok());
+    }
+
+    #[test]
+    fn custom_hasher() {
+        let mut tree = MerkleTree::<Sha256, 32, 64, Rfc6962Hashes>::new();
+        let mut plain = Tree::new();
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+            plain.add(&[i]).unwrap();
+        }
+
+        let reference = Rfc6962Tree::<Sha256>::from_leaves((0..5u8).map(|i| [i]));
+        assert_eq!(tree.root().unwrap(), reference.root());
+        assert_ne!(tree.root(), plain.root());
+
+        for i in 0..5u8 {
+            let proof = tree.create_proof_by_index(i.into()).unwrap();
+            assert!(tree.verify(&[i], &proof));
+            assert!(MerkleTree::<Sha256, 32, 64, Rfc6962Hashes>::verify_proof(
+                &[i],
+                &proof,
+                &reference.root()
+            ));
+            assert!(!Tree::verify_proof(&[i], &proof, &reference.root()));
+        }
+
+        assert_eq!(
+            MerkleTree::<Sha256, 32, 64, Rfc6962Hashes>::empty_root(),
+            Rfc6962Tree::<Sha256>::leaf_hash(b"")
+        );
+    }
+
+    #[test]
+    fn failed_add_leaves_tree_untouched() {
+        let mut tree = Tree::new();
+
+        for i in 0..3u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        // node 1 is read on the way up when the fourth leaf is appended
+        tree.tree[1].truncate(4);
+
+        let len = tree.len();
+        let nodes = tree.tree.clone();
+        let root = tree.root();
+        let proofs: Vec<_> = (0..3u8)
+            .map(|i| format!("{:?}", tree.create_proof(&[i])))
+            .collect();
+
+        assert!(tree.add(&[0x03]).is_err());
+
+        assert_eq!(tree.len(), len);
+        assert_eq!(tree.tree, nodes);
+        assert_eq!(tree.root(), root);
+        for i in 0..3u8 {
+            assert_eq!(format!("{:?}", tree.create_proof(&[i])), proofs[i as usize]);
+        }
+    }
+
+    #[test]
+    fn near_capacity() {
+        type Narrow = MerkleTree<Sha256, 4, 8>;
+        assert_eq!(Narrow::recommended_max_leaves(), 1 << 16);
+        assert_eq!(Tree::recommended_max_leaves(), Tree::MAX_LEAVES);
+        assert!(!Tree::new().is_near_capacity());
+
+        let mut tree = Narrow::from_leaves((0..1u32 << 15).map(u32::to_be_bytes)).unwrap();
+        assert!(!tree.is_near_capacity());
+
+        tree.add(b"one more").unwrap();
+        assert!(tree.is_near_capacity());
+    }
+
+    #[test]
+    fn root_ref() {
+        let mut tree = Tree::new();
+        assert!(tree.root_ref().is_none());
+
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        assert_eq!(tree.root_ref(), tree.root().as_deref());
+    }
+
+    #[test]
+    fn update() {
+        let mut tree = Tree::new();
+        let mut expected = Tree::new();
+
+        assert_eq!(
+            tree.update(0, &[0x00]),
+            Err(MerkleError::LeafOutOfBounds { index: 0, len: 0 })
+        );
+
+        tree.add(&[0x00]).unwrap();
+        tree.update(0, &[0x10]).unwrap();
+        expected.add(&[0x10]).unwrap();
+        assert_eq!(tree.root(), expected.root());
+
+        for i in 1..5u8 {
+            tree.add(&[i]).unwrap();
+            expected.add(&[if i == 3 { 0x13 } else { i }]).unwrap();
+        }
+
+        tree.update(3, &[0x13]).unwrap();
+        assert_eq!(tree.root(), expected.root());
+        assert!(tree.update(5, &[0x05]).is_err());
+    }
+
+    #[test]
+    fn corrupted_node_errors_on_mutation() {
+        let mut tree = Tree::new();
+
+        for i in 0..3u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let err = MerkleError::InvalidHashLength {
+            expected: 32,
+            actual: 16,
+        };
+
+        // a short leaf is overwritten cleanly, but a short sibling is read and rejected
+        tree.tree[4].truncate(16);
+        assert_eq!(tree.update(2, &[0x12]), Ok(()));
+
+        tree.tree[2].truncate(16);
+        assert_eq!(tree.update(0, &[0x10]), Err(err.clone()));
+
+        tree.tree[2] = vec![0; 32];
+        tree.tree[1].truncate(16);
+        assert_eq!(tree.add(&[0x03]), Err(err));
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn verify_derived_matches_stored_directions() {
+        for size in 1..=9u8 {
+            let mut tree = Tree::new();
+
+            for i in 0..size {
+                tree.add(&[i]).unwrap();
+            }
+
+            let root = tree.root().unwrap();
+
+            for i in 0..size {
+                let proof = tree.create_proof(&[i]).unwrap();
+                let siblings: Vec<[u8; 32]> = proof
+                    .iter()
+                    .map(|e| e.hash.as_slice().try_into().unwrap())
+                    .collect();
+
+                let index = i as usize;
+                let size = size as usize;
+
+                assert!(Tree::verify_proof(&[i], &proof, &root));
+                assert!(Tree::verify_proof_derived(
+                    &[i],
+                    index,
+                    size,
+                    &siblings,
+                    &root,
+                    TagMode::Block
+                ));
+                assert!(!Tree::verify_proof_derived(
+                    &[i ^ 0xff],
+                    index,
+                    size,
+                    &siblings,
+                    &root,
+                    TagMode::Block
+                ));
+
+                if size > 1 {
+                    let other = (index + 1) % size;
+                    assert!(!Tree::verify_proof_derived(
+                        &[i],
+                        other,
+                        size,
+                        &siblings,
+                        &root,
+                        TagMode::Block
+                    ));
+                    assert!(!Tree::verify_proof_derived(
+                        &[i],
+                        index,
+                        size,
+                        &siblings[1..],
+                        &root,
+                        TagMode::Block
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prove_all() {
+        assert!(Tree::new().prove_all().is_empty());
+
+        for size in 1..=9u8 {
+            let mut tree = Tree::new();
+
+            for i in 0..size {
+                tree.add(&[i]).unwrap();
+            }
+
+            let root = tree.root().unwrap();
+            let proofs = tree.prove_all();
+            assert_eq!(proofs.len(), tree.len());
+
+            for (i, proof) in proofs.iter().enumerate() {
+                let data = [i as u8];
+
+                assert!(Tree::verify_proof(&data, proof, &root));
+                assert_eq!(
+                    format!("{proof:?}"),
+                    format!("{:?}", tree.create_proof(&data).unwrap())
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "sibling node is not the hash of its children")]
+    fn corrupted_internal_node_trips_debug_assertion() {
+        let mut tree = Tree::new();
+
+        for i in 0..3u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        // right width, wrong contents: only the invariant check can notice
+        tree.tree[1] = vec![0xee; 32];
+        let _ = tree.update(2, &[0x12]);
+    }
+
+    #[test]
+    fn empty_root() {
+        let hex = crate::hex::encode;
+
+        let empty = Tree::empty_root();
+        assert_eq!(
+            hex(&empty),
+            "c2569d62cb7428c84430125af79d0a6030a861ceff70d3985390e062b4133c82"
+        );
+        assert_eq!(Tree::new().root_or_empty(), empty);
+        assert!(Tree::verify_proof(b"", &[], &empty));
+        assert!(!Tree::verify_proof(b"x", &[], &empty));
+
+        let mut tree = Tree::new();
+        tree.add(&[0x01]).unwrap();
+        assert_eq!(tree.root_or_empty(), tree.root().unwrap());
+
+        // every empty tree reports the same root, but only a plain one-leaf tree
+        // over empty data has it
+        let configs = [
+            Tree::builder(),
+            Tree::builder().bind_index(true),
+            Tree::builder().tag_mode(TagMode::None),
+            #[cfg(feature = "keyed")]
+            Tree::builder().hmac_key(b"key"),
+        ];
+        for config in configs {
+            let mut tree = config.build();
+            assert_eq!(tree.root_or_empty(), empty);
+
+            let plain = tree.index_binding() == IndexBinding::None
+                && tree.tag_mode() == TagMode::Block
+                && tree.same_key(&Tree::new());
+            tree.add(b"").unwrap();
+            assert_eq!(tree.root_or_empty() == empty, plain);
+        }
+    }
+
+    #[test]
+    fn nodes_round_trip() {
+        let mut tree = Tree::new();
+
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let root = tree.root();
+        let nodes = tree.into_nodes();
+        assert_eq!(nodes.len(), 9);
+
+        let tree = Tree::from_nodes(nodes.clone()).unwrap();
+        assert_eq!(tree.root(), root);
+        assert!(tree.verify(&[0x02], &tree.create_proof(&[0x02]).unwrap()));
+
+        assert!(Tree::from_nodes(vec![]).unwrap().is_empty());
+        assert_eq!(
+            Tree::from_nodes(nodes[..8].to_vec()).err(),
+            Some(MerkleError::InvalidNodeCount(8))
+        );
+
+        let mut short = nodes;
+        short[3].pop();
+        assert_eq!(
+            Tree::from_nodes(short).err(),
+            Some(MerkleError::InvalidHashLength {
+                expected: 32,
+                actual: 31
+            })
+        );
+    }
+
+    #[test]
+    fn from_nodes_with_keeps_config() {
+        let config = || Tree::builder().bind_index(true).tag_mode(TagMode::None);
+        let mut built = config().build();
+        for i in 0..5u8 {
+            built.add(&[i]).unwrap();
+        }
+
+        let mut tree = Tree::from_nodes_with(config(), built.as_nodes().to_vec()).unwrap();
+        assert_eq!(tree.index_binding(), IndexBinding::Prefix);
+        assert_eq!(tree.tag_mode(), TagMode::None);
+
+        // later adds hash the way the nodes already are
+        built.add(&[5]).unwrap();
+        tree.add(&[5]).unwrap();
+        assert_eq!(tree.root(), built.root());
+        assert!(tree.verify_integrity().is_ok());
+    }
+
+    #[test]
+    fn single_leaf_proof() {
+        let mut tree = Tree::new();
+        tree.add(&[0x01]).unwrap();
+
+        let root = tree.root().unwrap();
+        let proof: Proof<Sha256, 32, 64> = tree.create_proof(&[0x01]).unwrap();
+
+        assert!(proof.is_empty());
+        assert!(Tree::verify_proof(&[0x01], &proof, &root));
+        assert!(Tree::verify_proof_derived(
+            &[0x01],
+            0,
+            1,
+            &[],
+            &root,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_proof(&[0x02], &proof, &root));
+
+        tree.add(&[0x02]).unwrap();
+        let root = tree.root().unwrap();
+
+        // a lazy attacker's empty proof must not pass against a multi-leaf root
+        assert!(!Tree::verify_proof(&[0x01], &[], &root));
+        assert!(!Tree::verify_proof(&root, &[], &root));
+        assert!(!Tree::verify_proof_derived(
+            &[0x01],
+            0,
+            2,
+            &[],
+            &root,
+            TagMode::Block
+        ));
+    }
+
+    #[test]
+    fn build_with_proofs() {
+        let leaves: Vec<[u8; 1]> = (0..7u8).map(|i| [i]).collect();
+
+        let (tree, proofs) = Tree::build_with_proofs(&leaves).unwrap();
+        let root = tree.root().unwrap();
+
+        let mut expected = Tree::new();
+        for leaf in &leaves {
+            expected.add(leaf).unwrap();
+        }
+        assert_eq!(tree.tree, expected.tree);
+
+        assert_eq!(proofs.len(), leaves.len());
+        for (leaf, proof) in leaves.iter().zip(&proofs) {
+            assert!(Tree::verify_proof(leaf, proof, &root));
+        }
+
+        let (empty, proofs) = Tree::build_with_proofs(Vec::<Vec<u8>>::new()).unwrap();
+        assert!(empty.is_empty());
+        assert!(proofs.is_empty());
+    }
+
+    #[test]
+    fn verify_detailed_root_width() {
+        let mut tree = Tree::new();
+
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let root = tree.root().unwrap();
+        let proof = tree.create_proof(&[0x01]).unwrap();
+
+        assert_eq!(
+            Tree::verify_proof_detailed(&[0x01], &proof, &root),
+            Ok(true)
+        );
+        assert_eq!(
+            Tree::verify_proof_detailed(&[0x02], &proof, &root),
+            Ok(false)
+        );
+
+        let wide = [root.as_slice(), root.as_slice()].concat();
+        assert_eq!(
+            Tree::verify_proof_detailed(&[0x01], &proof, &wide),
+            Err(MerkleError::RootWidthMismatch {
+                expected: 32,
+                actual: 64
+            })
+        );
+        assert!(!Tree::verify_proof(&[0x01], &proof, &wide));
+    }
+
+    #[test]
+    fn bind_index_builder() {
+        let mut tree = Tree::builder().bind_index(true).build();
+        assert_eq!(tree.index_binding(), IndexBinding::Prefix);
+        assert_eq!(
+            Tree::builder().bind_index(false).build().index_binding(),
+            IndexBinding::None
+        );
+
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let root = tree.root().unwrap();
+        let proof = tree.create_proof(&[0x02]).unwrap();
+
+        assert!(Tree::verify_proof_at_index(
+            &[0x02],
+            2,
+            &proof,
+            &root,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_proof_at_index(
+            &[0x02],
+            3,
+            &proof,
+            &root,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_proof_at_index(
+            &[0x02],
+            0,
+            &proof,
+            &root,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_proof(&[0x02], &proof, &root));
+    }
+
+    #[test]
+    fn strict_builder() {
+        let mut tree = Tree::builder().strict().build();
+        assert!(tree.is_strict());
+        assert!(!Tree::new().is_strict());
+
+        assert_eq!(tree.add(b""), Err(MerkleError::EmptyLeaf));
+        assert!(tree.is_empty());
+        tree.add(b"a").unwrap();
+
+        // a lone leaf is its own root, so its empty proof stands
+        assert!(tree.verify(b"a", &[]));
+
+        tree.add(b"b").unwrap();
+        assert_eq!(tree.update(1, b""), Err(MerkleError::EmptyLeaf));
+        assert!(tree.verify(b"b", &tree.create_proof(b"b").unwrap()));
+
+        // without tags the root is H(a || b), which an empty proof over the two
+        // children's concatenation reproduces
+        let build = |strict: bool| {
+            let builder = Tree::builder().tag_mode(TagMode::None);
+            let mut tree = if strict { builder.strict() } else { builder }.build();
+            tree.add(b"a").unwrap();
+            tree.add(b"b").unwrap();
+            tree
+        };
+        let (lax, strict) = (build(false), build(true));
+        let children = [lax.tree[0].as_slice(), lax.tree[2].as_slice()].concat();
+
+        assert!(lax.verify(&children, &[]));
+        assert!(!strict.verify(&children, &[]));
+        assert!(strict.verify(b"a", &strict.create_proof(b"a").unwrap()));
+    }
+
+    #[test]
+    fn index_math_at_capacity() {
+        let top = 1usize << (usize::BITS - 1);
+
+        assert_eq!(flat::last_set_bit(0), 0);
+        assert_eq!(flat::last_zero_bit(usize::MAX), 0);
+
+        // the largest legal tree has 2 * MAX_LEAVES - 1 nodes, rooted just below top
+        let size = 2 * Tree::MAX_LEAVES - 1;
+        assert_eq!(flat::lpbt_root(size), Some(top / 2 - 1));
+        assert_eq!(flat::lpbt_parent(size - 1, size), Some(size - 2));
+        assert_eq!(flat::lpbt_parent(top / 2 - 1, size), None);
+        assert_eq!(
+            flat::lpbt_right_child(top / 2 - 1, size),
+            Some(top / 2 + top / 4 - 1)
+        );
+
+        // beyond it, nothing wraps
+        assert_eq!(flat::lpbt_root(top), None);
+        assert_eq!(flat::lpbt_root(usize::MAX), None);
+        assert_eq!(flat::pbt_parent(top - 1), None);
+        assert_eq!(flat::pbt_parent(usize::MAX), None);
+        assert_eq!(flat::pbt_leftmost_leaf(usize::MAX), None);
+        assert_eq!(flat::lpbt_parent(usize::MAX - 1, usize::MAX), None);
+        assert_eq!(flat::lpbt_right_child(usize::MAX, usize::MAX), None);
+
+        assert!(Tree::ensure_capacity(Tree::MAX_LEAVES - 1).is_ok());
+        assert_eq!(
+            Tree::ensure_capacity(Tree::MAX_LEAVES),
+            Err(MerkleError::CapacityExceeded {
+                max: Tree::MAX_LEAVES
+            })
+        );
+    }
+
+    #[test]
+    fn path_indices() {
+        let mut tree = Tree::new();
+        assert_eq!(tree.path_indices(0), None);
+
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        //          7
+        //      3       \
+        //    1   5      \
+        //   0 2 4 6      8
+        assert_eq!(tree.path_indices(1), Some(vec![2, 1, 3, 7]));
+        assert_eq!(tree.path_indices(3), Some(vec![6, 5, 3, 7]));
+        assert_eq!(tree.path_indices(4), Some(vec![8, 7]));
+        assert_eq!(tree.path_indices(5), None);
+    }
+
+    #[test]
+    fn hand_built_proofs() {
+        //      3
+        //    1   \
+        //   0 2   4
+        let tree = Tree::from_leaves([b"a", b"b", b"c"]).unwrap();
+        let root = tree.root().unwrap();
+        let node = |i: usize| <[u8; 32]>::try_from(tree.as_nodes()[i].as_slice()).unwrap();
+
+        let proof = vec![ProofElement::new(node(1), ProofElementDirection::Left)];
+        assert!(Tree::verify_proof(b"c", &proof, &root));
+        assert!(!Tree::verify_proof(b"a", &proof, &root));
+
+        let proof = vec![
+            ProofElement::new(node(2), ProofElementDirection::Right),
+            ProofElement::new(node(4), ProofElementDirection::Right),
+        ];
+        assert!(Tree::verify_proof(b"a", &proof, &root));
+        assert_eq!(
+            Tree::proof_to_bytes(&proof),
+            Tree::proof_to_bytes(&tree.create_proof_by_index(0).unwrap())
+        );
+        assert_eq!(proof[1].hash(), &node(4));
+        assert_eq!(proof[1].direction(), ProofElementDirection::Right);
+
+        // a flipped direction hashes the pair the wrong way round
+        let proof = vec![
+            ProofElement::new(node(2), ProofElementDirection::Left),
+            ProofElement::new(node(4), ProofElementDirection::Right),
+        ];
+        assert!(!Tree::verify_proof(b"a", &proof, &root));
+    }
+
+    #[test]
+    fn leaf_index_from_proof() {
+        for size in 1..=17usize {
+            let tree = Tree::from_leaves((0..size as u8).map(|i| [i])).unwrap();
+
+            for i in 0..size {
+                let proof = tree.create_proof_by_index(i).unwrap();
+                assert_eq!(Tree::leaf_index_from_proof(&proof, size), i);
+            }
+        }
+
+        // the proof for leaf 5 of 8, cut short of its last level, stops at the
+        // subtree over leaves 4 and 5
+        let tree = Tree::from_leaves((0..8u8).map(|i| [i])).unwrap();
+        let proof = tree.create_proof_by_index(5).unwrap();
+        assert_eq!(Tree::leaf_index_from_proof(&proof[1..], 8), 4);
+        assert_eq!(Tree::leaf_index_from_proof(&[], 8), 0);
+    }
+
+    #[test]
+    fn prove_first_n() {
+        let leaves = (0..6u8).map(|i| [i]).collect::<Vec<_>>();
+        let tree = Tree::from_leaves(&leaves).unwrap();
+        let root = tree.root().unwrap();
+
+        //          7
+        //      3        \
+        //    1   5       9
+        //   0 2 4 6    8  10
+        // leaves 0..3 need leaf 3 and the subtree over 4..6
+        let proof = tree.prove_first_n(3).unwrap();
+        assert_eq!(proof.range(), 0..3);
+        assert_eq!(proof.tree_size(), 6);
+        assert_eq!(proof.hashes(), [tree.tree[6].clone(), tree.tree[9].clone()]);
+        assert!(Tree::verify_multiproof(
+            &leaves[..3],
+            &proof,
+            &root,
+            IndexBinding::None,
+            TagMode::Block
+        ));
+
+        // the wrong leaves, too few or too many, or another root
+        assert!(!Tree::verify_multiproof(
+            &leaves[1..4],
+            &proof,
+            &root,
+            IndexBinding::None,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_multiproof(
+            &leaves[..2],
+            &proof,
+            &root,
+            IndexBinding::None,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_multiproof(
+            &leaves[..4],
+            &proof,
+            &root,
+            IndexBinding::None,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_multiproof(
+            &leaves[..3],
+            &proof,
+            &[0; 32],
+            IndexBinding::None,
+            TagMode::Block
+        ));
+
+        // every prefix, and the whole tree with nothing to add
+        for n in 1..=6 {
+            let proof = tree.prove_first_n(n).unwrap();
+            assert!(Tree::verify_multiproof(
+                &leaves[..n],
+                &proof,
+                &root,
+                IndexBinding::None,
+                TagMode::Block
+            ));
+        }
+        assert!(tree.prove_first_n(6).unwrap().hashes().is_empty());
+
+        assert!(tree.prove_first_n(0).is_none());
+        assert!(tree.prove_first_n(7).is_none());
+        assert!(tree.prove_range(4, 4).is_none());
+
+        // ranges that do not start at the first leaf
+        let proof = tree.prove_range(2, 5).unwrap();
+        assert!(Tree::verify_multiproof(
+            &leaves[2..5],
+            &proof,
+            &root,
+            IndexBinding::None,
+            TagMode::Block
+        ));
+
+        // index-bound leaves are hashed at their own positions
+        let bound = Tree::from_bound_leaves(IndexBinding::Prefix, &leaves).unwrap();
+        let bound_root = bound.root().unwrap();
+        let proof = bound.prove_range(2, 5).unwrap();
+        assert!(Tree::verify_multiproof(
+            &leaves[2..5],
+            &proof.clone(),
+            &bound_root,
+            IndexBinding::Prefix,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_multiproof(
+            &leaves[2..5],
+            &proof,
+            &bound_root,
+            IndexBinding::None,
+            TagMode::Block
+        ));
+        assert!(format!("{proof:?}").starts_with("MultiProof { start: 2, end: 5, tree_size: 6"));
+    }
+
+    #[test]
+    fn verify_and_get_leaf_hash() {
+        let tree = Tree::from_leaves((0..5u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+
+        for i in 0..5u8 {
+            let proof = tree.create_proof_by_index(i.into()).unwrap();
+            let leaf = Tree::verify_and_get_leaf_hash(&[i], &proof, &root, TagMode::Block).unwrap();
+
+            assert_eq!(leaf, Tree::tag_hash(Tree::LEAF_TAG, &[i]));
+            assert_eq!(tree.get_leaf(i.into()), Some(leaf.as_slice()));
+
+            assert!(
+                Tree::verify_and_get_leaf_hash(&[i + 1], &proof, &root, TagMode::Block).is_none()
+            );
+            assert!(
+                Tree::verify_and_get_leaf_hash(&[i], &proof, &root[..31], TagMode::Block).is_none()
+            );
+        }
+    }
+
+    #[test]
+    fn verify_proof_any() {
+        let trees = (3..6u8)
+            .map(|n| Tree::from_leaves((0..n).map(|i| [i])).unwrap())
+            .collect::<Vec<_>>();
+        let roots = trees.iter().map(|t| t.root().unwrap()).collect::<Vec<_>>();
+        let candidates = roots.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        // leaf 3 only exists from the second tree on, and its proof there is
+        // not the one the third tree gives
+        let proof = trees[1].create_proof_by_index(3).unwrap();
+        assert_eq!(
+            Tree::verify_proof_any(&[3], &proof, &candidates, TagMode::Block),
+            Some(1)
+        );
+        assert_eq!(Tree::implied_root(&[3], &proof, TagMode::Block), roots[1]);
+
+        assert_eq!(
+            Tree::verify_proof_any(&[4], &proof, &candidates, TagMode::Block),
+            None
+        );
+        assert_eq!(
+            Tree::verify_proof_any(&[3], &proof, &[], TagMode::Block),
+            None
+        );
+        assert_eq!(
+            Tree::verify_proof_any(
+                &[3],
+                &proof,
+                &[candidates[1], candidates[1]],
+                TagMode::Block
+            ),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn verify_same_leaf() {
+        // the datum [2] in trees of 3, 4 and 5 leaves, each with its own root
+        let trees = (3..6u8)
+            .map(|n| Tree::from_leaves((0..n).map(|i| [i])).unwrap())
+            .collect::<Vec<_>>();
+        let pair = |t: &Tree| (t.create_proof_by_index(2).unwrap(), t.root().unwrap());
+
+        let mut pairs = trees.iter().map(pair).collect::<Vec<_>>();
+        assert_eq!(
+            Tree::verify_same_leaf(&[2], &pairs, TagMode::Block),
+            [true, true, true]
+        );
+        assert_eq!(
+            Tree::verify_same_leaf(&[3], &pairs, TagMode::Block),
+            [false, false, false]
+        );
+
+        // a root from another tree, a proof from another tree, a short root
+        pairs[0].1 = trees[1].root().unwrap();
+        pairs[1].0 = trees[2].create_proof_by_index(1).unwrap();
+        pairs[2].1.pop();
+        pairs.push(pair(&trees[2]));
+        assert_eq!(
+            Tree::verify_same_leaf(&[2], &pairs, TagMode::Block),
+            [false, false, false, true]
+        );
+        assert!(
+            pairs
+                .iter()
+                .map(|(proof, root)| Tree::verify_proof(&[2], proof, root))
+                .eq(Tree::verify_same_leaf(&[2], &pairs, TagMode::Block))
+        );
+
+        assert!(Tree::verify_same_leaf(&[2], &[], TagMode::Block).is_empty());
+    }
+
+    #[test]
+    fn verify_nested_proof() {
+        let shards = (0..3u8)
+            .map(|s| Tree::from_leaves((0..=s + 2).map(|i| [s, i])).unwrap())
+            .collect::<Vec<_>>();
+        let inner_roots = shards.iter().map(|t| t.root().unwrap()).collect::<Vec<_>>();
+        let outer = Tree::from_leaves(&inner_roots).unwrap();
+        let outer_root = outer.root().unwrap();
+
+        for (s, shard) in shards.iter().enumerate() {
+            let outer_proof = outer.create_proof_by_index(s).unwrap();
+
+            for i in 0..shard.len() {
+                let data = [s as u8, i as u8];
+                let inner_proof = shard.create_proof_by_index(i).unwrap();
+
+                assert!(Tree::verify_nested_proof(
+                    &data,
+                    &inner_proof,
+                    &inner_roots[s],
+                    &outer_proof,
+                    &outer_root
+                ));
+
+                // wrong data, a foreign shard root, and a mismatched outer proof
+                assert!(!Tree::verify_nested_proof(
+                    &[9, 9],
+                    &inner_proof,
+                    &inner_roots[s],
+                    &outer_proof,
+                    &outer_root
+                ));
+                assert!(!Tree::verify_nested_proof(
+                    &data,
+                    &inner_proof,
+                    &inner_roots[(s + 1) % 3],
+                    &outer_proof,
+                    &outer_root
+                ));
+                assert!(!Tree::verify_nested_proof(
+                    &data,
+                    &inner_proof,
+                    &inner_roots[s],
+                    &outer.create_proof_by_index((s + 1) % 3).unwrap(),
+                    &outer_root
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn proofs_at_historical_sizes() {
+        let leaves: Vec<[u8; 1]> = (0..13u8).map(|i| [i]).collect();
+        let tree = Tree::from_leaves(&leaves).unwrap();
+
+        for size in 1..=leaves.len() {
+            let old = Tree::from_leaves(&leaves[..size]).unwrap();
+            let root = tree.root_at_size(size).unwrap();
+            assert_eq!(Some(&root), old.root().as_ref());
+
+            for (i, leaf) in leaves[..size].iter().enumerate() {
+                let proof = tree.create_proof_at_size(i, size).unwrap();
+                assert!(Tree::verify_proof(leaf, &proof, &root));
+                assert_eq!(
+                    Tree::proof_to_bytes(&proof).unwrap(),
+                    Tree::proof_to_bytes(&old.create_proof_by_index(i).unwrap()).unwrap()
+                );
+            }
+        }
+
+        // the leaf's path has changed since, so the old proof is stale
+        let proof = tree.create_proof_at_size(2, 5).unwrap();
+        assert!(!Tree::verify_proof(&[2], &proof, &tree.root().unwrap()));
+
+        assert!(tree.root_at_size(0).is_none());
+        assert!(tree.root_at_size(14).is_none());
+        assert!(tree.create_proof_at_size(5, 5).is_none());
+        assert!(tree.create_proof_at_size(0, 14).is_none());
+        assert!(Tree::new().root_at_size(1).is_none());
+
+        // an update shows through every size that covers the leaf
+        let mut updated = tree;
+        updated.update(2, &[0xff]).unwrap();
+        let root = Tree::from_leaves(&leaves[..5]).unwrap().root();
+        assert_ne!(updated.root_at_size(5), root);
+        assert_eq!(
+            updated.root_at_size(2),
+            Tree::from_leaves(&leaves[..2]).unwrap().root()
+        );
+    }
+
+    #[test]
+    fn lca() {
+        let tree = Tree::from_leaves((0..8u8).map(|i| [i])).unwrap();
+
+        // 7 roots the whole tree, 3 leaves 0..4, 11 leaves 4..8
+        assert_eq!(tree.lca(0, 3), Some(3));
+        assert_eq!(tree.lca(3, 0), Some(3));
+        assert_eq!(tree.lca(0, 1), Some(1));
+        assert_eq!(tree.lca(4, 7), Some(11));
+        assert_eq!(tree.lca(3, 4), Some(7));
+        assert_eq!(tree.lca(5, 5), Some(10));
+        assert_eq!(tree.lca(0, 8), None);
+
+        // the odd leaf out of a 5-leaf tree hangs straight off the root
+        let tree = Tree::from_leaves((0..5u8).map(|i| [i])).unwrap();
+        assert_eq!(tree.lca(1, 4), Some(7));
+        assert_eq!(tree.lca(1, 2), Some(3));
+    }
+
+    #[test]
+    fn has_leaf() {
+        let tree = Tree::from_leaves([[0u8], [1], [2]]).unwrap();
+
+        assert!(tree.has_leaf(0));
+        assert!(tree.has_leaf(2));
+        assert!(!tree.has_leaf(3));
+        assert!(!tree.has_leaf(usize::MAX));
+        assert!(!Tree::new().has_leaf(0));
+    }
+
+    #[test]
+    fn node_kinds() {
+        let tree = Tree::from_leaves((0..4u8).map(|i| [i])).unwrap();
+        assert_eq!(tree.as_nodes().len(), 7);
+
+        for i in 0..7 {
+            assert_eq!(Tree::is_leaf(i), [0, 2, 4, 6].contains(&i));
+            assert_eq!(Tree::is_internal(i), [1, 3, 5].contains(&i));
+        }
+
+        let leaves = (0..7).filter(|&i| Tree::is_leaf(i)).count();
+        assert_eq!(leaves, tree.len());
+
+        for n in 1..=9u8 {
+            let tree = Tree::from_leaves((0..n).map(|i| [i])).unwrap();
+            assert_eq!(tree.internal_node_count(), tree.len() - 1);
+            assert_eq!(
+                tree.internal_node_count(),
+                (0..tree.as_nodes().len())
+                    .filter(|&i| Tree::is_internal(i))
+                    .count()
+            );
+        }
+        assert_eq!(Tree::new().internal_node_count(), 0);
+    }
+
+    #[test]
+    fn screened_verification_rejects_repeated_right_siblings() {
+        let mut tree = Tree::new();
+        for i in 0..8u8 {
+            tree.add(&[i]).unwrap();
+        }
+        let root = tree.root().unwrap();
+
+        let proof = proof_for(&tree, 0);
+        assert!(Tree::verify_proof_screened(&[0], &proof, &root));
+
+        let mut malformed = proof.clone();
+        malformed[1] = malformed[0].clone();
+        assert!(!Tree::verify_proof_screened(&[0], &malformed, &root));
+
+        // the screen fires before the fold, even against the root the pair folds to
+        let forged = Tree::node_hash(
+            &Tree::node_hash(
+                &Tree::node_hash(&Tree::tag_hash(Tree::LEAF_TAG, &[0]), &malformed[0].hash),
+                &malformed[1].hash,
+            ),
+            &malformed[2].hash,
+        );
+        assert!(Tree::verify_proof(&[0], &malformed, &forged));
+        assert!(!Tree::verify_proof_screened(&[0], &malformed, &forged));
+    }
+
+    #[test]
+    fn proof_by_index_on_a_large_tree() {
+        let leaves = (0..100_000u32).map(u32::to_be_bytes).collect::<Vec<_>>();
+        let tree = Tree::from_leaves(&leaves).unwrap();
+        let root = tree.root().unwrap();
+
+        for index in [0, 1, 65_535, 65_536, 99_998, 99_999] {
+            let proof = tree.create_proof_by_index(index).unwrap();
+            assert_eq!(
+                Tree::proof_to_bytes(&tree.try_create_proof_by_index(index).unwrap()),
+                Tree::proof_to_bytes(&proof)
+            );
+            assert_eq!(proof.len(), tree.path_indices(index).unwrap().len() - 1);
+            assert!(Tree::verify_proof(&leaves[index], &proof, &root));
+        }
+        assert!(tree.create_proof_by_index(100_000).is_none());
+        assert_eq!(
+            tree.try_create_proof_by_index(100_000).err(),
+            Some(MerkleError::LeafOutOfBounds {
+                index: 100_000,
+                len: 100_000
+            })
+        );
+        assert_eq!(
+            Tree::new().try_create_proof_by_index(0).err(),
+            Some(MerkleError::EmptyTree)
+        );
+
+        let proof = tree.create_proof(&leaves[99_999]).unwrap();
+        assert_eq!(
+            Tree::proof_to_bytes(&proof).unwrap(),
+            Tree::proof_to_bytes(&tree.create_proof_by_index(99_999).unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn dirty_tree_refuses_proofs_until_repaired() {
+        let clean = Tree::from_leaves((0..5u8).map(|i| [i])).unwrap();
+
+        // a snapshot taken after add pushed its placeholders but before it filled them
+        let mut nodes = clean.as_nodes().to_vec();
+        nodes.extend([vec![0; 32], vec![0; 32]]);
+        let mut tree = Tree::from_nodes(nodes).unwrap();
+
+        assert!(tree.is_dirty());
+        assert_eq!(tree.root(), None);
+        assert_eq!(tree.root_ref(), None);
+        assert!(tree.create_proof(&[0]).is_none());
+        assert!(tree.create_proof_by_index(0).is_none());
+        assert_eq!(
+            tree.try_create_proof_by_index(0).err(),
+            Some(MerkleError::PendingPlaceholders(2))
+        );
+        assert!(tree.prove_all().is_empty());
+        assert_eq!(tree.add(&[5]), Err(MerkleError::PendingPlaceholders(2)));
+        assert_eq!(
+            tree.verify_integrity(),
+            Err(MerkleError::PendingPlaceholders(2))
+        );
+
+        tree.repair().unwrap();
+        assert!(!tree.is_dirty());
+        assert!(tree == clean);
+
+        tree.add(&[5]).unwrap();
+        let root = tree.root().unwrap();
+        assert!(Tree::verify_proof(
+            &[5],
+            &tree.create_proof(&[5]).unwrap(),
+            &root
+        ));
+
+        // a placeholder among the leaves has lost its data for good
+        let mut nodes = clean.into_nodes();
+        nodes[1] = vec![0; 32];
+        nodes[2] = vec![0; 32];
+        let mut tree = Tree::from_nodes(nodes).unwrap();
+        assert_eq!(
+            tree.repair(),
+            Err(MerkleError::IntegrityViolation { index: 2 })
+        );
+        assert!(tree.is_dirty());
+    }
+
+    #[test]
+    fn zero_hashes_are_not_placeholders() {
+        type Narrow = MerkleTree<Sha256, 1, 2>;
+        let config = || Narrow::builder().tag_mode(TagMode::None);
+
+        // at N = 1 about one leaf in 256 hashes to zero
+        let zero = (0..=u16::MAX)
+            .map(u16::to_be_bytes)
+            .find(|d| Narrow::hash(d) == [0])
+            .unwrap();
+
+        let mut tree = config().build();
+        for leaf in [&b"a"[..], &zero, b"b"] {
+            tree.add(leaf).unwrap();
+        }
+        assert!(!tree.is_dirty());
+        let root = tree.root().unwrap();
+        assert!(tree.verify(&zero, &tree.create_proof_by_index(1).unwrap()));
+
+        tree.recompute();
+        assert!(!tree.is_dirty());
+        assert_eq!(tree.repair(), Ok(()));
+        assert_eq!(tree.root(), Some(root.clone()));
+
+        let mut tree = Narrow::from_nodes_with(config(), tree.into_nodes()).unwrap();
+        assert!(!tree.is_dirty());
+        assert_eq!(tree.repair(), Ok(()));
+        assert_eq!(tree.root(), Some(root));
+    }
+
+    #[test]
+    fn recompute_after_corruption() {
+        let mut tree = Tree::from_leaves((0..6u8).map(|i| [i])).unwrap();
+        let root = tree.root();
+
+        tree.tree[3][0] ^= 1;
+        assert_eq!(
+            tree.verify_integrity(),
+            Err(MerkleError::IntegrityViolation { index: 3 })
+        );
+
+        tree.recompute();
+        assert_eq!(tree.verify_integrity(), Ok(()));
+        assert_eq!(tree.root(), root);
+    }
+
+    #[test]
+    fn recompute_restores_internal_nodes() {
+        let clean = Tree::from_leaves((0..7u8).map(|i| [i])).unwrap();
+        let mut tree = Tree::from_nodes(clean.as_nodes().to_vec()).unwrap();
+
+        // garbage in some internal nodes, placeholders in others
+        for idx in [1, 3, 7] {
+            tree.tree[idx] = vec![0xab; 32];
+        }
+        tree.tree[5] = vec![0; 32];
+        tree.tree[11] = vec![0; 32];
+        tree.placeholders.extend([5, 11]);
+        let leaves: Vec<_> = (0..tree.tree.len())
+            .step_by(2)
+            .map(|i| tree.tree[i].clone())
+            .collect();
+
+        tree.recompute();
+        assert!(!tree.is_dirty());
+        assert!(tree == clean);
+        assert_eq!(tree.root(), clean.root());
+        assert!(tree.verify_integrity().is_ok());
+        assert!(
+            (0..tree.tree.len())
+                .step_by(2)
+                .map(|i| &tree.tree[i])
+                .eq(leaves.iter())
+        );
+
+        // a lost leaf stays lost
+        tree.tree[4] = vec![0; 32];
+        tree.placeholders.insert(4);
+        tree.recompute();
+        assert!(tree.is_dirty());
+        assert_eq!(tree.root(), None);
+
+        let mut empty = Tree::new();
+        empty.recompute();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn strict_verification_rejects_zero_siblings() {
+        let tree = Tree::from_leaves((0..3u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+
+        let proof = proof_for(&tree, 2);
+        assert!(Tree::verify_proof_strict(&[2], &proof, &root));
+
+        // a root derived from a placeholder sibling, as a half-built tree would report
+        let mut zeroed = proof.clone();
+        zeroed[0].hash = [0; 32];
+        let zero_root = Tree::node_hash(&zeroed[0].hash, &Tree::tag_hash(Tree::LEAF_TAG, &[2]));
+
+        assert!(Tree::verify_proof(&[2], &zeroed, &zero_root));
+        assert!(!Tree::verify_proof_strict(&[2], &zeroed, &zero_root));
+    }
+
+    #[test]
+    #[should_panic(expected = "N must be at least 1")]
+    fn zero_width_hashes_are_rejected() {
+        let _ = MerkleTree::<Sha256, 0, 0>::new();
+    }
+
+    #[test]
+    fn order_commitment_tracks_leaf_order() {
+        let a = Tree::from_leaves([[1u8], [2], [3]]).unwrap();
+        let same = Tree::from_leaves([[1u8], [2], [3]]).unwrap();
+        let reordered = Tree::from_leaves([[3u8], [2], [1]]).unwrap();
+
+        assert_eq!(a.root(), same.root());
+        assert_eq!(a.order_commitment(), same.order_commitment());
+
+        assert_ne!(a.root(), reordered.root());
+        assert_ne!(a.order_commitment(), reordered.order_commitment());
+
+        let leaves = [1u8, 2, 3]
+            .map(|i| Tree::tag_hash(Tree::LEAF_TAG, &[i]))
+            .concat();
+        assert_eq!(a.order_commitment(), Tree::hash(&leaves));
+        assert_eq!(Tree::new().order_commitment(), Tree::hash(b""));
+    }
+
+    #[test]
+    fn verify_cost_counts_leaf_and_node_hashes() {
+        let tree = Tree::from_leaves((0..8u8).map(|i| [i])).unwrap();
+
+        let proof = proof_for(&tree, 3);
+        assert_eq!(proof.len(), 3);
+        assert_eq!(Tree::verify_cost(&proof), 4);
+        assert_eq!(Tree::verify_cost(&[]), 1);
+    }
+
+    #[test]
+    fn verify_sample_checks_stored_leaves() {
+        for binding in [IndexBinding::None, IndexBinding::Prefix] {
+            let mut tree = Tree::with_index_binding(binding);
+            for i in 0..9u8 {
+                tree.add(&[i]).unwrap();
+            }
+
+            assert!(tree.verify_sample(&[(0, &[0]), (4, &[4]), (8, &[8])]));
+            assert!(tree.verify_sample(&[]));
+
+            assert!(!tree.verify_sample(&[(0, &[0]), (4, &[5])]));
+            assert!(!tree.verify_sample(&[(9, &[9])]));
+            assert!(!tree.verify_sample(&[(usize::MAX, &[0])]));
+        }
+
+        let tree = Tree::from_leaves([[7u8]]).unwrap();
+        assert_eq!(tree.get_leaf(0), tree.root_ref());
+        assert_eq!(tree.get_leaf(1), None);
+    }
+
+    #[test]
+    fn size_bytes_counts_every_node() {
+        let tree = Tree::from_leaves((0..4u8).map(|i| [i])).unwrap();
+        assert_eq!(tree.size_bytes(), 7 * 32);
+
+        assert_eq!(Tree::new().size_bytes(), 0);
+        assert_eq!(
+            MerkleTree::<Sha256, 16, 32>::from_leaves([[0], [1], [2]])
+                .unwrap()
+                .size_bytes(),
+            5 * 16
+        );
+    }
+
+    #[test]
+    fn truncated_roots_verify_full_width_proofs() {
+        let tree = Tree::from_leaves((0..6u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+        let short = &root[..16];
+
+        for (i, proof) in tree.prove_all().iter().enumerate() {
+            assert!(Tree::verify_proof_truncated::<16>(
+                &[i as u8],
+                proof,
+                short,
+                TagMode::Block
+            ));
+            assert!(Tree::verify_proof_truncated::<32>(
+                &[i as u8],
+                proof,
+                &root,
+                TagMode::Block
+            ));
+            assert!(!Tree::verify_proof_truncated::<16>(
+                &[i as u8 + 1],
+                proof,
+                short,
+                TagMode::Block
+            ));
+
+            // cutting the siblings instead (zeroing what would be dropped) breaks the fold
+            let mut cut = proof.clone();
+            cut.iter_mut().for_each(|e| e.hash[16..].fill(0));
+            assert!(!Tree::verify_proof_truncated::<16>(
+                &[i as u8],
+                &cut,
+                short,
+                TagMode::Block
+            ));
+        }
+
+        let proof = tree.create_proof_by_index(0).unwrap();
+        assert!(!Tree::verify_proof_truncated::<16>(
+            &[0],
+            &proof,
+            &root,
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_proof_truncated::<0>(
+            &[0],
+            &proof,
+            &[],
+            TagMode::Block
+        ));
+        assert!(!Tree::verify_proof_truncated::<33>(
+            &[0],
+            &proof,
+            &[0; 33],
+            TagMode::Block
+        ));
+    }
+
+    #[test]
+    fn memory_footprint_grows_linearly() {
+        let footprint = |n: u32| {
+            Tree::from_leaves((0..n).map(u32::to_be_bytes))
+                .unwrap()
+                .memory_footprint()
+        };
+
+        let (small, large) = (footprint(1000), footprint(4000));
+        assert!(
+            small
+                >= Tree::from_leaves((0..1000u32).map(u32::to_be_bytes))
+                    .unwrap()
+                    .size_bytes()
+        );
+
+        // four times the leaves, four times the nodes, give or take the spare
+        // capacity of the outer vector
+        let ratio = large as f64 / small as f64;
+        assert!((3.0..=5.0).contains(&ratio), "ratio {ratio}");
+
+        assert_eq!(Tree::new().memory_footprint(), size_of::<Tree>());
+    }
+
+    #[test]
+    fn verify_proof_iter_matches_verify_proof() {
+        let tree = Tree::from_leaves((0..9u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+
+        for (i, proof) in tree.prove_all().iter().enumerate() {
+            assert!(Tree::verify_proof(&[i as u8], proof, &root));
+            assert!(Tree::verify_proof_iter(
+                &[i as u8],
+                proof.iter().cloned(),
+                &root,
+                TagMode::Block
+            ));
+            assert!(!Tree::verify_proof_iter(
+                &[i as u8],
+                proof.iter().skip(1).cloned(),
+                &root,
+                TagMode::Block
+            ));
+
+            // elements decoded one at a time straight into the verifier
+            let bytes = Tree::proof_to_flat(proof);
+            let decoded = bytes
+                .chunks(33)
+                .map(|chunk| Tree::proof_from_flat(chunk).unwrap().remove(0));
+            assert!(Tree::verify_proof_iter(
+                &[i as u8],
+                decoded,
+                &root,
+                TagMode::Block
+            ));
+        }
+
+        let mut bad = tree.create_proof_by_index(0).unwrap();
+        bad[0].hash[0] ^= 1;
+        assert!(!Tree::verify_proof_iter(&[0], bad, &root, TagMode::Block));
+        assert!(!Tree::verify_proof_iter(&[0], [], &root, TagMode::Block));
+    }
+
+    #[test]
+    fn untagged_proofs_pass_every_verifier() {
+        let untagged = || Tree::builder().tag_mode(TagMode::None);
+        let mut tree = untagged().build();
+        let mut bound = untagged().bind_index(true).build();
+        for i in 0..5u8 {
+            tree.add(&[i]).unwrap();
+            bound.add(&[i]).unwrap();
+        }
+        let root = tree.root().unwrap();
+        let none = TagMode::None;
+
+        for i in 0..5u8 {
+            let proof = proof_for(&tree, i);
+            let siblings: Vec<_> = proof.iter().map(|e| *e.hash()).collect();
+
+            assert!(Tree::verify_proof_iter(&[i], proof.clone(), &root, none));
+            assert_eq!(Tree::implied_root(&[i], &proof, none), root);
+            assert_eq!(
+                Tree::verify_proof_any(&[i], &proof, &[&root], none),
+                Some(0)
+            );
+            assert_eq!(
+                Tree::verify_and_get_leaf_hash(&[i], &proof, &root, none),
+                Some(Tree::hash(&[i]))
+            );
+            assert_eq!(
+                Tree::verify_same_leaf(&[i], &[(proof.clone(), root.clone())], none),
+                [true]
+            );
+            assert!(Tree::verify_proof_truncated::<16>(
+                &[i],
+                &proof,
+                &root[..16],
+                none
+            ));
+            assert!(Tree::verify_proof_derived(
+                &[i],
+                i.into(),
+                5,
+                &siblings,
+                &root,
+                none
+            ));
+
+            // and none of them take the proof as a tagged one
+            assert!(!Tree::verify_proof_iter(
+                &[i],
+                proof.clone(),
+                &root,
+                TagMode::Block
+            ));
+            assert!(!Tree::verify_proof_derived(
+                &[i],
+                i.into(),
+                5,
+                &siblings,
+                &root,
+                TagMode::Block
+            ));
+
+            let bound_proof = bound.create_proof_by_index(i.into()).unwrap();
+            assert!(Tree::verify_proof_at_index(
+                &[i],
+                i.into(),
+                &bound_proof,
+                &bound.root().unwrap(),
+                none
+            ));
+        }
+
+        let range = tree.prove_range(1, 4).unwrap();
+        assert!(Tree::verify_multiproof(
+            [[1], [2], [3]],
+            &range,
+            &root,
+            IndexBinding::None,
+            none
+        ));
+        assert!(!Tree::verify_multiproof(
+            [[1], [2], [3]],
+            &range,
+            &root,
+            IndexBinding::None,
+            TagMode::Block
+        ));
+    }
+
+    #[test]
+    fn untagged_trees_hash_plainly() {
+        let leaves = (0..5u8).map(|i| [i]).collect::<Vec<_>>();
+        let tagged = Tree::from_leaves(&leaves).unwrap();
+
+        let mut untagged = Tree::builder().tag_mode(TagMode::None).build();
+        for leaf in &leaves {
+            untagged.add(leaf).unwrap();
+        }
+        assert_eq!(untagged.tag_mode(), TagMode::None);
+        assert_ne!(tagged.root(), untagged.root());
+        assert!(tagged != untagged);
+
+        // H(H(H(d0) || H(d1)) || H(H(d2) || H(d3))) || H(d4)
+        let h = |data: &[u8]| Sha256::digest(data).to_vec();
+        let pair = |l: &[u8], r: &[u8]| h(&[l, r].concat());
+        let [d0, d1, d2, d3, d4] = [0u8, 1, 2, 3, 4].map(|i| h(&[i]));
+        let expected = pair(&pair(&pair(&d0, &d1), &pair(&d2, &d3)), &d4);
+        assert_eq!(untagged.root().unwrap(), expected);
+
+        let root = untagged.root().unwrap();
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = untagged.create_proof(leaf).unwrap();
+            assert!(untagged.verify(leaf, &proof));
+            assert!(Tree::verify_proof_with_tag_mode(
+                leaf,
+                &proof,
+                &root,
+                TagMode::None
+            ));
+            assert!(!Tree::verify_proof(leaf, &proof, &root));
+
+            // updates and the integrity check follow the tree's mode
+            let mut updated = Tree::builder().tag_mode(TagMode::None).build();
+            for leaf in &leaves {
+                updated.add(leaf).unwrap();
+            }
+            updated.update(i, &[9]).unwrap();
+            assert!(updated.verify_integrity().is_ok());
+        }
+        assert!(untagged.verify_integrity().is_ok());
+
+        // the weakness the tags exist for: the children of an internal node,
+        // concatenated, pass as a leaf that was never added
+        let forged = [d0.as_slice(), &d1].concat();
+        let proof = untagged.create_proof_by_index(2).unwrap();
+        let mut forged_proof = proof[2..].to_vec();
+        forged_proof.insert(
+            0,
+            ProofElement::from_slice(&pair(&d2, &d3), ProofElementDirection::Right).unwrap(),
+        );
+        assert!(Tree::verify_proof_with_tag_mode(
+            &forged,
+            &forged_proof,
+            &root,
+            TagMode::None
+        ));
+        assert!(!Tree::verify_proof(
+            &forged,
+            &forged_proof,
+            &tagged.root().unwrap()
+        ));
+
+        assert_eq!(
+            Tree::builder()
+                .tag_mode(TagMode::None)
+                .build()
+                .root_or_empty(),
+            Tree::empty_root()
+        );
+    }
+
+    #[test]
+    fn new_unchecked_builds_a_working_tree() {
+        // SAFETY: N = 32 fits sha256's output and ND = 2 * N
+        let mut unchecked = unsafe { Tree::new_unchecked() };
+        assert!(unchecked == Tree::new());
+
+        for i in 0..5u8 {
+            unchecked.add(&[i]).unwrap();
+        }
+        let root = unchecked.root().unwrap();
+        assert_eq!(
+            Some(root.clone()),
+            Tree::from_leaves((0..5u8).map(|i| [i])).unwrap().root()
+        );
+        assert!(Tree::verify_proof(
+            &[3],
+            &unchecked.create_proof(&[3]).unwrap(),
+            &root
+        ));
+
+        // usable where new() is not
+        const EMPTY: MerkleTree<Sha256, 16, 32> = unsafe { MerkleTree::new_unchecked() };
+        assert!(EMPTY.is_empty());
+    }
+
+    #[test]
+    fn truncate_matches_a_fresh_tree() {
+        for total in 1..=17u8 {
+            for keep in 0..=total as usize {
+                let mut tree = Tree::new();
+                for i in 0..total {
+                    tree.add(&[i]).unwrap();
+                }
+
+                tree.truncate(keep).unwrap();
+                tree.verify_integrity().unwrap();
+
+                let expected = Tree::from_leaves((0..keep as u8).map(|i| [i])).unwrap();
+                assert!(tree == expected, "{total} leaves truncated to {keep}");
+            }
+        }
+    }
+
+    #[test]
+    fn concat_matches_a_single_tree() {
+        let whole = Tree::from_leaves((0..5u8).map(|i| [i])).unwrap();
+        let head = Tree::from_leaves((0..3u8).map(|i| [i])).unwrap();
+        let tail = Tree::from_leaves((3..5u8).map(|i| [i])).unwrap();
+
+        let joined = head.concat(tail).unwrap();
+        assert_eq!(joined.root(), whole.root());
+        assert!(joined == whole);
+        joined.verify_integrity().unwrap();
+
+        // every split point, including empty halves
+        for split in 0..=13u8 {
+            let left = Tree::from_leaves((0..split).map(|i| [i])).unwrap();
+            let right = Tree::from_leaves((split..13).map(|i| [i])).unwrap();
+            assert!(
+                left.concat(right).unwrap() == Tree::from_leaves((0..13u8).map(|i| [i])).unwrap()
+            );
+        }
+
+        let untagged = Tree::builder().tag_mode(TagMode::None).build();
+        assert_eq!(
+            whole.concat(untagged).err(),
+            Some(MerkleError::Structural(
+                "trees hash their leaves differently"
+            ))
+        );
+
+        let bound = |range: std::ops::Range<u8>| {
+            Tree::from_bound_leaves(IndexBinding::Prefix, range.map(|i| [i])).unwrap()
+        };
+        assert_eq!(
+            bound(0..3).concat(bound(3..5)).err(),
+            Some(MerkleError::Structural(
+                "index-bound leaves cannot change position"
+            ))
+        );
+        assert!(bound(0..0).concat(bound(0..5)).unwrap() == bound(0..5));
+    }
+
+    #[test]
+    fn verify_integrity_finds_tampered_nodes() {
+        let mut nodes = Tree::from_leaves((0..5u8).map(|i| [i]))
+            .unwrap()
+            .into_nodes();
+        nodes[4][0] ^= 1;
+
+        let mut tree = Tree::from_nodes(nodes).unwrap();
+        assert_eq!(
+            tree.verify_integrity(),
+            Err(MerkleError::IntegrityViolation { index: 5 })
+        );
+
+        // a changed leaf leaves its ancestors stale until recompute sweeps them, and
+        // so does a corrupted internal node
+        let leaf = tree.tree[4].clone();
+        tree.tree[1][0] ^= 1;
+        tree.recompute();
+        assert_eq!(tree.verify_integrity(), Ok(()));
+        assert_eq!(tree.tree[4], leaf);
+    }
+
+    mod adversarial {
+        use super::{Tree, proof_for};
+        use crate::merkle::{ProofElement, ProofElementDirection, TagMode};
+
+        fn element(direction: ProofElementDirection) -> ProofElement<sha2::Sha256, 32, 64> {
+            ProofElement::new([0x5a; 32], direction)
+        }
+
+        fn query_everything(tree: &Tree) {
+            let _ = tree.len();
+            let _ = tree.root();
+            let _ = tree.root_ref();
+            let _ = tree.root_array();
+            let _ = tree.root_or_empty();
+            let _ = tree.prove_all();
+
+            for i in [0, 1, 2, 7, usize::MAX] {
+                let _ = tree.path_indices(i);
+            }
+
+            for data in [&b""[..], &[0x00], &[0xff; 100]] {
+                let proof = tree.create_proof(data);
+                let _ = tree.verify(data, proof.as_deref().unwrap_or_default());
+            }
+        }
+
+        #[test]
+        fn empty_tree() {
+            let mut tree = Tree::new();
+
+            query_everything(&tree);
+            assert!(tree.create_proof(&[0x00]).is_none());
+            assert!(tree.update(0, &[0x00]).is_err());
+            assert!(tree.update(usize::MAX, &[0x00]).is_err());
+        }
+
+        #[test]
+        fn corrupted_nodes() {
+            let mut nodes = Tree::from_leaves([[0u8], [1], [2], [3], [4]])
+                .unwrap()
+                .into_nodes();
+            nodes[3] = vec![0; 32];
+            nodes[4] = vec![0xff; 32];
+
+            let tree = Tree::from_nodes(nodes).unwrap();
+            query_everything(&tree);
+
+            assert!(Tree::from_nodes(vec![vec![0; 32]; 4]).is_err());
+            assert!(Tree::from_nodes(vec![vec![0; 3]]).is_err());
+        }
+
+        #[test]
+        fn malformed_proofs_and_roots() {
+            let tree = Tree::from_leaves([[0u8], [1], [2]]).unwrap();
+            let root = tree.root().unwrap();
+            let good = proof_for(&tree, 1);
+
+            let proofs = [
+                vec![],
+                vec![element(ProofElementDirection::Left)],
+                vec![element(ProofElementDirection::Right)],
+                vec![element(ProofElementDirection::Left); 300],
+                good,
+            ];
+
+            // siblings of the wrong width never make it into an element
+            for len in [0, 31, 33] {
+                assert!(
+                    ProofElement::<sha2::Sha256, 32, 64>::from_slice(
+                        &vec![0x5a; len],
+                        ProofElementDirection::Left
+                    )
+                    .is_err()
+                );
+            }
+
+            for proof in &proofs {
+                for to_match in [
+                    &[][..],
+                    &root[..16],
+                    &root,
+                    &[root.clone(), root.clone()].concat(),
+                ] {
+                    let _ = Tree::verify_proof(&[0x01], proof, to_match);
+                    let _ = Tree::verify_proof_detailed(&[0x01], proof, to_match);
+                    let _ = Tree::verify_proof_at_index(
+                        &[0x01],
+                        usize::MAX,
+                        proof,
+                        to_match,
+                        TagMode::Block,
+                    );
+                }
+            }
+
+            for (index, size) in [(0, 0), (5, 3), (usize::MAX, usize::MAX), (0, usize::MAX)] {
+                assert!(!Tree::verify_proof_derived(
+                    &[0x01],
+                    index,
+                    size,
+                    &[[0; 32]; 3],
+                    &root,
+                    TagMode::Block
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn roots_by_prefix() {
+        let leaves: Vec<[u8; 1]> = (0..10u8).map(|i| [i]).collect();
+        let roots = Tree::roots_by_prefix(&leaves).unwrap();
+
+        assert_eq!(roots.len(), leaves.len());
+        for (i, root) in roots.iter().enumerate() {
+            let prefix = Tree::from_leaves(&leaves[..=i]).unwrap();
+            assert_eq!(Some(root), prefix.root().as_ref());
+        }
+
+        assert!(
+            Tree::roots_by_prefix(Vec::<Vec<u8>>::new())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zeroize")]
+    fn zeroize() {
+        use zeroize::Zeroize;
+
+        let leaves: Vec<[u8; 1]> = (0..5u8).map(|i| [i]).collect();
+        let (mut tree, mut proofs) = Tree::build_with_proofs(&leaves).unwrap();
+
+        // hashing with the feature on must not change any output
+        let hex = crate::hex::encode(&tree.root().unwrap());
+        assert_eq!(
+            hex,
+            "43680581585abed181b1eec574d755598bd9773593cb15dda0898c6110a65909"
+        );
+
+        tree.zeroize();
+        assert_eq!(tree.len(), 5);
+        assert!(tree.tree.iter().all(|node| node.iter().all(|&b| b == 0)));
+        assert_eq!(tree.root_ref(), Some(&[0u8; 32][..]));
+
+        // Vec::zeroize would clear the proof and leave nothing to check
+        assert_eq!(proofs[0].len(), 3);
+        for e in &mut proofs[0] {
+            e.zeroize();
+        }
+        assert_eq!(proofs[0].len(), 3);
+        assert!(proofs[0].iter().all(|e| e.hash == [0; 32]));
+    }
+
+    #[test]
+    #[cfg(feature = "keyed")]
+    fn keyed_leaves() {
+        let keyed = |key: &[u8], leaves: &[[u8; 1]]| {
+            let mut tree = Tree::builder().hmac_key(key).build();
+            for leaf in leaves {
+                tree.add(leaf).unwrap();
+            }
+            tree
+        };
+
+        // H(0x01 * 32 || HMAC-SHA256(key, data)), from python's hmac module
+        let mut single = keyed(b"key one", &[]);
+        single.add(b"leaf").unwrap();
+        assert!(single.is_keyed());
+        assert_eq!(
+            single.root_hex_be().unwrap(),
+            "7ee1f826cd8c0a8f482c3a83ec4cc24d5d1aac359dd31b78864fc9200faf3565"
+        );
+
+        let leaves: Vec<[u8; 1]> = (0..6u8).map(|i| [i]).collect();
+        let one = keyed(b"key one", &leaves);
+        let two = keyed(b"key two", &leaves);
+        let plain = Tree::from_leaves(&leaves).unwrap();
+
+        let roots = [
+            one.root().unwrap(),
+            two.root().unwrap(),
+            plain.root().unwrap(),
+        ];
+        assert_ne!(roots[0], roots[1]);
+        assert_ne!(roots[0], roots[2]);
+        assert_ne!(roots[1], roots[2]);
+        assert!(one != two);
+
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = one.create_proof(leaf).unwrap();
+            assert_eq!(
+                proof.iter().map(|e| &e.hash).collect::<Vec<_>>(),
+                one.create_proof_by_index(i)
+                    .unwrap()
+                    .iter()
+                    .map(|e| &e.hash)
+                    .collect::<Vec<_>>()
+            );
+
+            assert!(Tree::verify_proof_keyed(
+                b"key one",
+                leaf,
+                &proof,
+                &roots[0],
+                TagMode::Block
+            ));
+            assert!(one.verify(leaf, &proof));
+            assert!(!Tree::verify_proof_keyed(
+                b"key two",
+                leaf,
+                &proof,
+                &roots[0],
+                TagMode::Block
+            ));
+            assert!(!Tree::verify_proof_keyed(
+                b"key one",
+                b"x",
+                &proof,
+                &roots[0],
+                TagMode::Block
+            ));
+            assert!(!Tree::verify_proof(leaf, &proof, &roots[0]));
+        }
+
+        // the key is not encoded, so the tree cannot be decoded without it
+        assert_eq!(one.to_bytes()[1], 4);
+        assert!(Tree::from_bytes(&one.to_bytes()).is_err());
+
+        // nor can a range proof be checked without it
+        assert!(one.prove_range(0, 2).is_none());
+
+        // the key combines with either tag mode and with index binding
+        for (bind, tag_mode) in [
+            (false, TagMode::None),
+            (true, TagMode::Block),
+            (true, TagMode::None),
+        ] {
+            let mut tree = Tree::builder()
+                .hmac_key(b"key one")
+                .bind_index(bind)
+                .tag_mode(tag_mode)
+                .build();
+            for leaf in &leaves {
+                tree.add(leaf).unwrap();
+            }
+            let root = tree.root().unwrap();
+
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = tree.create_proof_by_index(i).unwrap();
+                let verified = if bind {
+                    Tree::verify_proof_keyed_at_index(b"key one", leaf, i, &proof, &root, tag_mode)
+                } else {
+                    Tree::verify_proof_keyed(b"key one", leaf, &proof, &root, tag_mode)
+                };
+                assert!(verified);
+                assert!(!Tree::verify_proof_keyed_at_index(
+                    b"key one",
+                    leaf,
+                    i + 1,
+                    &proof,
+                    &root,
+                    tag_mode
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn equality() {
+        let a = Tree::from_leaves([[0u8], [1], [2]]).unwrap();
+        let b = Tree::from_leaves([[0u8], [1], [2]]).unwrap();
+        let c = Tree::from_leaves([[0u8], [1], [3]]).unwrap();
+
+        assert!(a == b);
+        assert!(a != c);
+        assert!(a != Tree::new());
+        assert!(Tree::new() == Tree::new());
+
+        // same root, different structure underneath
+        let mut nodes = a.tree.clone();
+        nodes[0] = vec![0xff; 32];
+        let forged = Tree::from_nodes(nodes).unwrap();
+        assert_eq!(forged.root(), a.root());
+        assert!(a != forged);
+    }
+
+    #[test]
+    fn proof_accumulator() {
+        let tree = Tree::from_leaves((0..6u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+
+        for i in 0..6u8 {
+            let proof = proof_for(&tree, i);
+
+            let mut acc = ProofAccumulator::<Sha256, 32, 64>::new(&[i], TagMode::Block);
+            for element in proof.iter().cloned() {
+                acc.push(element);
+            }
+            assert_eq!(acc.finish(&root), Tree::verify_proof(&[i], &proof, &root));
+        }
+
+        let mut acc = ProofAccumulator::<Sha256, 32, 64>::new(&[0], TagMode::Block);
+        for mut element in proof_for(&tree, 0) {
+            element.hash[0] ^= 1;
+            acc.push(element);
+        }
+        assert!(!acc.finish(&root));
+    }
+
+    mod without_reset {
+        use digest::{FixedOutput, HashMarker, Output, OutputSizeUser, Update};
+        use sha2::Sha256;
+
+        use crate::merkle::MerkleTree;
+
+        // sha256 with only by-value finalization: Digest, but not FixedOutputReset
+        #[derive(Clone, Debug, Default)]
+        struct OwnedSha256(Sha256);
+
+        impl HashMarker for OwnedSha256 {}
+
+        impl OutputSizeUser for OwnedSha256 {
+            type OutputSize = <Sha256 as OutputSizeUser>::OutputSize;
+        }
+
+        impl Update for OwnedSha256 {
+            fn update(&mut self, data: &[u8]) {
+                Update::update(&mut self.0, data);
+            }
+        }
+
+        impl FixedOutput for OwnedSha256 {
+            fn finalize_into(self, out: &mut Output<Self>) {
+                FixedOutput::finalize_into(self.0, out);
+            }
+        }
+
+        #[test]
+        fn digest_without_reset_matches_sha256() {
+            type Owned = MerkleTree<OwnedSha256, 32, 64>;
+            type Plain = MerkleTree<Sha256, 32, 64>;
+
+            let leaves = (0..9u8).map(|i| [i]).collect::<Vec<_>>();
+            let owned = Owned::from_leaves(&leaves).unwrap();
+            let plain = Plain::from_leaves(&leaves).unwrap();
+            let root = owned.root().unwrap();
+
+            assert_eq!(Some(root.clone()), plain.root());
+            assert_eq!(owned.order_commitment(), plain.order_commitment());
+            assert_eq!(Owned::empty_root(), Plain::empty_root());
+
+            for (i, leaf) in leaves.iter().enumerate() {
+                let proof = owned.create_proof_by_index(i).unwrap();
+                assert!(Owned::verify_proof(leaf, &proof, &root));
+                assert!(Plain::verify_proof(
+                    leaf,
+                    &plain.create_proof_by_index(i).unwrap(),
+                    &root
+                ));
+            }
+        }
+    }
+
+    mod auto_traits {
+        use digest::{
+            FixedOutput, FixedOutputReset, HashMarker, Output, OutputSizeUser, Reset, Update,
+        };
+        use sha2::Sha256;
+        use std::marker::PhantomData;
+
+        use crate::merkle::{MerkleTree, MerkleTreeBuilder, ProofAccumulator, ProofElement};
+        use crate::sparse::EmptyHashes;
+
+        // a usable digest that is neither Send nor Sync
+        #[derive(Clone, Debug, Default)]
+        struct LocalSha256(Sha256, PhantomData<*const ()>);
+
+        impl HashMarker for LocalSha256 {}
+
+        impl OutputSizeUser for LocalSha256 {
+            type OutputSize = <Sha256 as OutputSizeUser>::OutputSize;
+        }
+
+        impl Update for LocalSha256 {
+            fn update(&mut self, data: &[u8]) {
+                Update::update(&mut self.0, data);
+            }
+        }
+
+        impl FixedOutput for LocalSha256 {
+            fn finalize_into(self, out: &mut Output<Self>) {
+                FixedOutput::finalize_into(self.0, out);
+            }
+        }
+
+        impl Reset for LocalSha256 {
+            fn reset(&mut self) {
+                Reset::reset(&mut self.0);
+            }
+        }
+
+        impl FixedOutputReset for LocalSha256 {
+            fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+                FixedOutputReset::finalize_into_reset(&mut self.0, out);
+            }
+        }
+
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        #[test]
+        fn send_sync_without_sync_digest() {
+            assert_send_sync::<MerkleTree<LocalSha256, 32, 64>>();
+            assert_send_sync::<ProofElement<LocalSha256, 32, 64>>();
+            assert_send_sync::<ProofAccumulator<LocalSha256, 32, 64>>();
+            assert_send_sync::<MerkleTreeBuilder<LocalSha256, 32, 64>>();
+            assert_send_sync::<EmptyHashes<LocalSha256, 32, 64, 4>>();
+
+            let mut local = MerkleTree::<LocalSha256, 32, 64>::new();
+            let mut plain = MerkleTree::<Sha256, 32, 64>::new();
+            for i in 0..5u8 {
+                local.add(&[i]).unwrap();
+                plain.add(&[i]).unwrap();
+            }
+
+            let root = std::thread::spawn(move || local.root()).join().unwrap();
+            assert_eq!(root, plain.root());
+        }
     }
 }
@@ -0,0 +1,288 @@
+use sha3::{Digest, Keccak256};
+
+use crate::hex;
+
+// trees and proofs accepted by openzeppelin's MerkleProof.verify, laid out the
+// way @openzeppelin/merkle-tree's StandardMerkleTree builds them:
+//
+// - a leaf is keccak256(keccak256(abi.encode(values))). the double hash keeps a
+//   64-byte leaf preimage from passing as an internal node
+// - internal nodes hash the sorted pair, keccak256(min(a, b) || max(a, b)), so
+//   proofs carry no directions and the verifier needs no leaf index
+// - leaf hashes are sorted and stored in reverse at the end of a 2n - 1 node
+//   array whose node i has children 2i + 1 and 2i + 2, with the root at 0
+pub struct OpenZeppelinTree {
+    tree: Vec<[u8; 32]>,
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+    Keccak256::new()
+        .chain_update(lo)
+        .chain_update(hi)
+        .finalize()
+        .into()
+}
+
+impl OpenZeppelinTree {
+    // the standard double-hashed leaf over an abi-encoded value tuple
+    #[must_use]
+    pub fn leaf_hash(abi_encoded: &[u8]) -> [u8; 32] {
+        keccak256(&keccak256(abi_encoded))
+    }
+
+    pub fn from_values<I>(abi_encoded: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        Self::from_leaf_hashes(
+            abi_encoded
+                .into_iter()
+                .map(|value| Self::leaf_hash(value.as_ref())),
+        )
+    }
+
+    // for trees whose leaves are hashed some other way, e.g. a single keccak256
+    pub fn from_leaf_hashes<I>(leaves: I) -> Self
+    where
+        I: IntoIterator<Item = [u8; 32]>,
+    {
+        let mut leaves = leaves.into_iter().collect::<Vec<_>>();
+        leaves.sort_unstable();
+
+        let Some(len) = (leaves.len() * 2).checked_sub(1) else {
+            return Self { tree: vec![] };
+        };
+
+        let mut tree = vec![[0; 32]; len];
+        for (i, leaf) in leaves.into_iter().enumerate() {
+            tree[len - 1 - i] = leaf;
+        }
+        for i in (0..len / 2).rev() {
+            tree[i] = hash_pair(&tree[2 * i + 1], &tree[2 * i + 2]);
+        }
+
+        Self { tree }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tree.len().div_ceil(2)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<[u8; 32]> {
+        self.tree.first().copied()
+    }
+
+    // the ordered sibling list from the leaf up, as MerkleProof.verify takes it
+    #[must_use]
+    pub fn create_proof(&self, leaf: &[u8; 32]) -> Option<Vec<[u8; 32]>> {
+        let first_leaf = self.tree.len() / 2;
+        let mut i = first_leaf + self.tree[first_leaf..].iter().position(|n| n == leaf)?;
+
+        let mut proof = vec![];
+        while i > 0 {
+            let sibling = if i % 2 == 1 { i + 1 } else { i - 1 };
+            proof.push(self.tree[sibling]);
+            i = (i - 1) / 2;
+        }
+
+        Some(proof)
+    }
+
+    #[must_use]
+    pub fn create_proof_for_value(&self, abi_encoded: &[u8]) -> Option<Vec<[u8; 32]>> {
+        self.create_proof(&Self::leaf_hash(abi_encoded))
+    }
+
+    // MerkleProof.verify(proof, root, leaf), byte for byte
+    #[must_use]
+    pub fn verify(proof: &[[u8; 32]], root: &[u8; 32], leaf: &[u8; 32]) -> bool {
+        proof
+            .iter()
+            .fold(*leaf, |acc, sibling| hash_pair(&acc, sibling))
+            == *root
+    }
+
+    // a proof as a solidity calldata literal: ["0x..", "0x.."]
+    #[must_use]
+    pub fn proof_to_hex_array(proof: &[[u8; 32]]) -> String {
+        let items = proof
+            .iter()
+            .map(|hash| format!("\"0x{}\"", hex::encode(hash)))
+            .collect::<Vec<_>>();
+
+        format!("[{}]", items.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OpenZeppelinTree, keccak256};
+    use crate::hex::{self, encode as hex};
+
+    // abi.encode(address, uint256)
+    fn encode(address: u8, amount: u128) -> Vec<u8> {
+        let mut out = vec![0; 64];
+        out[12..32].fill(address);
+        out[48..].copy_from_slice(&amount.to_be_bytes());
+        out
+    }
+
+    // abi.encode(address, uint256) for an address given as 40 hex digits
+    fn encode_account(address: &str, amount: u128) -> Vec<u8> {
+        let mut out = vec![0; 64];
+        out[12..32].copy_from_slice(&hex::decode(address).unwrap());
+        out[48..].copy_from_slice(&amount.to_be_bytes());
+        out
+    }
+
+    // abi.encode(string) for a string of at most 32 bytes: offset, length, data
+    fn encode_string(s: &str) -> Vec<u8> {
+        let mut out = vec![0; 96];
+        out[31] = 0x20;
+        out[63] = s.len() as u8;
+        out[64..64 + s.len()].copy_from_slice(s.as_bytes());
+        out
+    }
+
+    fn unhex(s: &str) -> [u8; 32] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+
+    // StandardMerkleTree.of([["a"], ["b"], ["c"]], ["string"]), the tree
+    // @openzeppelin/merkle-tree's own render test prints, node for node
+    #[test]
+    fn standard_merkle_tree_three_strings() {
+        let values = ["a", "b", "c"].map(encode_string);
+        let tree = OpenZeppelinTree::from_values(&values);
+
+        let expected = [
+            "f2129b5a697531ef818f644564a6552b35c549722385bc52aa7fe46c0b5f46b1",
+            "fa914d99a18dc32d9725b3ef1c50426deb40ec8d0885dac8edcc5bfd6d030016",
+            "9cf5a63718145ba968a01c1d557020181c5b252f665cf7386d370eddb176517b",
+            "9c15a6a0eaeed500fd9eed4cbeab71f797cefcc67bfd46683e4d2e6ff7f06d1c",
+            "19ba6c6333e0e9a15bf67523e0676e2f23eb8e574092552d5e888c64a4bb3681",
+        ];
+        assert_eq!(tree.tree, expected.map(unhex));
+
+        let root = tree.root().unwrap();
+        for value in &values {
+            let leaf = OpenZeppelinTree::leaf_hash(value);
+            let proof = tree.create_proof(&leaf).unwrap();
+            assert!(OpenZeppelinTree::verify(&proof, &root, &leaf));
+        }
+    }
+
+    // StandardMerkleTree.of(values, ["address", "uint256"]) over the first five
+    // hardhat accounts, as generated with the javascript library
+    #[test]
+    fn standard_merkle_tree_five_accounts() {
+        let values = [
+            ("f39fd6e51aad88f6f4ce6ab8827279cfffb92266", 10_000),
+            ("70997970c51812dc3a010c7d01b50e0d17dc79c8", 1_000),
+            ("3c44cdddb6a900fa2b585dd299e03d12fa4293bc", 100),
+            ("90f79bf6eb2c4f870365e785982e1f101e93b906", 10),
+            ("15d34aaf54267db7d7c367839aaf71a00a2c6a65", 1),
+        ]
+        .map(|(address, amount)| encode_account(address, amount));
+        let tree = OpenZeppelinTree::from_values(&values);
+        let root = tree.root().unwrap();
+
+        assert_eq!(
+            hex(&root),
+            "2b4b963c699c531f94ca8f8a0ef76c5d28f067d79927c035a44296190c2d8029"
+        );
+        assert_eq!(
+            tree.create_proof_for_value(&values[0]).unwrap(),
+            [
+                "8ee56d16226ff6684927054c33cd505c4eee1ebabbffe198460d00cb083aaebd",
+                "fa31eb8d65ff2307b7026df667a06a19aade0151ed701ed2307295ae4fa48364",
+                "f0768f444c5a27a6bb7c9203b0b5b147e501ff7b7784e0363e5751590962b034",
+            ]
+            .map(unhex)
+        );
+
+        for value in &values {
+            let leaf = OpenZeppelinTree::leaf_hash(value);
+            let proof = tree.create_proof(&leaf).unwrap();
+            assert!(OpenZeppelinTree::verify(&proof, &root, &leaf));
+        }
+    }
+
+    // the example from the @openzeppelin/merkle-tree readme, built with
+    // StandardMerkleTree.of(values, ["address", "uint256"])
+    #[test]
+    fn standard_merkle_tree_readme_example() {
+        let values = [
+            encode(0x11, 5_000_000_000_000_000_000),
+            encode(0x22, 2_500_000_000_000_000_000),
+        ];
+        let tree = OpenZeppelinTree::from_values(&values);
+        let root = tree.root().unwrap();
+
+        assert_eq!(
+            hex(&root),
+            "d4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77"
+        );
+
+        let proof = tree.create_proof_for_value(&values[0]).unwrap();
+        assert_eq!(
+            OpenZeppelinTree::proof_to_hex_array(&proof),
+            "[\"0xb92c48e9d7abe27fd8dfd6b5dfdbfb1c9a463f80c712b66f3a5180a090cccafc\"]"
+        );
+
+        for value in &values {
+            let leaf = OpenZeppelinTree::leaf_hash(value);
+            let proof = tree.create_proof(&leaf).unwrap();
+            assert!(OpenZeppelinTree::verify(&proof, &root, &leaf));
+        }
+    }
+
+    #[test]
+    fn proofs_verify_for_every_leaf_count() {
+        for count in 1..=9u8 {
+            let values = (0..count)
+                .map(|i| encode(i, u128::from(i) * 1000))
+                .collect::<Vec<_>>();
+            let tree = OpenZeppelinTree::from_values(&values);
+            let root = tree.root().unwrap();
+            assert_eq!(tree.len(), count as usize);
+
+            for value in &values {
+                let leaf = OpenZeppelinTree::leaf_hash(value);
+                let proof = tree.create_proof(&leaf).unwrap();
+                assert!(OpenZeppelinTree::verify(&proof, &root, &leaf));
+
+                // a single hash of the encoding is not the leaf
+                assert!(!OpenZeppelinTree::verify(&proof, &root, &keccak256(value)));
+            }
+
+            // insertion order does not matter, leaves are sorted first
+            let reversed = OpenZeppelinTree::from_values(values.iter().rev());
+            assert_eq!(reversed.root(), Some(root));
+        }
+
+        assert!(
+            OpenZeppelinTree::from_values(Vec::<Vec<u8>>::new())
+                .root()
+                .is_none()
+        );
+        assert!(
+            OpenZeppelinTree::from_values([encode(1, 1)])
+                .create_proof_for_value(&encode(2, 2))
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use digest::Digest;
+use pyo3::{
+    create_exception,
+    exceptions::{PyIndexError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use sha2::{Sha256, Sha512};
+use sha3::{Keccak256, Sha3_256};
+use std::fmt::Debug;
+
+use crate::{error::MerkleError, merkle::MerkleTree};
+
+// python bindings, built as the `merkle` extension module with maturin (see
+// pyproject.toml). trees hash exactly as MerkleTree does at full digest width,
+// so roots and compact proofs match the rust side byte for byte.
+//
+// a LeafOutOfBounds error raises IndexError, every other MerkleError raises
+// merkle.MerkleError, a ValueError subclass, carrying the error's message.
+create_exception!(merkle, PyMerkleError, PyValueError);
+
+fn py_err(e: MerkleError) -> PyErr {
+    match e {
+        MerkleError::LeafOutOfBounds { .. } => PyIndexError::new_err(e.to_string()),
+        _ => PyMerkleError::new_err(e.to_string()),
+    }
+}
+
+enum Tree {
+    Sha256(MerkleTree<Sha256, 32, 64>),
+    Sha512(MerkleTree<Sha512, 64, 128>),
+    Sha3_256(MerkleTree<Sha3_256, 32, 64>),
+    Keccak256(MerkleTree<Keccak256, 32, 64>),
+}
+
+// runs $body with $tree bound to whichever MerkleTree the variant holds
+macro_rules! with_tree {
+    ($value:expr, $tree:ident => $body:expr) => {
+        match $value {
+            Tree::Sha256($tree) => $body,
+            Tree::Sha512($tree) => $body,
+            Tree::Sha3_256($tree) => $body,
+            Tree::Keccak256($tree) => $body,
+        }
+    };
+}
+
+impl Tree {
+    const ALGORITHMS: [&str; 4] = ["sha256", "sha512", "sha3_256", "keccak256"];
+
+    fn new(hash: &str) -> Result<Self, MerkleError> {
+        Ok(match hash {
+            "sha256" => Self::Sha256(MerkleTree::new()),
+            "sha512" => Self::Sha512(MerkleTree::new()),
+            "sha3_256" => Self::Sha3_256(MerkleTree::new()),
+            "keccak256" => Self::Keccak256(MerkleTree::new()),
+            _ => return Err(MerkleError::UnsupportedAlgorithm(hash.to_owned())),
+        })
+    }
+}
+
+fn prove<S: Debug + Digest, const N: usize, const ND: usize>(
+    tree: &MerkleTree<S, N, ND>,
+    index: usize,
+) -> Result<Vec<u8>, MerkleError> {
+    let proof = tree
+        .create_proof_by_index(index)
+        .ok_or(MerkleError::LeafOutOfBounds {
+            index,
+            len: tree.len(),
+        })?;
+
+    MerkleTree::<S, N, ND>::proof_to_bytes(&proof)
+}
+
+fn verify_with<S: Debug + Digest, const N: usize, const ND: usize>(
+    data: &[u8],
+    proof: &[u8],
+    root: &[u8],
+) -> Result<bool, MerkleError> {
+    let proof = MerkleTree::<S, N, ND>::proof_from_bytes(proof)?;
+
+    MerkleTree::<S, N, ND>::verify_proof_detailed(data, &proof, root)
+}
+
+fn verify_proof(data: &[u8], proof: &[u8], root: &[u8], hash: &str) -> Result<bool, MerkleError> {
+    match hash {
+        "sha256" => verify_with::<Sha256, 32, 64>(data, proof, root),
+        "sha512" => verify_with::<Sha512, 64, 128>(data, proof, root),
+        "sha3_256" => verify_with::<Sha3_256, 32, 64>(data, proof, root),
+        "keccak256" => verify_with::<Keccak256, 32, 64>(data, proof, root),
+        _ => Err(MerkleError::UnsupportedAlgorithm(hash.to_owned())),
+    }
+}
+
+#[pyclass(name = "MerkleTree", module = "merkle")]
+struct PyMerkleTree {
+    tree: Tree,
+}
+
+#[pymethods]
+impl PyMerkleTree {
+    #[new]
+    #[pyo3(signature = (hash = "sha256"))]
+    fn new(hash: &str) -> PyResult<Self> {
+        Ok(Self {
+            tree: Tree::new(hash).map_err(py_err)?,
+        })
+    }
+
+    // the new leaf's index
+    fn add(&mut self, data: &[u8]) -> PyResult<usize> {
+        with_tree!(&mut self.tree, tree => {
+            tree.add(data).map_err(py_err)?;
+            Ok(tree.len() - 1)
+        })
+    }
+
+    // an empty tree reports MerkleTree::empty_root, the same for every tree of
+    // the algorithm
+    fn root<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &with_tree!(&self.tree, tree => tree.root_or_empty()))
+    }
+
+    // the compact binary proof for the leaf at index
+    fn prove<'py>(&self, py: Python<'py>, index: usize) -> PyResult<Bound<'py, PyBytes>> {
+        let proof = with_tree!(&self.tree, tree => prove(tree, index)).map_err(py_err)?;
+
+        Ok(PyBytes::new(py, &proof))
+    }
+
+    fn __len__(&self) -> usize {
+        with_tree!(&self.tree, tree => tree.len())
+    }
+}
+
+// False for a well-formed proof that does not reach root; raises for a malformed
+// proof, a root of the wrong width or an unknown hash
+#[pyfunction]
+#[pyo3(signature = (data, proof, root, hash = "sha256"))]
+fn verify(data: &[u8], proof: &[u8], root: &[u8], hash: &str) -> PyResult<bool> {
+    verify_proof(data, proof, root, hash).map_err(py_err)
+}
+
+#[pymodule]
+fn merkle(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMerkleTree>()?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add("MerkleError", m.py().get_type::<PyMerkleError>())?;
+    m.add("ALGORITHMS", Tree::ALGORITHMS)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::{Tree, prove, verify_proof};
+    use crate::{error::MerkleError, hex, merkle::MerkleTree};
+
+    // the fixtures python/tests/test_merkle.py checks the extension against:
+    // roots over leaves 0x00..0x04 under each supported hash
+    const ROOT: &str = "43680581585abed181b1eec574d755598bd9773593cb15dda0898c6110a65909";
+    const ROOTS: [(&str, &str); 4] = [
+        ("sha256", ROOT),
+        (
+            "sha512",
+            "77800ff88913029a0ac609e82a7561b4450ea1d95e7cca5b8ea55d5d06f7a00a\
+             aede43967873d41536ef293c5aedf7128b6d983575562d00fc5af75b296a73c3",
+        ),
+        (
+            "sha3_256",
+            "145f5b6b4d430a3b50f2bf6a363e8ecec1b18ae185aa31a081193a496edf94cd",
+        ),
+        (
+            "keccak256",
+            "a87177fc26162d9168edcd32c1a28e5598bc36faff45f1871a919d264509b609",
+        ),
+    ];
+
+    #[test]
+    fn helpers_match_the_rust_api() {
+        let tree = MerkleTree::<Sha256, 32, 64>::from_leaves((0..5u8).map(|i| [i])).unwrap();
+        let root = tree.root().unwrap();
+        assert_eq!(tree.root_hex_be().unwrap(), ROOT);
+
+        for i in 0..5u8 {
+            let proof = prove(&tree, i.into()).unwrap();
+            assert_eq!(verify_proof(&[i], &proof, &root, "sha256"), Ok(true));
+            assert_eq!(verify_proof(&[9], &proof, &root, "sha256"), Ok(false));
+            assert_eq!(verify_proof(&[i], &proof, &root, "sha3_256"), Ok(false));
+        }
+
+        assert_eq!(
+            prove(&tree, 5),
+            Err(MerkleError::LeafOutOfBounds { index: 5, len: 5 })
+        );
+        assert!(verify_proof(&[0], &[0, 0, 0, 1], &root, "sha256").is_err());
+
+        assert_eq!(
+            verify_proof(&[0], &[], &root, "md5"),
+            Err(MerkleError::UnsupportedAlgorithm("md5".into()))
+        );
+        assert_eq!(
+            Tree::new("sha1").err(),
+            Some(MerkleError::UnsupportedAlgorithm("sha1".into()))
+        );
+    }
+
+    #[test]
+    fn every_algorithm_matches_its_fixture() {
+        assert_eq!(ROOTS.map(|(hash, _)| hash), Tree::ALGORITHMS);
+
+        for (hash, expected) in ROOTS {
+            let mut tree = Tree::new(hash).unwrap();
+            let root = with_tree!(&mut tree, tree => {
+                for i in 0..5u8 {
+                    tree.add(&[i]).unwrap();
+                }
+                tree.root().unwrap()
+            });
+            assert_eq!(hex::encode(&root), expected, "{hash}");
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use digest::Digest;
+use std::{collections::BTreeMap, fmt::Debug};
+
+use crate::{error::MerkleError, flat, merkle::MerkleTree};
+
+// a tree for dynamo/cassandra style anti-entropy repair: leaf i covers the i-th
+// of 2^depth equal ranges of the key-hash space, so two replicas holding the same
+// keys line up leaf for leaf whatever order they saw writes in, and comparing
+// their trees finds the ranges to stream across.
+//
+// a key falls in the range given by the top depth bits of H(key). each entry
+// hashes to H(len(key) as u64 be || key || value) and a bucket's leaf data is
+// its entry hashes back to back in H(key) order, so a bucket's hash does not
+// depend on the order entries arrived in. a key holds one value: inserting it
+// again replaces the entry.
+pub type RangeId = usize;
+
+pub struct RangeMerkleTree<S: Digest, const N: usize, const ND: usize> {
+    depth: u32,
+    // per range, its entry hashes by key hash
+    buckets: Vec<BTreeMap<Vec<u8>, Vec<u8>>>,
+    tree: MerkleTree<S, N, ND>,
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> RangeMerkleTree<S, N, ND> {
+    // 2^24 ranges already take a gigabyte of nodes at N = 32
+    pub const MAX_DEPTH: u32 = 24;
+
+    // a tree of 2^depth empty ranges
+    pub fn new(depth: u32) -> Result<Self, MerkleError> {
+        if depth > Self::MAX_DEPTH {
+            return Err(MerkleError::CapacityExceeded {
+                max: 1 << Self::MAX_DEPTH,
+            });
+        }
+
+        let buckets = vec![BTreeMap::new(); 1 << depth];
+        let tree = MerkleTree::from_leaves(buckets.iter().map(|_| []))?;
+
+        Ok(Self {
+            depth,
+            buckets,
+            tree,
+        })
+    }
+
+    pub fn from_pairs<I, K, V>(depth: u32, pairs: I) -> Result<Self, MerkleError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let mut tree = Self::new(depth)?;
+        for (key, value) in pairs {
+            let (key, value) = (key.as_ref(), value.as_ref());
+            let key_hash = S::digest(key);
+            let range = tree.range_of_hash(&key_hash);
+            tree.buckets[range].insert(key_hash.to_vec(), Self::entry_hash(key, value));
+        }
+
+        // one tree build rather than an update per pair
+        tree.tree = MerkleTree::from_leaves(tree.buckets.iter().map(Self::leaf_data))?;
+
+        Ok(tree)
+    }
+
+    // adds key, or replaces the value it holds
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) -> Result<(), MerkleError> {
+        let key_hash = S::digest(key);
+        let range = self.range_of_hash(&key_hash);
+
+        let mut bucket = self.buckets[range].clone();
+        bucket.insert(key_hash.to_vec(), Self::entry_hash(key, value));
+
+        // updated aside so a failure leaves the tree untouched
+        self.tree.update(range, &Self::leaf_data(&bucket))?;
+        self.buckets[range] = bucket;
+
+        Ok(())
+    }
+
+    fn leaf_data(bucket: &BTreeMap<Vec<u8>, Vec<u8>>) -> Vec<u8> {
+        bucket.values().flatten().copied().collect()
+    }
+
+    fn entry_hash(key: &[u8], value: &[u8]) -> Vec<u8> {
+        S::new()
+            .chain_update((key.len() as u64).to_be_bytes())
+            .chain_update(key)
+            .chain_update(value)
+            .finalize()[..N]
+            .to_vec()
+    }
+
+    // the range H(key) falls in: its top depth bits
+    #[must_use]
+    pub fn range_of(&self, key: &[u8]) -> RangeId {
+        self.range_of_hash(&S::digest(key))
+    }
+
+    fn range_of_hash(&self, hash: &[u8]) -> RangeId {
+        if self.depth == 0 {
+            return 0;
+        }
+
+        let prefix = hash
+            .iter()
+            .take(4)
+            .fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+
+        (prefix >> (32 - self.depth)) as RangeId
+    }
+
+    #[must_use]
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    #[must_use]
+    pub fn range_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    // the number of entries in range
+    #[must_use]
+    pub fn range_len(&self, range: RangeId) -> Option<usize> {
+        self.buckets.get(range).map(BTreeMap::len)
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.tree.root()
+    }
+
+    #[must_use]
+    pub fn as_tree(&self) -> &MerkleTree<S, N, ND> {
+        &self.tree
+    }
+
+    // the ranges whose contents differ, in order. only subtrees whose roots
+    // disagree are descended into, so replicas that mostly agree compare a few
+    // nodes per differing range rather than every leaf
+    pub fn diff_ranges(&self, other: &Self) -> Result<Vec<RangeId>, MerkleError> {
+        if self.depth != other.depth {
+            return Err(MerkleError::Structural(
+                "trees split the key space into different ranges",
+            ));
+        }
+
+        let (ours, theirs) = (self.tree.as_nodes(), other.tree.as_nodes());
+        let mut ranges = vec![];
+        let mut pending = flat::lpbt_root(ours.len()).into_iter().collect::<Vec<_>>();
+        while let Some(node) = pending.pop() {
+            if ours[node] == theirs[node] {
+                continue;
+            }
+
+            match (
+                flat::pbt_left_child(node),
+                flat::lpbt_right_child(node, ours.len()),
+            ) {
+                // popped right before left, so ranges come out in order
+                (Some(left), Some(right)) => pending.extend([right, left]),
+                _ => ranges.push(node / 2),
+            }
+        }
+
+        Ok(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::RangeMerkleTree;
+    use crate::error::MerkleError;
+
+    type Tree = RangeMerkleTree<Sha256, 32, 64>;
+
+    fn dataset() -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..500u32)
+            .map(|i| (format!("key{i}").into_bytes(), i.to_be_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn diff_finds_the_ranges_of_changed_keys() {
+        let data = dataset();
+        let ours = Tree::from_pairs(6, data.iter().map(|(k, v)| (k, v))).unwrap();
+        assert_eq!(ours.range_count(), 64);
+        assert_eq!(
+            (0..64).map(|r| ours.range_len(r).unwrap()).sum::<usize>(),
+            500
+        );
+
+        // insertion order does not matter, nor does building in one go
+        let mut shuffled = Tree::new(6).unwrap();
+        for (key, value) in data.iter().rev() {
+            shuffled.insert(key, value).unwrap();
+        }
+        assert_eq!(shuffled.root(), ours.root());
+        assert_eq!(ours.diff_ranges(&shuffled), Ok(vec![]));
+
+        // a changed value, a missing key and an extra key
+        let mut changed = data.clone();
+        changed[17].1 = b"stale".to_vec();
+        let (missing, _) = changed.remove(230);
+        changed.push((b"extra".to_vec(), b"1".to_vec()));
+        let theirs = Tree::from_pairs(6, changed.iter().rev().map(|(k, v)| (k, v))).unwrap();
+
+        let mut expected = [&data[17].0, &missing, &b"extra".to_vec()]
+            .iter()
+            .map(|key| ours.range_of(key))
+            .collect::<Vec<_>>();
+        expected.sort_unstable();
+        expected.dedup();
+
+        assert_eq!(ours.diff_ranges(&theirs), Ok(expected.clone()));
+        assert_eq!(theirs.diff_ranges(&ours), Ok(expected));
+    }
+
+    #[test]
+    fn inserting_a_key_again_replaces_its_value() {
+        let data = dataset();
+        let mut tree = Tree::from_pairs(4, data.iter().map(|(k, v)| (k, v))).unwrap();
+        let range = tree.range_of(b"key7");
+        let len = tree.range_len(range);
+
+        tree.insert(b"key7", b"new").unwrap();
+        assert_eq!(tree.range_len(range), len);
+
+        let mut updated = data.clone();
+        updated[7].1 = b"new".to_vec();
+        let expected = Tree::from_pairs(4, updated.iter().map(|(k, v)| (k, v))).unwrap();
+        assert_eq!(tree.root(), expected.root());
+        assert_eq!(tree.diff_ranges(&expected), Ok(vec![]));
+
+        // the same holds for duplicates handed to from_pairs, the last one wins
+        let duplicated = Tree::from_pairs(
+            4,
+            data.iter()
+                .map(|(k, v)| (k.as_slice(), v.as_slice()))
+                .chain([(&b"key7"[..], &b"new"[..])]),
+        )
+        .unwrap();
+        assert_eq!(duplicated.root(), expected.root());
+    }
+
+    #[test]
+    fn depths_must_match() {
+        let data = dataset();
+        let single = Tree::from_pairs(0, data.iter().map(|(k, v)| (k, v))).unwrap();
+        assert_eq!(single.range_count(), 1);
+        assert_eq!(single.range_of(b"anything"), 0);
+        assert_eq!(
+            single
+                .diff_ranges(&Tree::from_pairs(0, data[1..].iter().map(|(k, v)| (k, v))).unwrap()),
+            Ok(vec![0])
+        );
+
+        assert!(
+            Tree::new(3)
+                .unwrap()
+                .diff_ranges(&Tree::new(4).unwrap())
+                .is_err()
+        );
+        assert_eq!(
+            Tree::new(Tree::MAX_DEPTH + 1).err(),
+            Some(MerkleError::CapacityExceeded {
+                max: 1 << Tree::MAX_DEPTH
+            })
+        );
+    }
+}
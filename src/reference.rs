@@ -0,0 +1,187 @@
+use digest::Digest;
+use std::marker::PhantomData;
+
+use crate::merkle::{Proof, ProofElement, ProofElementDirection};
+
+// a deliberately naive merkle tree to check the flat layout against. it shares
+// no code with MerkleTree: hashing is spelled out again from the definition and
+// the tree is a list of levels, pairing nodes left to right and carrying an odd
+// last node up unchanged. that yields the same shape as the left-perfect flat
+// layout, so roots and proofs must match exactly.
+pub struct ReferenceTree<S: Digest, const N: usize, const ND: usize> {
+    // levels[0] holds the leaf hashes, the last level holds just the root
+    levels: Vec<Vec<Vec<u8>>>,
+    _s: PhantomData<fn() -> S>,
+}
+
+impl<S: Digest, const N: usize, const ND: usize> ReferenceTree<S, N, ND> {
+    fn h(data: &[u8]) -> Vec<u8> {
+        S::digest(data)[..N].to_vec()
+    }
+
+    // H(tag^N || H(data)), truncated to N bytes
+    fn tagged(tag: u8, data: &[u8]) -> Vec<u8> {
+        let mut block = vec![tag; N];
+        block.extend(Self::h(data));
+        Self::h(&block)
+    }
+
+    #[must_use]
+    pub fn leaf_hash(data: &[u8]) -> Vec<u8> {
+        Self::tagged(1, data)
+    }
+
+    #[must_use]
+    pub fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+        Self::tagged(2, &Self::h(&[left, right].concat()))
+    }
+
+    pub fn from_leaves<I>(leaves: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut levels = vec![
+            leaves
+                .into_iter()
+                .map(|leaf| Self::leaf_hash(leaf.as_ref()))
+                .collect::<Vec<_>>(),
+        ];
+
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => Self::node_hash(left, right),
+                    [carried] => carried.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Self {
+            levels,
+            _s: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.levels.last()?.first().cloned()
+    }
+
+    // siblings from the leaf upwards. a node carried up a level has no sibling
+    // there and contributes nothing to the proof.
+    #[must_use]
+    pub fn proof(&self, mut index: usize) -> Option<Proof<S, N, ND>> {
+        if index >= self.levels[0].len() {
+            return None;
+        }
+
+        let mut proof = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if let Some(hash) = level.get(sibling) {
+                let direction = if sibling > index {
+                    ProofElementDirection::Right
+                } else {
+                    ProofElementDirection::Left
+                };
+                proof.push(ProofElement::from_slice(hash, direction).ok()?);
+            }
+            index /= 2;
+        }
+
+        Some(proof)
+    }
+
+    #[must_use]
+    pub fn verify(data: &[u8], proof: &[ProofElement<S, N, ND>], root: &[u8]) -> bool {
+        if root.len() != N {
+            return false;
+        }
+
+        let folded = proof
+            .iter()
+            .fold(Self::leaf_hash(data), |acc, e| match e.direction {
+                ProofElementDirection::Left => Self::node_hash(&e.hash, &acc),
+                ProofElementDirection::Right => Self::node_hash(&acc, &e.hash),
+            });
+
+        folded == root
+    }
+}
+
+// proptest needs an os rng, so these stay off the wasm test build
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use proptest::prelude::*;
+    use sha2::Sha256;
+
+    use super::ReferenceTree;
+    use crate::merkle::MerkleTree;
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+    type Reference = ReferenceTree<Sha256, 32, 64>;
+    type Truncated = MerkleTree<Sha256, 16, 32>;
+    type TruncatedReference = ReferenceTree<Sha256, 16, 32>;
+
+    fn leaves() -> impl Strategy<Value = Vec<Vec<u8>>> {
+        prop::collection::vec(prop::collection::vec(any::<u8>(), 0..16), 0..40)
+    }
+
+    proptest! {
+        #[test]
+        fn roots_match(leaves in leaves()) {
+            let mut grown = Tree::new();
+            for leaf in &leaves {
+                grown.add(leaf).unwrap();
+            }
+            let expected = Reference::from_leaves(&leaves).root();
+
+            prop_assert_eq!(grown.root(), expected.clone());
+            prop_assert_eq!(Tree::from_leaves(&leaves).unwrap().root(), expected);
+            prop_assert_eq!(
+                Truncated::from_leaves(&leaves).unwrap().root(),
+                TruncatedReference::from_leaves(&leaves).root()
+            );
+        }
+
+        #[test]
+        fn proofs_match_and_cross_verify(leaves in leaves(), flip in any::<prop::sample::Index>()) {
+            let tree = Tree::from_leaves(&leaves).unwrap();
+            let reference = Reference::from_leaves(&leaves);
+            let Some(root) = tree.root() else {
+                return Ok(());
+            };
+
+            for (i, (leaf, proof)) in leaves.iter().zip(tree.prove_all()).enumerate() {
+                let expected = reference.proof(i).unwrap();
+                prop_assert_eq!(
+                    Tree::proof_to_bytes(&proof).unwrap(),
+                    Tree::proof_to_bytes(&expected).unwrap()
+                );
+
+                prop_assert!(Reference::verify(leaf, &proof, &root));
+                prop_assert!(Tree::verify_proof(leaf, &expected, &root));
+
+                // both sides must also agree on what fails
+                let mut tampered = proof.clone();
+                if !tampered.is_empty() {
+                    let e = flip.index(tampered.len());
+                    tampered[e].hash[0] ^= 1;
+                }
+                let other: &[u8] = if leaf.as_slice() == b"x" { b"y" } else { b"x" };
+                for (data, proof) in [(leaf.as_slice(), &tampered), (other, &proof)] {
+                    prop_assert_eq!(
+                        Tree::verify_proof(data, proof, &root),
+                        Reference::verify(data, proof, &root)
+                    );
+                }
+            }
+
+            prop_assert!(reference.proof(leaves.len()).is_none());
+        }
+    }
+}
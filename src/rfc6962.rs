@@ -0,0 +1,313 @@
+use digest::Digest;
+use std::marker::PhantomData;
+
+// certificate transparency compatible tree (rfc 6962 section 2.1): leaves hash
+// as H(0x00 || data), nodes as H(0x01 || left || right), full digest width, and
+// the empty tree hashes to H(""). the split at the largest power of two below n
+// is the same shape MerkleTree's flat layout uses, but the hashing and the audit
+// path format (bare hashes, sides implied by index and tree size) are not, so
+// this is a separate type rather than a MerkleTree option.
+//
+// only leaf hashes are stored; root and audit paths are recomputed from them in
+// O(n), following the recursive definitions in the rfc.
+pub struct Rfc6962Tree<S: Digest> {
+    leaves: Vec<Vec<u8>>,
+    _s: PhantomData<fn() -> S>,
+}
+
+impl<S: Digest> Default for Rfc6962Tree<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Digest> Rfc6962Tree<S> {
+    const LEAF_PREFIX: u8 = 0x00;
+    const NODE_PREFIX: u8 = 0x01;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            leaves: vec![],
+            _s: PhantomData,
+        }
+    }
+
+    pub fn from_leaves<I>(leaves: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        Self {
+            leaves: leaves
+                .into_iter()
+                .map(|leaf| Self::leaf_hash(leaf.as_ref()))
+                .collect(),
+            _s: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn add(&mut self, data: &[u8]) {
+        self.leaves.push(Self::leaf_hash(data));
+    }
+
+    #[must_use]
+    pub fn leaf_hash(data: &[u8]) -> Vec<u8> {
+        S::new()
+            .chain_update([Self::LEAF_PREFIX])
+            .chain_update(data)
+            .finalize()
+            .to_vec()
+    }
+
+    #[must_use]
+    pub fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+        S::new()
+            .chain_update([Self::NODE_PREFIX])
+            .chain_update(left)
+            .chain_update(right)
+            .finalize()
+            .to_vec()
+    }
+
+    // largest power of two strictly below n, for n > 1
+    fn split(n: usize) -> usize {
+        1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+    }
+
+    // MTH(D[n])
+    fn subtree_hash(leaves: &[Vec<u8>]) -> Vec<u8> {
+        match leaves {
+            [] => S::digest(b"").to_vec(),
+            [leaf] => leaf.clone(),
+            _ => {
+                let (left, right) = leaves.split_at(Self::split(leaves.len()));
+                Self::node_hash(&Self::subtree_hash(left), &Self::subtree_hash(right))
+            }
+        }
+    }
+
+    // PATH(m, D[n]), siblings from the leaf upwards
+    fn subtree_path(m: usize, leaves: &[Vec<u8>], path: &mut Vec<Vec<u8>>) {
+        if leaves.len() <= 1 {
+            return;
+        }
+
+        let k = Self::split(leaves.len());
+        let (left, right) = leaves.split_at(k);
+        if m < k {
+            Self::subtree_path(m, left, path);
+            path.push(Self::subtree_hash(right));
+        } else {
+            Self::subtree_path(m - k, right, path);
+            path.push(Self::subtree_hash(left));
+        }
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Vec<u8> {
+        Self::subtree_hash(&self.leaves)
+    }
+
+    // the audit path for a leaf, as served by a log's get-proof-by-hash
+    #[must_use]
+    pub fn audit_path(&self, leaf_index: usize) -> Option<Vec<Vec<u8>>> {
+        if leaf_index >= self.leaves.len() {
+            return None;
+        }
+
+        let mut path = vec![];
+        Self::subtree_path(leaf_index, &self.leaves, &mut path);
+
+        Some(path)
+    }
+
+    // inclusion proof verification as specified in rfc 9162 section 2.1.3.2. the
+    // side of each sibling follows from leaf_index and tree_size, so those are
+    // part of what the proof commits to
+    #[must_use]
+    pub fn verify_inclusion(
+        data: &[u8],
+        leaf_index: u64,
+        tree_size: u64,
+        audit_path: &[Vec<u8>],
+        root: &[u8],
+    ) -> bool {
+        if leaf_index >= tree_size {
+            return false;
+        }
+
+        let (mut fnode, mut snode) = (leaf_index, tree_size - 1);
+        let mut r = Self::leaf_hash(data);
+
+        for p in audit_path {
+            if snode == 0 {
+                return false;
+            }
+
+            if fnode & 1 == 1 || fnode == snode {
+                r = Self::node_hash(p, &r);
+
+                while fnode & 1 == 0 && fnode != 0 {
+                    fnode >>= 1;
+                    snode >>= 1;
+                }
+            } else {
+                r = Self::node_hash(&r, p);
+            }
+
+            fnode >>= 1;
+            snode >>= 1;
+        }
+
+        snode == 0 && r == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::Rfc6962Tree;
+    use crate::hex::encode as hex;
+
+    type Ct = Rfc6962Tree<Sha256>;
+
+    // leaf inputs and roots from the certificate-transparency reference test suite
+    const LEAVES: [&[u8]; 8] = [
+        b"",
+        b"\x00",
+        b"\x10",
+        b"\x20\x21",
+        b"\x30\x31",
+        b"\x40\x41\x42\x43",
+        b"\x50\x51\x52\x53\x54\x55\x56\x57",
+        b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+    ];
+
+    const ROOTS: [&str; 8] = [
+        "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+        "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+        "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+        "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+        "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+        "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+    ];
+
+    #[test]
+    fn reference_roots() {
+        assert_eq!(
+            hex(&Ct::new().root()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let mut tree = Ct::new();
+        for (leaf, root) in LEAVES.iter().zip(ROOTS) {
+            tree.add(leaf);
+            assert_eq!(hex(&tree.root()), root);
+        }
+    }
+
+    #[test]
+    fn reference_audit_paths() {
+        let tree = Ct::from_leaves(LEAVES);
+        let root = tree.root();
+
+        let cases: [(usize, [&str; 3]); 2] = [
+            (
+                0,
+                [
+                    "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+                    "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+                    "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+                ],
+            ),
+            (
+                5,
+                [
+                    "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+                    "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+                    "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+                ],
+            ),
+        ];
+
+        for (index, expected) in cases {
+            let path = tree.audit_path(index).unwrap();
+            assert_eq!(path.iter().map(|p| hex(p)).collect::<Vec<_>>(), expected);
+        }
+
+        for (index, leaf) in LEAVES.iter().enumerate() {
+            let path = tree.audit_path(index).unwrap();
+            assert!(Ct::verify_inclusion(leaf, index as u64, 8, &path, &root));
+        }
+    }
+
+    // the seven leaf example from rfc 6962 section 2.1.3:
+    //
+    //            hash
+    //           /    \
+    //          /      \
+    //         /        \
+    //        k          l
+    //       / \        / \
+    //      /   \      /   \
+    //     g     h    i    j
+    //    / \   / \  / \   |
+    //    a b   c d  e f   d6
+    #[test]
+    fn seven_leaf_example() {
+        let tree = Ct::from_leaves(&LEAVES[..7]);
+        let [a, b, c, d, e, f, j, _] = LEAVES.map(Ct::leaf_hash);
+
+        let (g, h, i) = (
+            Ct::node_hash(&a, &b),
+            Ct::node_hash(&c, &d),
+            Ct::node_hash(&e, &f),
+        );
+        let (k, l) = (Ct::node_hash(&g, &h), Ct::node_hash(&i, &j));
+        assert_eq!(tree.root(), Ct::node_hash(&k, &l));
+        assert_eq!(hex(&tree.root()), ROOTS[6]);
+
+        assert_eq!(tree.audit_path(0).unwrap(), [b, h, l.clone()]);
+        assert_eq!(tree.audit_path(3).unwrap(), [c, g, l]);
+        assert_eq!(tree.audit_path(4).unwrap(), [f, j, k.clone()]);
+        assert_eq!(tree.audit_path(6).unwrap(), [i, k]);
+        assert!(tree.audit_path(7).is_none());
+
+        let root = tree.root();
+        for (index, leaf) in LEAVES[..7].iter().enumerate() {
+            let path = tree.audit_path(index).unwrap();
+            assert!(Ct::verify_inclusion(leaf, index as u64, 7, &path, &root));
+
+            // the sides come from the index, so a neighbour's position cannot reuse it
+            assert!(!Ct::verify_inclusion(
+                leaf,
+                index as u64 ^ 1,
+                7,
+                &path,
+                &root
+            ));
+        }
+
+        // a size only fails where it changes the shape of the path: d0 sits under
+        // the same complete subtree in trees of 7 and 8 leaves, d6 does not
+        let (path0, path6) = (tree.audit_path(0).unwrap(), tree.audit_path(6).unwrap());
+        assert!(Ct::verify_inclusion(LEAVES[0], 0, 8, &path0, &root));
+        assert!(!Ct::verify_inclusion(LEAVES[0], 0, 4, &path0, &root));
+        assert!(!Ct::verify_inclusion(LEAVES[6], 6, 8, &path6, &root));
+        assert!(!Ct::verify_inclusion(LEAVES[0], 7, 7, &[], &root));
+    }
+}
@@ -0,0 +1,210 @@
+use digest::Digest;
+use std::fmt::Debug;
+
+use crate::{
+    error::MerkleError,
+    merkle::{MerkleTree, Proof, ProofElement, ProofElementDirection, TagMode},
+};
+
+// interop with the rs_merkle crate. its trees have MerkleTree's left-perfect
+// shape (a node without a partner is carried up a level unhashed) and untagged
+// nodes, H(left || right) at full digest width, over leaf hashes the caller
+// computes. rs_merkle_tree covers the usual H(data) leaves, which is
+// TagMode::None; from_leaf_hashes takes leaves hashed any other way. N must be
+// the digest's full output size for either to match.
+//
+// a single-leaf rs_merkle proof, as MerkleProof::to_bytes lays it out, is the
+// sibling hashes back to back from the leaf up with no directions: its verifier
+// derives them from the leaf index and the tree size, so converting a proof in
+// needs both. multi-leaf proofs interleave the siblings of several paths and are
+// not supported.
+impl<S: Debug + Digest, const N: usize, const ND: usize> MerkleTree<S, N, ND> {
+    #[must_use]
+    pub fn rs_merkle_tree() -> Self {
+        Self::builder().tag_mode(TagMode::None).build()
+    }
+
+    // an untagged tree over leaf hashes as rs_merkle's from_leaves takes them
+    pub fn from_leaf_hashes<I>(leaf_hashes: I) -> Result<Self, MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut nodes = vec![];
+        for (i, leaf) in leaf_hashes.into_iter().enumerate() {
+            if i > 0 {
+                nodes.push(vec![0; N]);
+            }
+            nodes.push(leaf.as_ref().to_vec());
+        }
+
+        // from_nodes checks the capacity and every hash's width
+        let mut tree = Self::from_nodes_with(Self::builder().tag_mode(TagMode::None), nodes)?;
+        tree.recompute();
+
+        Ok(tree)
+    }
+
+    #[must_use]
+    pub fn proof_to_rs_merkle(proof: &[ProofElement<S, N, ND>]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(proof.len() * N);
+        for e in proof {
+            out.extend_from_slice(&e.hash);
+        }
+
+        out
+    }
+
+    pub fn proof_from_rs_merkle(
+        bytes: &[u8],
+        index: usize,
+        tree_size: usize,
+    ) -> Result<Proof<S, N, ND>, MerkleError> {
+        if index >= tree_size {
+            return Err(MerkleError::LeafOutOfBounds {
+                index,
+                len: tree_size,
+            });
+        }
+        if !bytes.len().is_multiple_of(N) {
+            return Err(MerkleError::InvalidEncoding(
+                "siblings are not a whole number of hashes",
+            ));
+        }
+
+        let directions = Self::path_directions(index, tree_size);
+        if directions.len() != bytes.len() / N {
+            return Err(MerkleError::InvalidEncoding(
+                "sibling count does not match the leaf's path",
+            ));
+        }
+
+        bytes
+            .chunks(N)
+            .zip(directions)
+            .map(|(hash, direction)| ProofElement::from_slice(hash, direction))
+            .collect()
+    }
+
+    // MerkleProof::verify for a single leaf, given its hash however it was made
+    #[must_use]
+    pub fn verify_rs_merkle_proof(
+        leaf_hash: &[u8],
+        proof: &[ProofElement<S, N, ND>],
+        root: &[u8],
+    ) -> bool {
+        leaf_hash.len() == N
+            && matches!(
+                Self::fold_proof(TagMode::None, leaf_hash.to_vec(), proof, root),
+                Ok(true)
+            )
+    }
+
+    // the side each sibling of leaf index sits on, leaf upwards, in a tree of
+    // tree_size leaves
+    fn path_directions(mut index: usize, mut tree_size: usize) -> Vec<ProofElementDirection> {
+        let mut directions = vec![];
+        while tree_size > 1 {
+            let split = 1 << (tree_size - 1).ilog2();
+            if index < split {
+                directions.push(ProofElementDirection::Right);
+                tree_size = split;
+            } else {
+                directions.push(ProofElementDirection::Left);
+                index -= split;
+                tree_size -= split;
+            }
+        }
+        directions.reverse();
+
+        directions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::rs_merkle::{Hasher, MerkleProof, algorithms::Sha256 as RsSha256};
+    use sha2::Sha256;
+
+    use crate::{error::MerkleError, merkle::MerkleTree};
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+
+    fn leaves(count: u8) -> Vec<Vec<u8>> {
+        (0..count)
+            .map(|i| format!("leaf {i}").into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn proofs_cross_both_ways() {
+        for count in 1..=17u8 {
+            let data = leaves(count);
+            let hashes = data.iter().map(|d| RsSha256::hash(d)).collect::<Vec<_>>();
+            let theirs = ::rs_merkle::MerkleTree::<RsSha256>::from_leaves(&hashes);
+
+            let mut ours = Tree::rs_merkle_tree();
+            for d in &data {
+                ours.add(d).unwrap();
+            }
+            let root = ours.root().unwrap();
+            assert_eq!(root, theirs.root().unwrap());
+
+            let size = data.len();
+            for (i, d) in data.iter().enumerate() {
+                // rs_merkle to us
+                let bytes = theirs.proof(&[i]).to_bytes();
+                let proof = Tree::proof_from_rs_merkle(&bytes, i, size).unwrap();
+                assert!(Tree::verify_proof_with_tag_mode(
+                    d,
+                    &proof,
+                    &root,
+                    ours.tag_mode()
+                ));
+                assert!(Tree::verify_rs_merkle_proof(&hashes[i], &proof, &root));
+
+                // us to rs_merkle
+                let proof = ours.create_proof_by_index(i).unwrap();
+                let bytes = Tree::proof_to_rs_merkle(&proof);
+                let proof = MerkleProof::<RsSha256>::from_bytes(&bytes).unwrap();
+                assert!(proof.verify(theirs.root().unwrap(), &[i], &[hashes[i]], size));
+                let other = RsSha256::hash(b"other");
+                assert!(!proof.verify(theirs.root().unwrap(), &[i], &[other], size));
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_hashed_another_way() {
+        let hashes = leaves(6)
+            .iter()
+            .map(|d| RsSha256::hash(&[b"leaf:".as_slice(), d].concat()))
+            .collect::<Vec<_>>();
+        let theirs = ::rs_merkle::MerkleTree::<RsSha256>::from_leaves(&hashes);
+        let ours = Tree::from_leaf_hashes(&hashes).unwrap();
+        let root = ours.root().unwrap();
+        assert_eq!(root, theirs.root().unwrap());
+
+        let bytes = theirs.proof(&[4]).to_bytes();
+        let proof = Tree::proof_from_rs_merkle(&bytes, 4, 6).unwrap();
+        assert!(Tree::verify_rs_merkle_proof(&hashes[4], &proof, &root));
+        assert!(!Tree::verify_rs_merkle_proof(&hashes[3], &proof, &root));
+        assert!(!Tree::verify_rs_merkle_proof(
+            &hashes[4][1..],
+            &proof,
+            &root
+        ));
+
+        // the wrong index puts the siblings on the wrong sides
+        let proof = Tree::proof_from_rs_merkle(&bytes, 5, 6).unwrap();
+        assert!(!Tree::verify_rs_merkle_proof(&hashes[4], &proof, &root));
+
+        assert_eq!(
+            Tree::proof_from_rs_merkle(&bytes, 6, 6).err(),
+            Some(MerkleError::LeafOutOfBounds { index: 6, len: 6 })
+        );
+        assert!(Tree::proof_from_rs_merkle(&bytes[1..], 4, 6).is_err());
+        assert!(Tree::proof_from_rs_merkle(&bytes[32..], 4, 6).is_err());
+        assert!(Tree::from_leaf_hashes([[0u8; 31]]).is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use digest::Digest;
+use std::fmt::Debug;
+
+use crate::{
+    error::MerkleError,
+    merkle::{MerkleTree, Proof, ProofElement},
+};
+
+// a tree whose leaves each carry a random salt, for disclosures such as proofs
+// of reserves: a leaf is the tagged hash of salt || data, i.e.
+// H(0x01 * N || H(salt || data)), so a sibling hash in a revealed proof cannot be
+// checked against guessed data without that sibling's salt. proofs hold sibling
+// hashes only and never another leaf's salt.
+//
+// every salt must be fresh and kept secret until its own leaf is disclosed; a
+// reused or predictable salt gives its leaf no more privacy than an unsalted one.
+pub type Salt = [u8; SALT_LEN];
+
+pub const SALT_LEN: usize = 32;
+
+/// Controls where a `SaltedMerkleTree` keeps the salts of its leaves.
+///
+/// `Caller` only hands each salt back from `add_salted`, so the caller must keep
+/// it to prove that leaf later. `Tree` also stores every salt, so `salt(index)`
+/// can look it up again, at the cost of keeping the secrets next to the tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SaltStorage {
+    #[default]
+    Caller,
+    Tree,
+}
+
+// the salts a tree keeps are secrets, so with zeroize on they are wiped on drop
+#[cfg(feature = "zeroize")]
+type Salts = zeroize::Zeroizing<Vec<Salt>>;
+#[cfg(not(feature = "zeroize"))]
+type Salts = Vec<Salt>;
+
+pub struct SaltedMerkleTree<S: Digest, const N: usize, const ND: usize> {
+    tree: MerkleTree<S, N, ND>,
+    // one per leaf under SaltStorage::Tree, None under Caller
+    salts: Option<Salts>,
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> Default for SaltedMerkleTree<S, N, ND> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> SaltedMerkleTree<S, N, ND> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_salt_storage(SaltStorage::Caller)
+    }
+
+    #[must_use]
+    pub fn with_salt_storage(storage: SaltStorage) -> Self {
+        Self {
+            tree: MerkleTree::new(),
+            salts: match storage {
+                SaltStorage::Caller => None,
+                SaltStorage::Tree => Some(Salts::default()),
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn salt_storage(&self) -> SaltStorage {
+        if self.salts.is_some() {
+            SaltStorage::Tree
+        } else {
+            SaltStorage::Caller
+        }
+    }
+
+    fn salted(data: &[u8], salt: &Salt) -> Vec<u8> {
+        [salt.as_slice(), data].concat()
+    }
+
+    // adds data under a fresh salt from the operating system's generator and
+    // returns the leaf's index with the salt needed to prove it
+    pub fn add_salted(&mut self, data: &[u8]) -> Result<(usize, Salt), MerkleError> {
+        let mut salt = [0; SALT_LEN];
+        getrandom::fill(&mut salt).map_err(|_| MerkleError::RandomnessUnavailable)?;
+
+        Ok((self.add_with_salt(data, salt)?, salt))
+    }
+
+    // adds data under a salt the caller drew, see the note on fresh salts above
+    pub fn add_with_salt(&mut self, data: &[u8], salt: Salt) -> Result<usize, MerkleError> {
+        let index = self.tree.len();
+        self.tree.add(&Self::salted(data, &salt))?;
+
+        if let Some(salts) = &mut self.salts {
+            salts.push(salt);
+        }
+
+        Ok(index)
+    }
+
+    // None unless the tree stores its salts
+    #[must_use]
+    pub fn salt(&self, index: usize) -> Option<&Salt> {
+        self.salts.as_ref()?.get(index)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.tree.root()
+    }
+
+    #[must_use]
+    pub fn create_proof(&self, data: &[u8], salt: &Salt) -> Option<Proof<S, N, ND>> {
+        self.tree.create_proof(&Self::salted(data, salt))
+    }
+
+    #[must_use]
+    pub fn create_proof_by_index(&self, index: usize) -> Option<Proof<S, N, ND>> {
+        self.tree.create_proof_by_index(index)
+    }
+
+    #[must_use]
+    pub fn verify_proof(
+        data: &[u8],
+        salt: &Salt,
+        proof: &[ProofElement<S, N, ND>],
+        to_match: &[u8],
+    ) -> bool {
+        MerkleTree::<S, N, ND>::verify_proof(&Self::salted(data, salt), proof, to_match)
+    }
+
+    #[must_use]
+    pub fn as_tree(&self) -> &MerkleTree<S, N, ND> {
+        &self.tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::{SaltStorage, SaltedMerkleTree};
+    use crate::merkle::MerkleTree;
+
+    type Salted = SaltedMerkleTree<Sha256, 32, 64>;
+
+    #[test]
+    fn salted_proofs_need_their_salt() {
+        let mut tree = Salted::new();
+        let accounts: [&[u8]; 5] = [b"alice:10", b"bob:20", b"carol:30", b"dave:40", b"erin:50"];
+        let salts = accounts.map(|a| tree.add_salted(a).unwrap());
+        let root = tree.root().unwrap();
+
+        assert_eq!(tree.salt_storage(), SaltStorage::Caller);
+        assert_eq!(tree.salt(0), None);
+
+        for (account, (index, salt)) in accounts.iter().zip(&salts) {
+            let proof = tree.create_proof(account, salt).unwrap();
+            assert_eq!(
+                MerkleTree::<Sha256, 32, 64>::proof_to_bytes(&proof).unwrap(),
+                MerkleTree::<Sha256, 32, 64>::proof_to_bytes(
+                    &tree.create_proof_by_index(*index).unwrap()
+                )
+                .unwrap()
+            );
+            assert!(Salted::verify_proof(account, salt, &proof, &root));
+
+            // the wrong salt, no salt, and another leaf's salt all fail
+            let (_, other) = salts[(index + 1) % salts.len()];
+            assert!(!Salted::verify_proof(account, &other, &proof, &root));
+            assert!(!Salted::verify_proof(account, &[0; 32], &proof, &root));
+            assert!(!MerkleTree::<Sha256, 32, 64>::verify_proof(
+                account, &proof, &root
+            ));
+
+            // no salt appears anywhere in a proof
+            for (_, salt) in &salts {
+                assert!(proof.iter().all(|e| e.hash.as_slice() != salt.as_slice()));
+            }
+        }
+
+        assert!(tree.create_proof(accounts[0], &salts[1].1).is_none());
+    }
+
+    #[test]
+    fn fresh_salts_change_the_root() {
+        let accounts: [&[u8]; 3] = [b"alice:10", b"bob:20", b"carol:30"];
+
+        let roots = [(); 2].map(|()| {
+            let mut tree = Salted::new();
+            for account in accounts {
+                tree.add_salted(account).unwrap();
+            }
+            tree.root().unwrap()
+        });
+        assert_ne!(roots[0], roots[1]);
+
+        // fixed salts reproduce the root
+        let fixed = [(); 2].map(|()| {
+            let mut tree = Salted::with_salt_storage(SaltStorage::Tree);
+            for (i, account) in accounts.iter().enumerate() {
+                assert_eq!(tree.add_with_salt(account, [i as u8; 32]).unwrap(), i);
+            }
+            assert_eq!(tree.salt(2), Some(&[2; 32]));
+            assert_eq!(tree.salt(3), None);
+            tree.root().unwrap()
+        });
+        assert_eq!(fixed[0], fixed[1]);
+    }
+}
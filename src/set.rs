@@ -0,0 +1,181 @@
+use digest::Digest;
+use std::fmt::Debug;
+
+use crate::{
+    error::MerkleError,
+    merkle::{MerkleTree, Proof},
+};
+
+// commits to a set rather than a sequence: values are kept ordered by their leaf
+// hash and duplicates are dropped, so the root depends only on which values are
+// present. the tree is rebuilt with from_leaves on every insert, O(n) each.
+//
+// proofs are ordinary MerkleTree proofs for the value's slot in that sorted
+// order and verify with MerkleTree::verify_proof.
+pub struct SetMerkleTree<S: Digest, const N: usize, const ND: usize> {
+    // (leaf hash, value), sorted by leaf hash
+    values: Vec<(Vec<u8>, Vec<u8>)>,
+    tree: MerkleTree<S, N, ND>,
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> Default for SetMerkleTree<S, N, ND> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> SetMerkleTree<S, N, ND> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            values: vec![],
+            tree: MerkleTree::new(),
+        }
+    }
+
+    pub fn from_values<I>(values: I) -> Result<Self, MerkleError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut values = values
+            .into_iter()
+            .map(|v| (Self::leaf_hash(v.as_ref()), v.as_ref().to_vec()))
+            .collect::<Vec<_>>();
+        values.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        values.dedup_by(|a, b| a.0 == b.0);
+
+        let tree = MerkleTree::from_leaves(values.iter().map(|(_, v)| v))?;
+        Ok(Self { values, tree })
+    }
+
+    fn leaf_hash(data: &[u8]) -> Vec<u8> {
+        MerkleTree::<S, N, ND>::tag_hash(MerkleTree::<S, N, ND>::LEAF_TAG, data)
+    }
+
+    fn search(&self, data: &[u8]) -> Result<usize, usize> {
+        let hash = Self::leaf_hash(data);
+        self.values.binary_search_by(|(h, _)| h.cmp(&hash))
+    }
+
+    // false if the value was already in the set
+    pub fn insert(&mut self, data: &[u8]) -> Result<bool, MerkleError> {
+        let Err(pos) = self.search(data) else {
+            return Ok(false);
+        };
+
+        let mut values = self.values.clone();
+        values.insert(pos, (Self::leaf_hash(data), data.to_vec()));
+
+        // built aside so a failure leaves the set untouched
+        self.tree = MerkleTree::from_leaves(values.iter().map(|(_, v)| v))?;
+        self.values = values;
+
+        Ok(true)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    #[must_use]
+    pub fn contains(&self, data: &[u8]) -> bool {
+        self.search(data).is_ok()
+    }
+
+    // the leaf slot the value occupies in the sorted order
+    #[must_use]
+    pub fn position(&self, data: &[u8]) -> Option<usize> {
+        self.search(data).ok()
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.tree.root()
+    }
+
+    #[must_use]
+    pub fn create_proof(&self, data: &[u8]) -> Option<Proof<S, N, ND>> {
+        self.tree.create_proof_by_index(self.position(data)?)
+    }
+
+    #[must_use]
+    pub fn as_tree(&self) -> &MerkleTree<S, N, ND> {
+        &self.tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::SetMerkleTree;
+    use crate::merkle::MerkleTree;
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+    type Set = SetMerkleTree<Sha256, 32, 64>;
+
+    #[test]
+    fn root_ignores_insertion_order() {
+        let orders: [[&[u8]; 3]; 3] = [
+            [b"apple", b"banana", b"cherry"],
+            [b"cherry", b"apple", b"banana"],
+            [b"banana", b"cherry", b"apple"],
+        ];
+
+        let roots = orders.map(|order| {
+            let mut set = Set::new();
+            for value in order {
+                assert!(set.insert(value).unwrap());
+            }
+            assert!(!set.insert(order[0]).unwrap());
+            assert_eq!(set.len(), 3);
+
+            set.root().unwrap()
+        });
+
+        assert_eq!(roots[0], roots[1]);
+        assert_eq!(roots[1], roots[2]);
+        assert_eq!(
+            Set::from_values(orders[2].iter().chain(&orders[0]))
+                .unwrap()
+                .root(),
+            Some(roots[0].clone())
+        );
+
+        // a sequence tree over the same values is order sensitive
+        assert_ne!(
+            Tree::from_leaves(orders[0]).unwrap().root(),
+            Tree::from_leaves(orders[1]).unwrap().root()
+        );
+    }
+
+    #[test]
+    fn membership_proofs_follow_sorted_position() {
+        let values: [&[u8]; 5] = [b"e", b"d", b"c", b"b", b"a"];
+        let set = Set::from_values(values).unwrap();
+        let root = set.root().unwrap();
+
+        let mut positions = values.map(|v| set.position(v).unwrap());
+        positions.sort_unstable();
+        assert_eq!(positions, [0, 1, 2, 3, 4]);
+
+        for value in values {
+            let proof = set.create_proof(value).unwrap();
+            assert!(Tree::verify_proof(value, &proof, &root));
+            assert_eq!(
+                Tree::proof_to_bytes(&proof).unwrap(),
+                Tree::proof_to_bytes(&set.as_tree().create_proof(value).unwrap()).unwrap()
+            );
+        }
+
+        assert!(!set.contains(b"f"));
+        assert!(set.create_proof(b"f").is_none());
+    }
+}
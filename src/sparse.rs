@@ -0,0 +1,123 @@
+use digest::Digest;
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::Debug,
+    marker::PhantomData,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+use crate::merkle::MerkleTree;
+
+// default node values for a sparse merkle tree of height DEPTH: level 0 is the
+// hash of an empty leaf and every level above is the node hash of two copies of
+// the level below. these are what absent keys resolve to in sparse proofs.
+pub struct EmptyHashes<S: Digest, const N: usize, const ND: usize, const DEPTH: usize> {
+    _s: PhantomData<fn() -> S>,
+}
+
+struct Table<const DEPTH: usize> {
+    levels: [Vec<u8>; DEPTH],
+    root: Vec<u8>,
+}
+
+// one table per <S, N, ND, DEPTH>, computed on first use and shared by the whole
+// process like ssz's ZERO_HASHES. a static cannot be generic, so the tables sit
+// in one map keyed by the EmptyHashes type, leaked so they can be handed out
+// as 'static
+type Tables = HashMap<TypeId, &'static (dyn Any + Send + Sync)>;
+
+static TABLES: OnceLock<Mutex<Tables>> = OnceLock::new();
+
+impl<S: Debug + Digest + 'static, const N: usize, const ND: usize, const DEPTH: usize>
+    EmptyHashes<S, N, ND, DEPTH>
+{
+    fn table() -> &'static Table<DEPTH> {
+        let mut tables = TABLES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let table = *tables
+            .entry(TypeId::of::<Self>())
+            .or_insert_with(|| Box::leak(Box::new(Self::compute())));
+
+        table
+            .downcast_ref()
+            .expect("tables are keyed by the type that computed them")
+    }
+
+    fn compute() -> Table<DEPTH> {
+        let mut current = MerkleTree::<S, N, ND>::tag_hash(MerkleTree::<S, N, ND>::LEAF_TAG, b"");
+        let levels = std::array::from_fn(|_| {
+            let level = current.clone();
+            current = MerkleTree::<S, N, ND>::node_hash(&level, &level);
+            level
+        });
+
+        // one level past the top, or the empty leaf itself for DEPTH = 0
+        Table {
+            levels,
+            root: current,
+        }
+    }
+
+    // empty subtree hashes for heights 0..DEPTH
+    #[must_use]
+    pub fn empty_hashes() -> &'static [Vec<u8>; DEPTH] {
+        &Self::table().levels
+    }
+
+    // hash of an entirely empty tree of height DEPTH
+    #[must_use]
+    pub fn empty_root() -> &'static [u8] {
+        &Self::table().root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::EmptyHashes;
+    use crate::merkle::MerkleTree;
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+
+    #[test]
+    fn empty_hashes_chain() {
+        type Empty = EmptyHashes<Sha256, 32, 64, 8>;
+        let levels = Empty::empty_hashes();
+
+        assert_eq!(levels[0], Tree::empty_root());
+
+        let mut current = levels[0].clone();
+        for level in &levels[1..] {
+            current = Tree::node_hash(&current, &current);
+            assert_eq!(&current, level);
+        }
+
+        current = Tree::node_hash(&current, &current);
+        assert_eq!(current.as_slice(), Empty::empty_root());
+
+        // every call, from any thread, hands back the one table
+        assert!(std::ptr::eq(levels, Empty::empty_hashes()));
+        let elsewhere = std::thread::spawn(|| Empty::empty_hashes() as *const _ as usize)
+            .join()
+            .unwrap();
+        assert_eq!(elsewhere, levels as *const _ as usize);
+
+        // other parameters get tables of their own
+        assert_eq!(
+            EmptyHashes::<Sha256, 32, 64, 4>::empty_hashes()[..],
+            levels[..4]
+        );
+        assert_eq!(
+            EmptyHashes::<Sha256, 16, 32, 8>::empty_hashes()[0].len(),
+            16
+        );
+        assert_eq!(
+            EmptyHashes::<Sha256, 32, 64, 0>::empty_root(),
+            Tree::empty_root()
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+// ssz merkleization as in the ethereum consensus specs: 32-byte chunks are the
+// leaves, padded with zero chunks up to a power of two (or up to the limit of a
+// list type), and every node is a plain sha256(left || right) with no tags. a
+// node is addressed by its generalized index: the root is 1 and the children of
+// g are 2g and 2g + 1, so chunk i of a depth d tree is 2^d + i.
+//
+// padding is never materialized. a subtree made only of zero chunks has a fixed
+// hash per depth, kept in ZERO_HASHES, so a list with a limit of 2^40 and three
+// elements costs three chunks plus one hash per level.
+pub const CHUNK_SIZE: usize = 32;
+
+pub type Chunk = [u8; CHUNK_SIZE];
+
+// a tree of up to 2^64 chunks has 64 levels above the chunks
+const MAX_DEPTH: usize = 64;
+
+static ZERO_HASHES: OnceLock<[Chunk; MAX_DEPTH + 1]> = OnceLock::new();
+
+// the root of a subtree of 2^depth zero chunks
+#[must_use]
+pub fn zero_hash(depth: usize) -> Chunk {
+    ZERO_HASHES.get_or_init(|| {
+        let mut zeros = [[0; CHUNK_SIZE]; MAX_DEPTH + 1];
+        for depth in 1..=MAX_DEPTH {
+            zeros[depth] = hash_pair(&zeros[depth - 1], &zeros[depth - 1]);
+        }
+        zeros
+    })[depth]
+}
+
+#[must_use]
+pub fn hash_pair(left: &Chunk, right: &Chunk) -> Chunk {
+    Sha256::new()
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+// list and bitlist roots commit to their length: hash(root || length as a
+// little-endian uint256). the data tree then sits at generalized index 2 of the
+// mixed root, so its gindices shift down one level
+#[must_use]
+pub fn mix_in_length(root: &Chunk, length: u64) -> Chunk {
+    let mut len = [0; CHUNK_SIZE];
+    len[..8].copy_from_slice(&length.to_le_bytes());
+
+    hash_pair(root, &len)
+}
+
+// merkleize(chunks) with the chunks padded to the next power of two
+#[must_use]
+pub fn ssz_root(chunks: &[Chunk]) -> Chunk {
+    SszTree::new(chunks).root()
+}
+
+// ceil(log2(n)), with no levels for zero or one chunk
+fn depth_for(n: u64) -> usize {
+    match n {
+        0 | 1 => 0,
+        n => (u64::BITS - (n - 1).leading_zeros()) as usize,
+    }
+}
+
+// floor(log2(gindex)): how many levels below the root the node sits
+fn gindex_depth(gindex: u64) -> usize {
+    (u64::BITS - 1 - gindex.leading_zeros()) as usize
+}
+
+pub struct SszTree {
+    // levels[0] holds the chunks and levels[depth] the root, if any chunk exists.
+    // a node past the end of its level is a zero subtree
+    levels: Vec<Vec<Chunk>>,
+}
+
+impl SszTree {
+    #[must_use]
+    pub fn new(chunks: &[Chunk]) -> Self {
+        Self::build(chunks, depth_for(chunks.len() as u64))
+    }
+
+    // merkleize(chunks, limit) for list types: the tree is as deep as the limit
+    // requires, whatever the number of chunks actually present. None if the
+    // chunks exceed the limit
+    #[must_use]
+    pub fn with_limit(chunks: &[Chunk], limit: u64) -> Option<Self> {
+        if chunks.len() as u64 > limit {
+            return None;
+        }
+
+        Some(Self::build(chunks, depth_for(limit)))
+    }
+
+    fn build(chunks: &[Chunk], depth: usize) -> Self {
+        let mut levels = vec![chunks.to_vec()];
+
+        for level in 0..depth {
+            let next = levels[level]
+                .chunks(2)
+                .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&zero_hash(level))))
+                .collect();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Chunk {
+        self.levels[self.depth()]
+            .first()
+            .copied()
+            .unwrap_or_else(|| zero_hash(self.depth()))
+    }
+
+    // generalized index of chunk index, whether or not it holds data
+    #[must_use]
+    pub fn chunk_gindex(&self, index: u64) -> Option<u64> {
+        let width = 1u64.checked_shl(self.depth() as u32)?;
+        (index < width).then(|| width + index)
+    }
+
+    // the node at a generalized index, padding included
+    #[must_use]
+    pub fn node(&self, gindex: u64) -> Option<Chunk> {
+        if gindex == 0 || gindex_depth(gindex) > self.depth() {
+            return None;
+        }
+
+        let level = self.depth() - gindex_depth(gindex);
+        let pos = usize::try_from(gindex - (1 << gindex_depth(gindex))).ok()?;
+
+        Some(
+            self.levels[level]
+                .get(pos)
+                .copied()
+                .unwrap_or_else(|| zero_hash(level)),
+        )
+    }
+
+    // the spec's single proof for gindex: sibling hashes from the node upwards,
+    // one per level between it and the root
+    #[must_use]
+    pub fn proof(&self, gindex: u64) -> Option<Vec<Chunk>> {
+        self.node(gindex)?;
+
+        let mut branch = vec![];
+        let mut g = gindex;
+        while g > 1 {
+            branch.push(self.node(g ^ 1)?);
+            g >>= 1;
+        }
+
+        Some(branch)
+    }
+
+    // is_valid_merkle_branch keyed by generalized index: bit i of gindex says
+    // whether the node at height i is a right child. the branch length must be
+    // exactly the gindex's depth, so one branch cannot be replayed at another
+    // depth
+    #[must_use]
+    pub fn verify_proof(leaf: &Chunk, branch: &[Chunk], gindex: u64, root: &Chunk) -> bool {
+        if gindex == 0 || branch.len() != gindex_depth(gindex) {
+            return false;
+        }
+
+        let computed = branch
+            .iter()
+            .enumerate()
+            .fold(*leaf, |node, (height, sibling)| {
+                if (gindex >> height) & 1 == 1 {
+                    hash_pair(sibling, &node)
+                } else {
+                    hash_pair(&node, sibling)
+                }
+            });
+
+        computed == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chunk, SszTree, hash_pair, mix_in_length, ssz_root, zero_hash};
+    use crate::hex::encode as hex;
+
+    // the chunk holding v as a little-endian uint256, as in the consensus spec's
+    // merkle_minimal tests
+    fn e(v: u8) -> Chunk {
+        let mut chunk = [0; 32];
+        chunk[0] = v;
+        chunk
+    }
+
+    #[test]
+    fn zero_hashes_and_the_empty_deposit_root() {
+        assert_eq!(zero_hash(0), [0; 32]);
+        assert_eq!(
+            hex(&zero_hash(1)),
+            "f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a92759fb4b"
+        );
+        assert_eq!(
+            hex(&zero_hash(2)),
+            "db56114e00fdd4c1f85c892bf35ac9a89289aaecb1ebd0a96cde606a748b5d71"
+        );
+        assert_eq!(
+            hex(&zero_hash(3)),
+            "c78009fdf07fc56a11f122370658a353aaa542ed63e44c4bc15ff4cd105ab33c"
+        );
+
+        // the deposit contract's get_deposit_root() before any deposit: a
+        // List[DepositData, 2**32] with no elements
+        let empty = SszTree::with_limit(&[], 1 << 32).unwrap();
+        assert_eq!(empty.depth(), 32);
+        assert_eq!(
+            hex(&mix_in_length(&empty.root(), 0)),
+            "d70a234731285c6804c2a4f56711ddb8c82c99740f207854891028af34e27e5e"
+        );
+    }
+
+    // merkleize(chunks, limit) cases from the spec's merkle_minimal tests, with
+    // the padding spelled out as zero hashes
+    #[test]
+    fn merkleize_pads_with_zero_subtrees() {
+        let h = hash_pair;
+        let z = zero_hash;
+
+        assert_eq!(ssz_root(&[]), z(0));
+        assert_eq!(ssz_root(&[e(0)]), e(0));
+        assert_eq!(ssz_root(&[e(0), e(1)]), h(&e(0), &e(1)));
+        assert_eq!(
+            ssz_root(&[e(0), e(1), e(2)]),
+            h(&h(&e(0), &e(1)), &h(&e(2), &z(0)))
+        );
+        assert_eq!(
+            ssz_root(&[e(0), e(1), e(2), e(3), e(4)]),
+            h(
+                &h(&h(&e(0), &e(1)), &h(&e(2), &e(3))),
+                &h(&h(&e(4), &z(0)), &z(1))
+            )
+        );
+
+        let limited = |chunks: &[Chunk], limit| SszTree::with_limit(chunks, limit).unwrap().root();
+        assert_eq!(limited(&[], 4), z(2));
+        assert_eq!(limited(&[e(0)], 2), h(&e(0), &z(0)));
+        assert_eq!(
+            limited(&[e(0), e(1), e(2)], 8),
+            h(&h(&h(&e(0), &e(1)), &h(&e(2), &z(0))), &z(2))
+        );
+        assert!(SszTree::with_limit(&[e(0), e(1)], 1).is_none());
+    }
+
+    #[test]
+    fn single_proofs_by_generalized_index() {
+        let chunks = (0..5).map(e).collect::<Vec<_>>();
+        let tree = SszTree::with_limit(&chunks, 16).unwrap();
+        let root = tree.root();
+        assert_eq!(tree.depth(), 4);
+
+        // every chunk slot, padding included
+        for i in 0..16u64 {
+            let gindex = tree.chunk_gindex(i).unwrap();
+            assert_eq!(gindex, 16 + i);
+
+            let leaf = tree.node(gindex).unwrap();
+            assert_eq!(leaf, chunks.get(i as usize).copied().unwrap_or([0; 32]));
+
+            let branch = tree.proof(gindex).unwrap();
+            assert_eq!(branch.len(), 4);
+            assert!(SszTree::verify_proof(&leaf, &branch, gindex, &root));
+
+            // two zero slots under one parent hash the same either way round
+            if i < 5 {
+                assert!(!SszTree::verify_proof(&leaf, &branch, gindex ^ 1, &root));
+            }
+        }
+
+        // internal nodes are provable too: gindex 5 is the second quarter
+        let branch = tree.proof(5).unwrap();
+        assert_eq!(branch, [tree.node(4).unwrap(), tree.node(3).unwrap()]);
+        assert!(SszTree::verify_proof(
+            &tree.node(5).unwrap(),
+            &branch,
+            5,
+            &root
+        ));
+
+        // a branch is bound to its depth
+        let branch = tree.proof(17).unwrap();
+        assert!(!SszTree::verify_proof(&e(1), &branch[..3], 8, &root));
+        assert!(!SszTree::verify_proof(&e(1), &branch, 0, &root));
+
+        assert!(tree.chunk_gindex(16).is_none());
+        assert!(tree.node(0).is_none());
+        assert!(tree.proof(32).is_none());
+        assert_eq!(tree.proof(1).unwrap(), Vec::<Chunk>::new());
+    }
+
+    #[test]
+    fn list_roots_mix_in_the_length() {
+        let chunks = [e(1), e(2), e(3)];
+        let data = SszTree::with_limit(&chunks, 4).unwrap();
+        let root = mix_in_length(&data.root(), 3);
+
+        let mut len = [0; 32];
+        len[0] = 3;
+        assert_eq!(root, hash_pair(&data.root(), &len));
+        assert_ne!(root, mix_in_length(&data.root(), 4));
+
+        // chunk 2 sits at gindex 4 + 2 = 6 under the data root; under the mixed-in
+        // root the data root is gindex 2, so the chunk moves to 2 * 4 + 2 = 10
+        let mut branch = data.proof(data.chunk_gindex(2).unwrap()).unwrap();
+        branch.push(len);
+        assert!(SszTree::verify_proof(&e(3), &branch, 10, &root));
+    }
+}
@@ -0,0 +1,576 @@
+use digest::Digest;
+use std::{
+    fmt::Debug,
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    ops::Range,
+};
+
+use crate::{
+    flat,
+    merkle::{IndexBinding, MerkleTree, TagMode},
+};
+
+// verified streaming in the style of bao: content is cut into 1 KiB chunks that
+// become the leaves of an index-bound MerkleTree, and the encoding interleaves
+// the tree with the data so a reader holding only the root can check every
+// chunk before handing it on.
+//
+// the encoding is the content length as 8 little-endian bytes, then the tree in
+// pre-order: a parent as its two child hashes, a chunk as its bytes. a decoder
+// checks each parent against the hash it expects for it, which gives it the
+// hashes to expect for both children, so every chunk is checked as soon as it
+// has been read. a slice is the same with every subtree outside a byte range
+// left out, which keeps the parents on the way to the chunks that overlap it.
+//
+// leaves carry their chunk index, as blake3 chunks carry their counter, so a
+// chunk cannot be replayed at another offset. the header length picks the
+// tree's shape and is only authenticated once the final chunk verifies, so a
+// range past the end is served as the final chunk, as bao does. empty content
+// is a single empty chunk.
+pub const CHUNK_SIZE: usize = 1024;
+
+const HEADER_SIZE: usize = 8;
+
+// the header is untrusted, so none of the arithmetic on its length may
+// overflow: a count is at most 2^54, which leaves chunk_count and chunk_span
+// safe as they are, while byte offsets near u64::MAX saturate or are checked
+fn chunk_count(content_len: u64) -> u64 {
+    content_len.div_ceil(CHUNK_SIZE as u64).max(1)
+}
+
+// chunks under the left child of a node over count > 1 chunks
+fn left_chunks(count: u64) -> u64 {
+    1 << (count - 1).ilog2()
+}
+
+// the chunks [first, end) a slice for range keeps, never empty: a range past
+// the end, or an empty one there, still gets the final chunk
+fn chunk_span(range: &Range<u64>, content_len: u64) -> Range<u64> {
+    let count = chunk_count(content_len);
+    let first = (range.start / CHUNK_SIZE as u64).min(count - 1);
+    let end = range.end.div_ceil(CHUNK_SIZE as u64).min(count);
+
+    first..end.max(first + 1)
+}
+
+fn overlaps(first: u64, count: u64, span: &Range<u64>) -> bool {
+    first < span.end && span.start < first + count
+}
+
+// bytes of content under the chunks [first, first + count)
+fn content_under(first: u64, count: u64, content_len: u64) -> u64 {
+    let start = first.saturating_mul(CHUNK_SIZE as u64);
+    let end = (first + count).saturating_mul(CHUNK_SIZE as u64);
+    end.min(content_len) - start.min(content_len)
+}
+
+// bytes of encoding under the chunks [first, first + count), None past u64
+fn encoded_under<const N: usize>(first: u64, count: u64, content_len: u64) -> Option<u64> {
+    (count - 1)
+        .checked_mul(2 * N as u64)?
+        .checked_add(content_under(first, count, content_len))
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// an io::Write that takes the content and writes its encoding on finish. every
+// parent comes ahead of the data below it, so nothing can be written until the
+// last byte is in and the content is held until then
+pub struct EncodedStream<S: Digest, const N: usize, const ND: usize, W: Write> {
+    inner: W,
+    tree: MerkleTree<S, N, ND>,
+    data: Vec<u8>,
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize, W: Write> EncodedStream<S, N, ND, W> {
+    #[must_use]
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            tree: MerkleTree::with_index_binding(IndexBinding::Prefix),
+            data: vec![],
+        }
+    }
+
+    // writes the encoding and hands back the writer with the root
+    pub fn finish(mut self) -> io::Result<(W, Vec<u8>)> {
+        // the final chunk, short or empty, is only known to be final now
+        let hashed = self.tree.len() * CHUNK_SIZE;
+        if hashed < self.data.len() || self.data.is_empty() {
+            self.tree
+                .add(&self.data[hashed..])
+                .map_err(io::Error::other)?;
+        }
+
+        self.inner
+            .write_all(&(self.data.len() as u64).to_le_bytes())?;
+
+        let nodes = self.tree.as_nodes();
+        let mut stack = flat::lpbt_root(nodes.len()).into_iter().collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            match (
+                flat::pbt_left_child(node),
+                flat::lpbt_right_child(node, nodes.len()),
+            ) {
+                (Some(left), Some(right)) => {
+                    self.inner.write_all(&nodes[left])?;
+                    self.inner.write_all(&nodes[right])?;
+                    stack.push(right);
+                    stack.push(left);
+                }
+                _ => {
+                    let start = node / 2 * CHUNK_SIZE;
+                    let end = (start + CHUNK_SIZE).min(self.data.len());
+                    self.inner.write_all(&self.data[start..end])?;
+                }
+            }
+        }
+        self.inner.flush()?;
+
+        let root = self
+            .tree
+            .root()
+            .ok_or_else(|| io::Error::other("encoded tree has no root"))?;
+
+        Ok((self.inner, root))
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize, W: Write> Write
+    for EncodedStream<S, N, ND, W>
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+
+        // hash every chunk that more data has shown not to be the last
+        while (self.tree.len() + 1) * CHUNK_SIZE < self.data.len() {
+            let start = self.tree.len() * CHUNK_SIZE;
+            self.tree
+                .add(&self.data[start..start + CHUNK_SIZE])
+                .map_err(io::Error::other)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// copies the slice of an encoding that covers a byte range: the header, the
+// parents on the way down to every chunk overlapping the range, and those
+// chunks, seeking past every other subtree. the slice is checked by a
+// VerifyingDecoder made with the same range. N is the tree's hash width
+pub struct SliceExtractor<const N: usize, R: Read + Seek> {
+    inner: R,
+    range: Range<u64>,
+}
+
+impl<const N: usize, R: Read + Seek> SliceExtractor<N, R> {
+    #[must_use]
+    pub fn new(inner: R, range: Range<u64>) -> Self {
+        Self { inner, range }
+    }
+
+    pub fn extract<W: Write>(mut self, mut out: W) -> io::Result<()> {
+        let mut header = [0; HEADER_SIZE];
+        self.inner.read_exact(&mut header)?;
+        out.write_all(&header)?;
+
+        let content_len = u64::from_le_bytes(header);
+        let span = chunk_span(&self.range, content_len);
+
+        let mut stack = vec![(0, chunk_count(content_len))];
+        while let Some((first, count)) = stack.pop() {
+            if !overlaps(first, count, &span) {
+                let skip = encoded_under::<N>(first, count, content_len)
+                    .and_then(|skip| i64::try_from(skip).ok())
+                    .ok_or_else(|| invalid("subtree too large to skip"))?;
+                self.inner.seek(SeekFrom::Current(skip))?;
+                continue;
+            }
+
+            let len = if count == 1 {
+                content_under(first, 1, content_len)
+            } else {
+                let left = left_chunks(count);
+                stack.push((first + left, count - left));
+                stack.push((first, left));
+                2 * N as u64
+            };
+            let copied = io::copy(&mut (&mut self.inner).take(len), &mut out)?;
+            if copied != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+
+        out.flush()
+    }
+}
+
+// an io::Read over an encoding, or a slice of one, that only ever returns
+// content it has checked against the root. a chunk is read and hashed whole
+// before any of it is returned, and the first mismatch, or anything else
+// wrong with the stream, fails this read and every one after it
+pub struct VerifyingDecoder<S: Digest, const N: usize, const ND: usize, R: Read> {
+    inner: R,
+    root: Vec<u8>,
+    range: Range<u64>,
+    // set once the header is in; the span is the chunks the stream carries
+    content_len: u64,
+    span: Range<u64>,
+    // (first chunk, chunk count, expected hash) of subtrees still to read
+    stack: Vec<(u64, u64, Vec<u8>)>,
+    started: bool,
+    failed: bool,
+    buf: Vec<u8>,
+    pos: usize,
+    _s: PhantomData<fn() -> S>,
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize, R: Read> VerifyingDecoder<S, N, ND, R> {
+    // a decoder for a whole encoding
+    #[must_use]
+    pub fn new(inner: R, root: &[u8]) -> Self {
+        Self::slice(inner, root, 0..u64::MAX)
+    }
+
+    // a decoder for the slice SliceExtractor gives for range, returning just
+    // the content within it
+    #[must_use]
+    pub fn slice(inner: R, root: &[u8], range: Range<u64>) -> Self {
+        Self {
+            inner,
+            root: root.to_vec(),
+            range,
+            content_len: 0,
+            span: 0..0,
+            stack: vec![],
+            started: false,
+            failed: false,
+            buf: vec![],
+            pos: 0,
+            _s: PhantomData,
+        }
+    }
+
+    // reads and checks the next chunk in the span into buf, false once there
+    // are none left
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        if !self.started {
+            let mut header = [0; HEADER_SIZE];
+            self.inner.read_exact(&mut header)?;
+            self.content_len = u64::from_le_bytes(header);
+
+            let count = chunk_count(self.content_len);
+            if count > MerkleTree::<S, N, ND>::MAX_LEAVES as u64 {
+                return Err(invalid("content length is past the tree's capacity"));
+            }
+            self.span = chunk_span(&self.range, self.content_len);
+            self.stack.push((0, count, self.root.clone()));
+            self.started = true;
+        }
+
+        while let Some((first, count, expected)) = self.stack.pop() {
+            if !overlaps(first, count, &self.span) {
+                continue;
+            }
+
+            if count == 1 {
+                let start = first * CHUNK_SIZE as u64;
+                let mut chunk = vec![0; content_under(first, 1, self.content_len) as usize];
+                self.inner.read_exact(&mut chunk)?;
+
+                let leaf = MerkleTree::<S, N, ND>::bound_leaf_hash(
+                    IndexBinding::Prefix,
+                    TagMode::Block,
+                    first as usize,
+                    &chunk,
+                );
+                if leaf != expected {
+                    return Err(invalid("chunk does not match its hash"));
+                }
+
+                // the part of the chunk inside the range
+                let from = self.range.start.clamp(start, start + chunk.len() as u64) - start;
+                let to = self.range.end.clamp(start, start + chunk.len() as u64) - start;
+                self.buf = chunk[from as usize..to.max(from) as usize].to_vec();
+                self.pos = 0;
+
+                return Ok(true);
+            }
+
+            let mut parent = vec![0; 2 * N];
+            self.inner.read_exact(&mut parent)?;
+            let (left, right) = parent.split_at(N);
+            if MerkleTree::<S, N, ND>::node_hash(left, right) != expected {
+                return Err(invalid("parent does not match its hash"));
+            }
+
+            let left_count = left_chunks(count);
+            self.stack
+                .push((first + left_count, count - left_count, right.to_vec()));
+            self.stack.push((first, left_count, left.to_vec()));
+        }
+
+        Ok(false)
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize, R: Read> Read
+    for VerifyingDecoder<S, N, ND, R>
+{
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.failed {
+                return Err(invalid("stream already failed verification"));
+            }
+
+            match self.next_chunk() {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(e) => {
+                    self.failed = true;
+                    return Err(e);
+                }
+            }
+        }
+
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+    use std::io::{Cursor, ErrorKind, Read, Write};
+
+    use super::{
+        CHUNK_SIZE, EncodedStream, SliceExtractor, VerifyingDecoder, chunk_count, content_under,
+        left_chunks,
+    };
+    use crate::merkle::{IndexBinding, MerkleTree};
+
+    type Tree = MerkleTree<Sha256, 32, 64>;
+    type Encoder = EncodedStream<Sha256, 32, 64, Vec<u8>>;
+    type Decoder<R> = VerifyingDecoder<Sha256, 32, 64, R>;
+
+    fn content(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i % 251) as u8 ^ (i >> 10) as u8)
+            .collect()
+    }
+
+    // fed in uneven writes, so chunks are completed across write boundaries
+    fn encode(data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut encoder = Encoder::new(vec![]);
+        for piece in data.chunks(7777) {
+            encoder.write_all(piece).unwrap();
+        }
+
+        encoder.finish().unwrap()
+    }
+
+    fn slice(encoded: &[u8], range: std::ops::Range<u64>) -> Vec<u8> {
+        let mut out = vec![];
+        SliceExtractor::<32, _>::new(Cursor::new(encoded), range)
+            .extract(&mut out)
+            .unwrap();
+
+        out
+    }
+
+    // where chunk target starts in the encoding of content_len bytes
+    fn chunk_offset(content_len: u64, target: u64) -> usize {
+        let (mut first, mut count, mut pos) = (0, chunk_count(content_len), 8);
+        while count > 1 {
+            pos += 64;
+            let left = left_chunks(count);
+            if target < first + left {
+                count = left;
+            } else {
+                pos += content_under(first, left, content_len) + (left - 1) * 64;
+                (first, count) = (first + left, count - left);
+            }
+        }
+
+        pos as usize
+    }
+
+    // everything a decoder yields before it fails, and how it failed
+    fn decode_until_error<R: Read>(mut decoder: Decoder<R>) -> (Vec<u8>, Option<ErrorKind>) {
+        let (mut out, mut buf) = (vec![], [0; 4096]);
+        loop {
+            match decoder.read(&mut buf) {
+                Ok(0) => return (out, None),
+                Ok(n) => out.extend_from_slice(&buf[..n]),
+                Err(e) => return (out, Some(e.kind())),
+            }
+        }
+    }
+
+    #[test]
+    fn full_streams_round_trip() {
+        let data = content(3 * 1024 * 1024 + 123);
+        let (encoded, root) = encode(&data);
+        let chunks = data.len().div_ceil(CHUNK_SIZE);
+
+        // the root is the index-bound tree over the chunks
+        let mut tree = Tree::with_index_binding(IndexBinding::Prefix);
+        for chunk in data.chunks(CHUNK_SIZE) {
+            tree.add(chunk).unwrap();
+        }
+        assert_eq!(Some(root.clone()), tree.root());
+
+        // header, content, and one parent per internal node
+        assert_eq!(encoded.len(), 8 + data.len() + (chunks - 1) * 64);
+        assert_eq!(encoded[..8], (data.len() as u64).to_le_bytes());
+
+        let mut decoded = vec![];
+        Decoder::new(&encoded[..], &root)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert!(decoded == data);
+
+        // the wrong root fails before any content is yielded
+        let mut other = root.clone();
+        other[0] ^= 1;
+        assert_eq!(
+            decode_until_error(Decoder::new(&encoded[..], &other)),
+            (vec![], Some(ErrorKind::InvalidData))
+        );
+
+        // small and empty content
+        for len in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1] {
+            let data = content(len);
+            let (encoded, root) = encode(&data);
+            let mut decoded = vec![];
+            Decoder::new(&encoded[..], &root)
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+        }
+    }
+
+    #[test]
+    fn slices_carry_just_their_range() {
+        let data = content(2 * 1024 * 1024 + 500);
+        let (encoded, root) = encode(&data);
+        let len = data.len() as u64;
+
+        for range in [
+            0..1,
+            0..len,
+            1000..5000,
+            1024..2048,
+            777_777..1_234_567,
+            len - 10..len,
+            len - 10..len + 100,
+        ] {
+            // a partial range costs its content, up to a chunk either side, the
+            // parents within it, and two parents per level on the way down
+            let slice = slice(&encoded, range.clone());
+            if range == (0..len) {
+                assert!(slice == encoded);
+            } else {
+                let covered = range.end.min(len) - range.start + 2 * CHUNK_SIZE as u64;
+                let parents = covered / CHUNK_SIZE as u64 + 2 * 12;
+                assert!((slice.len() as u64) < 8 + covered + parents * 64);
+            }
+
+            let mut decoded = vec![];
+            Decoder::slice(&slice[..], &root, range.clone())
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert!(decoded == data[range.start as usize..range.end.min(len) as usize]);
+
+            // a slice does not decode as another range within the content
+            if range.end + 4096 <= len {
+                let shifted = range.start + 4096..range.end + 4096;
+                let (_, err) = decode_until_error(Decoder::slice(&slice[..], &root, shifted));
+                assert!(err.is_some());
+            }
+        }
+
+        // a range past the end is served as the final chunk, authenticating the
+        // length, and yields nothing. the 2049th chunk hangs off the root
+        let past = slice(&encoded, len + 5..len + 10);
+        assert_eq!(past.len(), 8 + 64 + 500);
+        let mut decoded = vec![];
+        Decoder::slice(&past[..], &root, len + 5..len + 10)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn flipped_bits_are_caught_before_the_content_is_yielded() {
+        let data = content(2 * 1024 * 1024);
+        let (encoded, root) = encode(&data);
+
+        // a content byte: the decoder stops before the chunk holding it, so the
+        // output is an untouched prefix of the content
+        let chunk = 1500;
+        let offset = chunk_offset(data.len() as u64, chunk as u64) + 17;
+        let mut corrupted = encoded.clone();
+        corrupted[offset] ^= 0x10;
+
+        let (out, err) = decode_until_error(Decoder::new(&corrupted[..], &root));
+        assert_eq!(err, Some(ErrorKind::InvalidData));
+        assert!(out.len() <= chunk * CHUNK_SIZE);
+        assert!(data.starts_with(&out));
+
+        // a parent hash, the header's length, and a truncated stream
+        let mut parent = encoded.clone();
+        parent[8 + 40] ^= 1;
+        assert_eq!(
+            decode_until_error(Decoder::new(&parent[..], &root)),
+            (vec![], Some(ErrorKind::InvalidData))
+        );
+
+        let mut header = encoded.clone();
+        header[1] ^= 1;
+        let (out, err) = decode_until_error(Decoder::new(&header[..], &root));
+        assert!(err.is_some() && data.starts_with(&out));
+
+        let (out, err) = decode_until_error(Decoder::new(&encoded[..encoded.len() - 1], &root));
+        assert_eq!(err, Some(ErrorKind::UnexpectedEof));
+        assert!(out.len() < data.len() && data.starts_with(&out));
+
+        // and inside a slice
+        let range = 100_000..200_000;
+        let mut slice = slice(&encoded, range.clone());
+        let at = slice.len() - 50_000;
+        slice[at] ^= 0x01;
+        let (out, err) = decode_until_error(Decoder::slice(&slice[..], &root, range.clone()));
+        assert_eq!(err, Some(ErrorKind::InvalidData));
+        assert!(data[range.start as usize..].starts_with(&out));
+    }
+
+    #[test]
+    fn huge_header_lengths_fail_cleanly() {
+        for len in [u64::MAX, u64::MAX - 1, u64::MAX / 2, 1 << 63] {
+            let mut encoded = len.to_le_bytes().to_vec();
+            encoded.extend(content(5 * 1024));
+
+            for range in [0..1, len - 1..len, 0..u64::MAX] {
+                // the extractor checks nothing, so junk may well be copied out
+                // as a slice; it just must not panic on the length
+                let mut out = vec![];
+                let _ = SliceExtractor::<32, _>::new(Cursor::new(&encoded), range.clone())
+                    .extract(&mut out);
+
+                let (out, err) = decode_until_error(Decoder::slice(&encoded[..], &[0; 32], range));
+                assert!(out.is_empty() && err.is_some());
+            }
+        }
+    }
+}
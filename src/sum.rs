@@ -0,0 +1,375 @@
+use digest::Digest;
+use std::{fmt::Debug, marker::PhantomData};
+
+use crate::{
+    error::MerkleError,
+    flat,
+    merkle::{MerkleTree, ProofElementDirection},
+};
+
+// a merkle sum tree for proofs of liabilities (maxwell's construction): every
+// node commits to the total value below it, so an inclusion proof also shows the
+// leaf's value is counted in the published total.
+//
+// a leaf is H(0x01 * N || value_le || data) and a node
+// H(0x02 * N || left || left_sum_le || right || right_sum_le), truncated to N,
+// with sum = left_sum + right_sum. hashing both child sums rather than only
+// their total means a proof cannot claim another split of a parent's sum between
+// its children, the flaw in maxwell's original scheme. sums are u64 and any
+// overflow, building or verifying, is an error rather than a wrap.
+//
+// nodes are kept in MerkleTree's flat layout, so an append rehashes one path
+// and the root and proofs are read off the stored nodes.
+pub struct SumMerkleTree<S: Digest, const N: usize, const ND: usize> {
+    // (hash, sum) per node
+    tree: Vec<(Vec<u8>, u64)>,
+    total: u64,
+    _s: PhantomData<fn() -> S>,
+}
+
+// a sibling on the path to the root, with the sum it commits to
+pub struct SumProofElement<S: Digest, const N: usize, const ND: usize> {
+    pub(crate) hash: Vec<u8>,
+    pub(crate) sum: u64,
+    pub(crate) direction: ProofElementDirection,
+    _s: PhantomData<fn() -> S>,
+}
+
+pub type SumProof<S, const N: usize, const ND: usize> = Vec<SumProofElement<S, N, ND>>;
+
+impl<S: Digest, const N: usize, const ND: usize> SumProofElement<S, N, ND> {
+    #[must_use]
+    pub fn hash(&self) -> &[u8] {
+        &self.hash
+    }
+
+    #[must_use]
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    #[must_use]
+    pub fn direction(&self) -> ProofElementDirection {
+        self.direction
+    }
+}
+
+impl<S: Digest, const N: usize, const ND: usize> Clone for SumProofElement<S, N, ND> {
+    fn clone(&self) -> Self {
+        Self {
+            hash: self.hash.clone(),
+            sum: self.sum,
+            direction: self.direction,
+            _s: PhantomData,
+        }
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> Default for SumMerkleTree<S, N, ND> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Debug + Digest, const N: usize, const ND: usize> SumMerkleTree<S, N, ND> {
+    #[must_use]
+    pub fn new() -> Self {
+        // the same parameter checks MerkleTree::new makes
+        assert!(
+            N > 0,
+            "N must be at least 1, zero-width hashes commit to nothing"
+        );
+        assert!(
+            N <= <S as Digest>::output_size(),
+            "N must not exceed the digest output size"
+        );
+        assert!(ND == 2 * N, "ND must be exactly 2 * N");
+
+        Self {
+            tree: vec![],
+            total: 0,
+            _s: PhantomData,
+        }
+    }
+
+    pub fn from_leaves<I, D>(leaves: I) -> Result<Self, MerkleError>
+    where
+        I: IntoIterator<Item = (u64, D)>,
+        D: AsRef<[u8]>,
+    {
+        let mut tree = Self::new();
+        for (value, data) in leaves {
+            tree.add(value, data.as_ref())?;
+        }
+
+        Ok(tree)
+    }
+
+    // fails, leaving the tree untouched, if the total would overflow or the
+    // tree is full
+    pub fn add(&mut self, value: u64, data: &[u8]) -> Result<(), MerkleError> {
+        if self.len() >= MerkleTree::<S, N, ND>::MAX_LEAVES {
+            return Err(MerkleError::CapacityExceeded {
+                max: MerkleTree::<S, N, ND>::MAX_LEAVES,
+            });
+        }
+        let total = self
+            .total
+            .checked_add(value)
+            .ok_or(MerkleError::SumOverflow)?;
+
+        let leaf = (Self::leaf_hash(value, data), value);
+        if !self.tree.is_empty() {
+            self.tree.push(leaf.clone());
+        }
+        self.tree.push(leaf);
+        self.total = total;
+
+        // the sums below never overflow: every one is part of the checked total
+        let mut node = self.tree.len() - 1;
+        while let Some(parent) = flat::lpbt_parent(node, self.tree.len()) {
+            let (Some(left), Some(right)) = (
+                flat::pbt_left_child(parent),
+                flat::lpbt_right_child(parent, self.tree.len()),
+            ) else {
+                return Err(MerkleError::Structural("could not get children"));
+            };
+
+            let (l, r) = (&self.tree[left], &self.tree[right]);
+            self.tree[parent] =
+                Self::node_hash((&l.0, l.1), (&r.0, r.1)).ok_or(MerkleError::SumOverflow)?;
+            node = parent;
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tree.len().div_ceil(2)
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    #[must_use]
+    pub fn leaf_hash(value: u64, data: &[u8]) -> Vec<u8> {
+        let out = S::new()
+            .chain_update([MerkleTree::<S, N, ND>::LEAF_TAG; N])
+            .chain_update(value.to_le_bytes())
+            .chain_update(data)
+            .finalize();
+
+        out[..N].to_vec()
+    }
+
+    // None if the sums overflow, which only an untrusted proof can make happen
+    #[must_use]
+    pub fn node_hash(left: (&[u8], u64), right: (&[u8], u64)) -> Option<(Vec<u8>, u64)> {
+        let sum = left.1.checked_add(right.1)?;
+        let out = S::new()
+            .chain_update([MerkleTree::<S, N, ND>::NODE_TAG; N])
+            .chain_update(left.0)
+            .chain_update(left.1.to_le_bytes())
+            .chain_update(right.0)
+            .chain_update(right.1.to_le_bytes())
+            .finalize();
+
+        Some((out[..N].to_vec(), sum))
+    }
+
+    // the root hash and the total it commits to
+    #[must_use]
+    pub fn root(&self) -> Option<(Vec<u8>, u64)> {
+        flat::lpbt_root(self.tree.len()).and_then(|r| self.tree.get(r).cloned())
+    }
+
+    // siblings from the leaf upwards, each with its sum
+    #[must_use]
+    pub fn create_proof(&self, index: usize) -> Option<SumProof<S, N, ND>> {
+        let mut node = index.checked_mul(2).filter(|&n| n < self.tree.len())?;
+
+        let mut proof = vec![];
+        while let Some(parent) = flat::lpbt_parent(node, self.tree.len()) {
+            let left = flat::pbt_left_child(parent)?;
+            let right = flat::lpbt_right_child(parent, self.tree.len())?;
+
+            let (sibling, direction) = if node == left {
+                (right, ProofElementDirection::Right)
+            } else {
+                (left, ProofElementDirection::Left)
+            };
+            let (hash, sum) = self.tree.get(sibling)?.clone();
+            proof.push(SumProofElement {
+                hash,
+                sum,
+                direction,
+                _s: PhantomData,
+            });
+            node = parent;
+        }
+
+        Some(proof)
+    }
+
+    // recomputes both the root hash and the total from the leaf upwards. a
+    // tampered sibling sum changes the hash, and sums that overflow are rejected
+    #[must_use]
+    pub fn verify_proof(
+        value: u64,
+        data: &[u8],
+        proof: &[SumProofElement<S, N, ND>],
+        root: &[u8],
+        total: u64,
+    ) -> bool {
+        if proof.iter().any(|e| e.hash.len() != N) {
+            return false;
+        }
+
+        let leaf = (Self::leaf_hash(value, data), value);
+        let implied = proof
+            .iter()
+            .try_fold(leaf, |(hash, sum), e| match e.direction {
+                ProofElementDirection::Left => Self::node_hash((&e.hash, e.sum), (&hash, sum)),
+                ProofElementDirection::Right => Self::node_hash((&hash, sum), (&e.hash, e.sum)),
+            });
+
+        implied.is_some_and(|(hash, sum)| hash == root && sum == total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::SumMerkleTree;
+    use crate::{error::MerkleError, merkle::ProofElementDirection};
+
+    type Tree = SumMerkleTree<Sha256, 32, 64>;
+
+    fn balances() -> Vec<(u64, Vec<u8>)> {
+        (0..7u64)
+            .map(|i| (100 * (i + 1), format!("user{i}").into_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn proofs_reproduce_the_published_total() {
+        let leaves = balances();
+        let tree = Tree::from_leaves(leaves.iter().map(|(v, d)| (*v, d))).unwrap();
+        let (root, total) = tree.root().unwrap();
+        assert_eq!(total, 2800);
+        assert_eq!(tree.total(), total);
+
+        for (i, (value, data)) in leaves.iter().enumerate() {
+            let proof = tree.create_proof(i).unwrap();
+            assert!(Tree::verify_proof(*value, data, &proof, &root, total));
+
+            // the leaf's value and the total are both committed to
+            assert!(!Tree::verify_proof(value + 1, data, &proof, &root, total));
+            assert!(!Tree::verify_proof(*value, data, &proof, &root, total - 1));
+        }
+
+        assert!(tree.create_proof(7).is_none());
+        assert!(Tree::new().root().is_none());
+
+        // a single leaf is its own root
+        let single = Tree::from_leaves([(5, b"only")]).unwrap();
+        assert_eq!(single.root().unwrap().1, 5);
+        assert!(Tree::verify_proof(
+            5,
+            b"only",
+            &single.create_proof(0).unwrap(),
+            &single.root().unwrap().0,
+            5
+        ));
+    }
+
+    // the root straight from the definition, splitting off the largest power of
+    // two below n on the left
+    fn reference_root(leaves: &[(u64, Vec<u8>)]) -> (Vec<u8>, u64) {
+        match leaves {
+            [(value, data)] => (Tree::leaf_hash(*value, data), *value),
+            _ => {
+                let (left, right) = leaves.split_at(1 << (leaves.len() - 1).ilog2());
+                let (l, r) = (reference_root(left), reference_root(right));
+                Tree::node_hash((&l.0, l.1), (&r.0, r.1)).unwrap()
+            }
+        }
+    }
+
+    // a verifier outside the crate only has the accessors to fold a proof with
+    #[test]
+    fn proofs_fold_through_the_accessors() {
+        let leaves = balances();
+        let tree = Tree::from_leaves(leaves.iter().map(|(v, d)| (*v, d))).unwrap();
+
+        for (i, (value, data)) in leaves.iter().enumerate() {
+            let leaf = (Tree::leaf_hash(*value, data), *value);
+            let folded = tree
+                .create_proof(i)
+                .unwrap()
+                .iter()
+                .try_fold(leaf, |(hash, sum), e| match e.direction() {
+                    ProofElementDirection::Left => {
+                        Tree::node_hash((e.hash(), e.sum()), (&hash, sum))
+                    }
+                    ProofElementDirection::Right => {
+                        Tree::node_hash((&hash, sum), (e.hash(), e.sum()))
+                    }
+                });
+            assert_eq!(folded, tree.root());
+        }
+    }
+
+    #[test]
+    fn stored_nodes_match_the_definition() {
+        let leaves = balances();
+        let mut tree = Tree::new();
+        for (i, (value, data)) in leaves.iter().enumerate() {
+            tree.add(*value, data).unwrap();
+            assert_eq!(tree.root(), Some(reference_root(&leaves[..=i])));
+        }
+    }
+
+    #[test]
+    fn tampered_sums_are_detected() {
+        let leaves = balances();
+        let tree = Tree::from_leaves(leaves.iter().map(|(v, d)| (*v, d))).unwrap();
+        let (root, total) = tree.root().unwrap();
+        let (value, data) = &leaves[2];
+
+        // moving value out of a sibling shrinks the total it rolls up to, but
+        // the sibling's sum is hashed into its parent
+        let mut proof = tree.create_proof(2).unwrap();
+        proof[1].sum -= 100;
+        assert!(!Tree::verify_proof(*value, data, &proof, &root, total));
+        assert!(!Tree::verify_proof(
+            *value,
+            data,
+            &proof,
+            &root,
+            total - 100
+        ));
+
+        // sums that would overflow are refused, not wrapped
+        let mut proof = tree.create_proof(2).unwrap();
+        proof[0].sum = u64::MAX;
+        assert!(!Tree::verify_proof(*value, data, &proof, &root, total));
+
+        let mut proof = tree.create_proof(2).unwrap();
+        proof[0].hash.pop();
+        assert!(!Tree::verify_proof(*value, data, &proof, &root, total));
+
+        let mut tree = Tree::from_leaves([(u64::MAX, b"a")]).unwrap();
+        assert_eq!(tree.add(1, b"b"), Err(MerkleError::SumOverflow));
+        assert_eq!(tree.len(), 1);
+    }
+}
@@ -0,0 +1,405 @@
+use digest::Digest;
+use std::{
+    io::{self, Read},
+    marker::PhantomData,
+};
+
+// tree hash exchange (thex) trees, as used for the tiger tree hash (tth) that
+// dc++ and gnutella clients identify files by: 1024-byte segments, leaves
+// H(0x00 || segment), nodes H(0x01 || left || right), and a level with an odd
+// node out promotes it unchanged to the level above. promotion gives the same
+// shape as MerkleTree's split at the largest power of two. an empty file is a
+// single empty segment, so its root is H(0x00).
+//
+// generic over the digest; a tth is ThexTree<Tiger> with the root in unpadded
+// rfc 4648 base32. thex predates tiger2, so it is the original tiger padding.
+pub const SEGMENT_SIZE: usize = 1024;
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+pub struct ThexTree<S: Digest> {
+    // levels[0] holds the segment hashes and the last level the root
+    levels: Vec<Vec<Vec<u8>>>,
+    _s: PhantomData<fn() -> S>,
+}
+
+// unpadded rfc 4648 base32, the form tth roots are exchanged in
+#[must_use]
+pub fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let (mut buffer, mut bits) = (0u16, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | u16::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+
+    out
+}
+
+// the base32 root of everything read, streamed a segment at a time. only one
+// pending subtree per height is kept, so memory is logarithmic in the length
+pub fn thex_root_base32<S: Digest, R: Read>(mut reader: R) -> io::Result<String> {
+    // (height, hash) of complete subtrees, heights strictly decreasing
+    let mut stack: Vec<(u32, Vec<u8>)> = vec![];
+    let mut segment = Vec::with_capacity(SEGMENT_SIZE);
+
+    loop {
+        segment.clear();
+        (&mut reader)
+            .take(SEGMENT_SIZE as u64)
+            .read_to_end(&mut segment)?;
+        if segment.is_empty() && !stack.is_empty() {
+            break;
+        }
+
+        let mut node = (0, ThexTree::<S>::leaf_hash(&segment));
+        while let Some((height, left)) = stack.pop_if(|(height, _)| *height == node.0) {
+            node = (height + 1, ThexTree::<S>::node_hash(&left, &node.1));
+        }
+        stack.push(node);
+
+        if segment.len() < SEGMENT_SIZE {
+            break;
+        }
+    }
+
+    // the leftover subtrees fold from the right, smaller ones promoted upwards
+    let root = stack
+        .into_iter()
+        .rev()
+        .map(|(_, hash)| hash)
+        .reduce(|right, left| ThexTree::<S>::node_hash(&left, &right))
+        .unwrap_or_default();
+
+    Ok(base32(&root))
+}
+
+impl<S: Digest> ThexTree<S> {
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+
+        Ok(Self::from_bytes(&data))
+    }
+
+    #[must_use]
+    pub fn from_bytes(data: &[u8]) -> Self {
+        let leaves = if data.is_empty() {
+            vec![Self::leaf_hash(&[])]
+        } else {
+            data.chunks(SEGMENT_SIZE).map(Self::leaf_hash).collect()
+        };
+
+        let mut levels = vec![leaves];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => Self::node_hash(left, right),
+                    [promoted] => promoted.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+
+        Self {
+            levels,
+            _s: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn leaf_hash(segment: &[u8]) -> Vec<u8> {
+        S::new()
+            .chain_update([LEAF_PREFIX])
+            .chain_update(segment)
+            .finalize()
+            .to_vec()
+    }
+
+    #[must_use]
+    pub fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+        S::new()
+            .chain_update([NODE_PREFIX])
+            .chain_update(left)
+            .chain_update(right)
+            .finalize()
+            .to_vec()
+    }
+
+    // number of segments, one for an empty file
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    // never true, an empty file is still one segment
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    #[must_use]
+    pub fn root(&self) -> &[u8] {
+        &self.levels[self.levels.len() - 1][0]
+    }
+
+    #[must_use]
+    pub fn root_base32(&self) -> String {
+        base32(self.root())
+    }
+
+    // the hashes needed to recompute the root from segments start..end, level
+    // by level from the bottom: at each level the left neighbour of the range if
+    // it starts on a right child, then the right neighbour if it ends on a left
+    // child that is not promoted. the range stays contiguous all the way up, so
+    // this is at most two hashes per level
+    #[must_use]
+    pub fn range_proof(&self, start: usize, end: usize) -> Option<Vec<Vec<u8>>> {
+        if start >= end || end > self.len() {
+            return None;
+        }
+
+        let (mut s, mut e) = (start, end);
+        let mut proof = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            if s % 2 == 1 {
+                proof.push(level[s - 1].clone());
+            }
+            if e % 2 == 1 && e < level.len() {
+                proof.push(level[e].clone());
+            }
+            (s, e) = (s / 2, e.div_ceil(2));
+        }
+
+        Some(proof)
+    }
+
+    // checks that data is segments start.. of a file of total_segments segments
+    // with the given root. data must be whole segments, except that it may end
+    // on the file's final, shorter one
+    #[must_use]
+    pub fn verify_range(
+        data: &[u8],
+        start: usize,
+        total_segments: usize,
+        proof: &[Vec<u8>],
+        root: &[u8],
+    ) -> bool {
+        let count = data.len().div_ceil(SEGMENT_SIZE).max(1);
+        let Some(end) = start
+            .checked_add(count)
+            .filter(|&end| end <= total_segments)
+        else {
+            return false;
+        };
+        if !data.len().is_multiple_of(SEGMENT_SIZE) && end != total_segments {
+            return false;
+        }
+
+        let mut nodes = if data.is_empty() {
+            vec![Self::leaf_hash(&[])]
+        } else {
+            data.chunks(SEGMENT_SIZE).map(Self::leaf_hash).collect()
+        };
+        let mut proof = proof.iter();
+        let (mut s, mut e, mut len) = (start, end, total_segments);
+
+        while len > 1 {
+            if s % 2 == 1 {
+                let Some(left) = proof.next() else {
+                    return false;
+                };
+                nodes.insert(0, left.clone());
+            }
+            if e % 2 == 1 && e < len {
+                let Some(right) = proof.next() else {
+                    return false;
+                };
+                nodes.push(right.clone());
+            }
+
+            nodes = nodes
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => Self::node_hash(left, right),
+                    [promoted] => promoted.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            (s, e, len) = (s / 2, e.div_ceil(2), len.div_ceil(2));
+        }
+
+        proof.next().is_none() && nodes.len() == 1 && nodes[0] == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::{Digest, Sha256};
+    use tiger::Tiger;
+
+    use super::{SEGMENT_SIZE, ThexTree, base32, thex_root_base32};
+
+    type Tree = ThexTree<Sha256>;
+
+    // the file of n segments whose last one is partial bytes long, every byte
+    // differing so segments never hash alike
+    fn file(n: usize, partial: usize) -> Vec<u8> {
+        (0..(n - 1) * SEGMENT_SIZE + partial)
+            .map(|i| (i % 251) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn base32_matches_rfc_4648() {
+        // the rfc's own vectors, without the padding
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"f"), "MY");
+        assert_eq!(base32(b"fo"), "MZXQ");
+        assert_eq!(base32(b"foo"), "MZXW6");
+        assert_eq!(base32(b"foob"), "MZXW6YQ");
+        assert_eq!(base32(b"fooba"), "MZXW6YTB");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+
+        // a 24-byte tiger hash is 39 characters
+        assert_eq!(base32(&[0xff; 24]).len(), 39);
+    }
+
+    // the test vectors published with the thex specification, as dc++ and
+    // other tth clients report them
+    #[test]
+    fn tiger_tree_hashes_match_published_vectors() {
+        type Tth = ThexTree<Tiger>;
+
+        let vectors: [(&[u8], &str); 4] = [
+            (b"", "LWPNACQDBZRYXW3VHJVCJ64QBZNGHOHHHZWCLNQ"),
+            (&[0], "VK54ZIEEVTWNAUI5D5RDFIL37LX2IQNSTAXFKSA"),
+            (&[b'A'; 1024], "L66Q4YVNAFWVS23X2HJIRA5ZJ7WXR3F26RSASFA"),
+            (&[b'A'; 1025], "PZMRYHGY6LTBEH63ZWAHDORHSYTLO4LEFUIKHWY"),
+        ];
+
+        for (data, root) in vectors {
+            assert_eq!(Tth::from_bytes(data).root_base32(), root);
+            assert_eq!(thex_root_base32::<Tiger, _>(data).unwrap(), root);
+        }
+    }
+
+    #[test]
+    fn segments_hash_with_prefixes_and_promotion() {
+        let leaf = |d: &[u8]| Sha256::digest([&[0], d].concat()).to_vec();
+        let node = |l: &[u8], r: &[u8]| Sha256::digest([&[1], l, r].concat()).to_vec();
+
+        // an empty file is one empty segment
+        let empty = Tree::from_bytes(&[]);
+        assert_eq!(empty.len(), 1);
+        assert_eq!(empty.root(), leaf(&[]));
+
+        assert_eq!(Tree::from_bytes(&[0]).root(), leaf(&[0]));
+        assert_eq!(
+            Tree::from_bytes(&[b'A'; SEGMENT_SIZE]).root(),
+            leaf(&[b'A'; SEGMENT_SIZE])
+        );
+
+        // 1025 bytes split into a full segment and a single byte
+        assert_eq!(
+            Tree::from_bytes(&[b'A'; SEGMENT_SIZE + 1]).root(),
+            node(&leaf(&[b'A'; SEGMENT_SIZE]), &leaf(b"A"))
+        );
+
+        // five segments: the fifth is promoted twice, unchanged
+        let data = file(5, 10);
+        let l = data.chunks(SEGMENT_SIZE).map(leaf).collect::<Vec<_>>();
+        let tree = Tree::from_bytes(&data);
+        assert_eq!(tree.len(), 5);
+        assert_eq!(
+            tree.root(),
+            node(&node(&node(&l[0], &l[1]), &node(&l[2], &l[3])), &l[4])
+        );
+        assert_eq!(tree.root_base32(), base32(tree.root()));
+    }
+
+    #[test]
+    fn streaming_matches_the_whole_tree() {
+        let sizes = [
+            (1, 0),
+            (1, 1),
+            (1, SEGMENT_SIZE - 1),
+            (1, SEGMENT_SIZE),
+            (2, 1),
+            (2, SEGMENT_SIZE),
+            (3, 7),
+            (7, SEGMENT_SIZE),
+            (8, SEGMENT_SIZE),
+            (13, 100),
+        ];
+
+        for (n, partial) in sizes {
+            let data = file(n, partial);
+            let tree = Tree::from_reader(&data[..]).unwrap();
+
+            assert_eq!(tree.len(), n);
+            assert_eq!(
+                thex_root_base32::<Sha256, _>(&data[..]).unwrap(),
+                tree.root_base32()
+            );
+        }
+    }
+
+    #[test]
+    fn segment_ranges_verify_against_the_root() {
+        for (n, partial) in [(1, 5), (7, 300), (13, SEGMENT_SIZE)] {
+            let data = file(n, partial);
+            let tree = Tree::from_bytes(&data);
+            let root = tree.root();
+
+            for start in 0..n {
+                for end in start + 1..=n {
+                    let range = &data[start * SEGMENT_SIZE..(end * SEGMENT_SIZE).min(data.len())];
+                    let proof = tree.range_proof(start, end).unwrap();
+
+                    assert!(proof.len() <= 2 * tree.levels.len());
+                    assert!(Tree::verify_range(range, start, n, &proof, root));
+
+                    // tampered data and a shifted start
+                    let mut tampered = range.to_vec();
+                    tampered[0] ^= 1;
+                    assert!(!Tree::verify_range(&tampered, start, n, &proof, root));
+                    if start > 0 {
+                        assert!(!Tree::verify_range(range, start - 1, n, &proof, root));
+                    }
+                    // a short final segment has to end the file
+                    if end == n && partial < SEGMENT_SIZE {
+                        assert!(!Tree::verify_range(range, start, n + 1, &proof, root));
+                    }
+                }
+            }
+        }
+
+        let tree = Tree::from_bytes(&file(4, 1));
+        assert!(tree.range_proof(2, 2).is_none());
+        assert!(tree.range_proof(3, 5).is_none());
+
+        // a proof with a hash to spare is refused
+        let mut proof = tree.range_proof(0, 1).unwrap();
+        proof.push(vec![0; 32]);
+        assert!(!Tree::verify_range(
+            &file(4, 1)[..SEGMENT_SIZE],
+            0,
+            4,
+            &proof,
+            tree.root()
+        ));
+    }
+}
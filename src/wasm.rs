@@ -0,0 +1,180 @@
+use js_sys::Array;
+use sha2::Sha256;
+use wasm_bindgen::{JsCast, prelude::*};
+
+use crate::{
+    error::MerkleError,
+    hex::{self, encode as to_hex},
+    merkle::MerkleTree,
+};
+
+// browser-side verification for MerkleTree<Sha256, 32, 64>. proofs arrive in
+// the compact binary format of codec.rs, roots and leaves as hex strings.
+//
+// nothing on these paths panics: every malformed input is a MerkleError, which
+// the exported functions hand to javascript as a thrown Error carrying its
+// message. the try_ functions hold the logic so it can be tested natively, where
+// wasm-bindgen's JsValue cannot be used.
+pub type WasmTree = MerkleTree<Sha256, 32, 64>;
+
+pub fn try_verify_proof(
+    data: &[u8],
+    proof_bytes: &[u8],
+    root_hex: &str,
+) -> Result<bool, MerkleError> {
+    let proof = WasmTree::proof_from_bytes(proof_bytes)?;
+    let root = hex::decode(root_hex)?;
+
+    WasmTree::verify_proof_detailed(data, &proof, &root)
+}
+
+pub fn try_compute_root<I>(leaves_hex: I) -> Result<String, MerkleError>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let leaves = leaves_hex
+        .into_iter()
+        .map(|leaf| hex::decode(leaf.as_ref()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    WasmTree::from_leaves(leaves)?
+        .root()
+        .map(|root| to_hex(&root))
+        .ok_or(MerkleError::InvalidEncoding(
+            "a tree needs at least one leaf",
+        ))
+}
+
+// false for a well-formed proof that does not reach the root; throws for a
+// malformed proof or root
+#[wasm_bindgen(js_name = verifyProof)]
+pub fn verify_proof(data: &[u8], proof_bytes: &[u8], root_hex: &str) -> Result<bool, JsError> {
+    Ok(try_verify_proof(data, proof_bytes, root_hex)?)
+}
+
+// the root of a tree over leaves, an array of hex strings, as hex. every leaf is
+// hashed in memory, so this is meant for small trees
+#[wasm_bindgen(js_name = computeRoot)]
+pub fn compute_root(leaves: JsValue) -> Result<String, JsError> {
+    let leaves = leaves
+        .dyn_into::<Array>()
+        .map_err(|_| MerkleError::InvalidEncoding("leaves must be an array"))?
+        .iter()
+        .map(|leaf| {
+            leaf.as_string()
+                .ok_or(MerkleError::InvalidEncoding("leaves must be hex strings"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(try_compute_root(leaves)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WasmTree, to_hex, try_compute_root, try_verify_proof};
+    use crate::error::MerkleError;
+
+    // leaves 00..04, the same tree merkle.rs pins its zeroize test to
+    const ROOT: &str = "43680581585abed181b1eec574d755598bd9773593cb15dda0898c6110a65909";
+
+    fn fixture() -> (WasmTree, Vec<u8>) {
+        let tree = WasmTree::from_leaves((0..5u8).map(|i| [i])).unwrap();
+        let proof = WasmTree::proof_to_bytes(&tree.create_proof_by_index(3).unwrap()).unwrap();
+
+        (tree, proof)
+    }
+
+    #[test]
+    fn compute_root_matches_native_trees() {
+        assert_eq!(
+            try_compute_root(["00", "01", "02", "03", "04"]).unwrap(),
+            ROOT
+        );
+        assert_eq!(to_hex(&fixture().0.root().unwrap()), ROOT);
+
+        assert_eq!(
+            try_compute_root(["0"]),
+            Err(MerkleError::InvalidEncoding(
+                "not an even-length hex string"
+            ))
+        );
+        for bad in ["zz", "+1"] {
+            assert_eq!(
+                try_compute_root([bad]),
+                Err(MerkleError::InvalidEncoding(
+                    "not an even-length hex string"
+                ))
+            );
+        }
+        assert_eq!(
+            try_compute_root::<[&str; 0]>([]),
+            Err(MerkleError::InvalidEncoding(
+                "a tree needs at least one leaf"
+            ))
+        );
+    }
+
+    #[test]
+    fn verify_proof_takes_compact_proofs() {
+        let (_, proof) = fixture();
+
+        assert_eq!(try_verify_proof(&[3], &proof, ROOT), Ok(true));
+        assert_eq!(
+            try_verify_proof(&[3], &proof, &ROOT.to_uppercase()),
+            Ok(true)
+        );
+        assert_eq!(try_verify_proof(&[2], &proof, ROOT), Ok(false));
+
+        // malformed input is an error, not a false
+        assert!(try_verify_proof(&[3], &proof[..proof.len() - 1], ROOT).is_err());
+        assert!(try_verify_proof(&[3], &proof, &ROOT[..62]).is_err());
+        assert!(try_verify_proof(&[3], &proof, "é").is_err());
+    }
+}
+
+// the exported functions themselves, run under wasm-bindgen-test-runner as the
+// wasm32 cargo runner (see the wasm job in .github/workflows/ci.yml):
+// cargo test --target wasm32-unknown-unknown --no-default-features --features wasm
+#[cfg(all(test, target_arch = "wasm32"))]
+mod wasm_tests {
+    use js_sys::Array;
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::{WasmTree, compute_root, verify_proof};
+
+    const ROOT: &str = "43680581585abed181b1eec574d755598bd9773593cb15dda0898c6110a65909";
+
+    fn leaves(hex: &[&str]) -> JsValue {
+        hex.iter()
+            .map(|&leaf| JsValue::from_str(leaf))
+            .collect::<Array>()
+            .into()
+    }
+
+    #[wasm_bindgen_test]
+    fn compute_root_takes_a_js_array() {
+        assert_eq!(
+            compute_root(leaves(&["00", "01", "02", "03", "04"])).unwrap(),
+            ROOT
+        );
+
+        assert!(compute_root(leaves(&[])).is_err());
+        assert!(compute_root(leaves(&["zz"])).is_err());
+        assert!(compute_root(JsValue::from_str("00")).is_err());
+        assert!(compute_root(Array::of1(&JsValue::from(1)).into()).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn verify_proof_checks_compact_proofs() {
+        let tree = WasmTree::from_leaves((0..5u8).map(|i| [i])).unwrap();
+        let proof = WasmTree::proof_to_bytes(&tree.create_proof_by_index(3).unwrap()).unwrap();
+
+        assert!(verify_proof(&[3], &proof, ROOT).unwrap());
+        assert!(!verify_proof(&[2], &proof, ROOT).unwrap());
+
+        assert!(verify_proof(&[3], &proof[..proof.len() - 1], ROOT).is_err());
+        assert!(verify_proof(&[3], &proof, &ROOT[..62]).is_err());
+    }
+}
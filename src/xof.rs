@@ -0,0 +1,170 @@
+use digest::{ExtendableOutput, Update};
+use std::marker::PhantomData;
+
+use crate::{
+    dynamic::{DynMerkleTree, DynProofElement},
+    error::MerkleError,
+};
+
+// MerkleTree's layout and tagging over an extendable-output function: every
+// hash squeezes exactly N bytes from the xof instead of truncating a fixed
+// digest. the tree itself is a DynMerkleTree driven by that squeeze.
+pub struct XofMerkleTree<S: ExtendableOutput + Default + Update + 'static, const N: usize> {
+    inner: DynMerkleTree,
+    _s: PhantomData<fn() -> S>,
+}
+
+#[cfg(feature = "shake")]
+pub type Shake128MerkleTree<const N: usize> = XofMerkleTree<sha3::Shake128, N>;
+
+#[cfg(feature = "shake")]
+pub type Shake256MerkleTree<const N: usize> = XofMerkleTree<sha3::Shake256, N>;
+
+impl<S: ExtendableOutput + Default + Update + 'static, const N: usize> Default
+    for XofMerkleTree<S, N>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: ExtendableOutput + Default + Update + 'static, const N: usize> XofMerkleTree<S, N> {
+    #[must_use]
+    pub fn new() -> Self {
+        assert!(
+            N > 0,
+            "N must be at least 1, zero-width hashes commit to nothing"
+        );
+
+        let squeeze = |data: &[u8]| {
+            let mut out = vec![0; N];
+            S::default().chain(data).finalize_xof_into(&mut out);
+            out
+        };
+
+        Self {
+            inner: DynMerkleTree::new(Box::new(squeeze), N)
+                .expect("the squeeze returns exactly N > 0 bytes"),
+            _s: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn add(&mut self, data: &[u8]) -> Result<(), MerkleError> {
+        self.inner.add(data)
+    }
+
+    #[must_use]
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.inner.root()
+    }
+
+    #[must_use]
+    pub fn create_proof(&self, data: &[u8]) -> Option<Vec<DynProofElement>> {
+        self.inner.create_proof(data)
+    }
+
+    #[must_use]
+    pub fn verify_proof(data: &[u8], proof: &[DynProofElement], to_match: &[u8]) -> bool {
+        Self::new().inner.verify_proof(data, proof, to_match)
+    }
+}
+
+#[cfg(all(test, feature = "shake"))]
+mod tests {
+    use digest::{ExtendableOutput, Update};
+    use sha3::Shake256;
+
+    use super::{Shake128MerkleTree, Shake256MerkleTree};
+    use crate::hex::encode as hex;
+
+    type Tree = Shake256MerkleTree<32>;
+
+    #[test]
+    fn shake256_tree_proves_and_verifies() {
+        let mut tree = Tree::new();
+        for i in 0..7u8 {
+            tree.add(&[i]).unwrap();
+        }
+
+        let root = tree.root().unwrap();
+        assert_eq!(root.len(), 32);
+
+        for i in 0..7u8 {
+            let proof = tree.create_proof(&[i]).unwrap();
+            assert!(Tree::verify_proof(&[i], &proof, &root));
+            assert!(!Tree::verify_proof(&[i + 1], &proof, &root));
+        }
+
+        // a single leaf's root is the tagged leaf hash, squeezed from shake256
+        let mut single = Tree::new();
+        single.add(b"leaf").unwrap();
+
+        let squeeze = |data: &[u8]| {
+            let mut out = [0; 32];
+            Shake256::default().chain(data).finalize_xof_into(&mut out);
+            out
+        };
+        let leaf = squeeze(&[[1; 32], squeeze(b"leaf")].concat());
+        assert_eq!(single.root().unwrap(), leaf);
+    }
+
+    // roots over leaves [0], [1], .., computed independently with python's
+    // hashlib.shake_256 under the same tagging and left-perfect split
+    #[test]
+    fn shake256_known_answers() {
+        for (n, expected) in [
+            (
+                1,
+                "fcaee0c903b1020f1dc36eb27ba8b4bd17a9140688d7e979df1a9f059b34553c",
+            ),
+            (
+                2,
+                "73d088d0e967d2d79b1d9d15708cd5c8f5f75fa7a6bfd8a2176d634ce06ae5d6",
+            ),
+            (
+                5,
+                "3195a571f892b01fd196a2577330cc5d8dd9db3d473af5b9ec6c05f0dc2131c1",
+            ),
+            (
+                7,
+                "7a60a674b9f0fc9b30932c9c2278fb50297b2bbec2c4f97c184287dcc46d22b5",
+            ),
+        ] {
+            let mut tree = Tree::new();
+            for i in 0..n {
+                tree.add(&[i]).unwrap();
+            }
+
+            let root = tree.root().unwrap();
+            assert_eq!(hex(&root), expected);
+
+            for i in 0..n {
+                let proof = tree.create_proof(&[i]).unwrap();
+                assert!(Tree::verify_proof(&[i], &proof, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn width_is_squeezed_not_truncated() {
+        let mut wide = Shake128MerkleTree::<64>::new();
+        let mut narrow = Shake128MerkleTree::<16>::new();
+        for i in 0..3u8 {
+            wide.add(&[i]).unwrap();
+            narrow.add(&[i]).unwrap();
+        }
+
+        assert_eq!(wide.root().unwrap().len(), 64);
+        assert_eq!(narrow.root().unwrap().len(), 16);
+    }
+}